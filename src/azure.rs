@@ -3,12 +3,28 @@
 use anyhow::{Context, Result};
 use base64::Engine;
 use reqwest::blocking::Client;
-use reqwest::header::{HeaderValue, ACCEPT, AUTHORIZATION};
+use reqwest::header::{HeaderValue, ACCEPT, AUTHORIZATION, ETAG, IF_NONE_MATCH};
+use reqwest::StatusCode;
+use serde::de::DeserializeOwned;
 use serde::Deserialize;
 use std::collections::HashMap;
 
+use crate::cache::{CacheLookup, CachedResponse, ResponseCache};
+
+/// Number of variable groups requested per page when listing the project's groups
+const GROUP_PAGE_SIZE: usize = 500;
+
+/// Number of pipelines requested per page when listing the project's pipelines
+const PIPELINE_PAGE_SIZE: usize = 500;
+
+/// Number of requests the client sends at the same time unless configured otherwise
+pub const DEFAULT_CONCURRENCY: usize = 8;
+
+/// Response header carrying the token of the next page of a list
+const CONTINUATION_TOKEN: &str = "x-ms-continuationtoken";
+
 /// Variable group data returned from Azure DevOps
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct VariableGroupData {
     /// Variable group ID
     pub id: i32,
@@ -20,7 +36,7 @@ pub struct VariableGroupData {
 }
 
 /// Variable value from a variable group
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct VariableValue {
     /// The variable value (may be None for secret variables)
     pub value: Option<String>,
@@ -30,7 +46,7 @@ pub struct VariableValue {
 }
 
 /// Variable data from a pipeline definition
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct PipelineVariableValue {
     /// The variable value (may be None for secret variables)
     pub value: Option<String>,
@@ -43,7 +59,7 @@ pub struct PipelineVariableValue {
 }
 
 /// Pipeline info from pipelines list
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct PipelineInfo {
     /// Pipeline ID
     pub id: i32,
@@ -83,6 +99,10 @@ pub struct AzureDevOpsClient {
     http_client: Client,
     /// Authorization header value (pre-computed Basic auth)
     auth_header: HeaderValue,
+    /// Cache of responses, if enabled
+    cache: Option<ResponseCache>,
+    /// Maximum number of requests sent at the same time
+    pub concurrency: usize,
 }
 
 impl AzureDevOpsClient {
@@ -128,14 +148,103 @@ impl AzureDevOpsClient {
             project,
             http_client,
             auth_header,
+            cache: None,
+            concurrency: DEFAULT_CONCURRENCY,
         })
     }
 
+    /// Send at most this many independent requests at the same time
+    ///
+    /// # Arguments
+    /// * `concurrency` - Maximum number of concurrent requests (1 sends them one after another)
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Serve responses from an on-disk cache and store new responses in it
+    ///
+    /// Entries are keyed by this client's credential, so they are never served to another one.
+    ///
+    /// # Arguments
+    /// * `cache` - The response cache
+    pub fn with_cache(mut self, cache: ResponseCache) -> Self {
+        self.cache = Some(cache.with_credential(self.auth_header.as_bytes()));
+        self
+    }
+
     /// Construct the project URL base
     fn project_url(&self) -> String {
         format!("{}/{}", self.organization, self.project)
     }
 
+    /// Send a GET request and parse its JSON response, going through the cache if enabled
+    ///
+    /// # Arguments
+    /// * `url` - Request URL
+    /// * `context` - What is requested, for error messages (e.g. "variable group ID 7")
+    ///
+    /// # Returns
+    /// * `Result<T>` - The parsed response
+    fn get_json<T: DeserializeOwned>(&self, url: &str, context: &str) -> Result<T> {
+        parse_json(&self.get(url, context)?.body, context)
+    }
+
+    /// Send a GET request, going through the cache if enabled
+    ///
+    /// # Arguments
+    /// * `url` - Request URL
+    /// * `context` - What is requested, for error messages (e.g. "variable group ID 7")
+    ///
+    /// # Returns
+    /// * `Result<CachedResponse>` - Body and continuation token of the response
+    fn get(&self, url: &str, context: &str) -> Result<CachedResponse> {
+        let stale = match self.cache.as_ref().map(|cache| cache.lookup(url)) {
+            Some(CacheLookup::Fresh(entry)) => return Ok(entry),
+            Some(CacheLookup::Stale(entry)) => Some(entry),
+            Some(CacheLookup::Missing) | None => None,
+        };
+
+        let mut request = self
+            .http_client
+            .get(url)
+            .header(AUTHORIZATION, self.auth_header.clone())
+            .header(ACCEPT, "application/json");
+        if let Some(etag) = stale.as_ref().and_then(|entry| entry.etag.as_deref()) {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        let response = request
+            .send()
+            .with_context(|| format!("Failed to send request for {context}"))?;
+
+        let status = response.status();
+        if let (StatusCode::NOT_MODIFIED, Some(cache), Some(entry)) = (status, &self.cache, stale) {
+            return Ok(cache.revalidated(entry));
+        }
+        if !status.is_success() {
+            return Err(self.handle_response_error(status, context));
+        }
+
+        let header = |name| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .filter(|value| !value.is_empty())
+                .map(|value| value.to_string())
+        };
+        let etag = header(ETAG.as_str());
+        let continuation_token = header(CONTINUATION_TOKEN);
+        let body = response
+            .text()
+            .with_context(|| format!("Failed to read response for {context}"))?;
+        let entry = CachedResponse::new(url, etag, continuation_token, body);
+        if let Some(ref cache) = self.cache {
+            cache.store(&entry);
+        }
+        Ok(entry)
+    }
+
     /// Handle HTTP response status codes with helpful error messages
     fn handle_response_error(
         &self,
@@ -176,28 +285,8 @@ impl AzureDevOpsClient {
             encoded_name
         );
 
-        let response = self
-            .http_client
-            .get(&url)
-            .header(AUTHORIZATION, self.auth_header.clone())
-            .header(ACCEPT, "application/json")
-            .send()
-            .with_context(|| format!("Failed to send request for variable group '{}'", group_name))?;
-
-        let status = response.status();
-        if !status.is_success() {
-            return Err(self.handle_response_error(
-                status,
-                &format!("variable group '{}'", group_name),
-            ));
-        }
-
-        let groups_response: VariableGroupsResponse = response.json().with_context(|| {
-            format!(
-                "Failed to parse response for variable group '{}'",
-                group_name
-            )
-        })?;
+        let groups_response: VariableGroupsResponse =
+            self.get_json(&url, &format!("variable group '{}'", group_name))?;
 
         // Find exact match by name (API may return partial matches)
         groups_response
@@ -207,6 +296,62 @@ impl AzureDevOpsClient {
             .ok_or_else(|| anyhow::anyhow!("Variable group '{}' not found", group_name))
     }
 
+    /// List every variable group in the project
+    ///
+    /// The groups are requested in pages of `GROUP_PAGE_SIZE`, following the continuation token
+    /// Azure DevOps returns until the last page.
+    ///
+    /// # Returns
+    /// * `Result<Vec<VariableGroupData>>` - All variable groups, with their variables
+    pub fn list_variable_groups(&self) -> Result<Vec<VariableGroupData>> {
+        let url = format!(
+            "{}/_apis/distributedtask/variablegroups?$top={}&api-version=7.0",
+            self.project_url(),
+            GROUP_PAGE_SIZE
+        );
+
+        self.get_pages(&url, "variable groups", |body| {
+            Ok(parse_json::<VariableGroupsResponse>(body, "variable groups")?.value)
+        })
+    }
+
+    /// Send GET requests for every page of a list, following the continuation token Azure
+    /// DevOps returns until the last page
+    ///
+    /// # Arguments
+    /// * `url` - Request URL of the first page
+    /// * `context` - What is requested, for error messages (e.g. "pipelines")
+    /// * `parse` - Parse the items of one page
+    ///
+    /// # Returns
+    /// * `Result<Vec<T>>` - The items of all pages, in order
+    fn get_pages<T>(&self, url: &str, context: &str, parse: impl Fn(&str) -> Result<Vec<T>>) -> Result<Vec<T>> {
+        let mut items = Vec::new();
+        let mut continuation_token: Option<String> = None;
+
+        loop {
+            let mut page_url = url.to_string();
+            if let Some(ref token) = continuation_token {
+                page_url.push_str(&format!("&continuationToken={}", urlencoding::encode(token)));
+            }
+
+            let page = self.get(&page_url, context)?;
+            let page_items = parse(&page.body)?;
+            let page_len = page_items.len();
+            items.extend(page_items);
+
+            // Stop at the last page, and on a repeated token rather than looping forever
+            match page.continuation_token {
+                Some(token) if page_len > 0 && continuation_token.as_ref() != Some(&token) => {
+                    continuation_token = Some(token);
+                }
+                _ => break,
+            }
+        }
+
+        Ok(items)
+    }
+
     /// Get all variable names from a variable group by ID
     ///
     /// # Arguments
@@ -215,41 +360,25 @@ impl AzureDevOpsClient {
     /// # Returns
     /// * `Result<Vec<String>>` - List of variable names in the group
     pub fn get_variables_in_group(&self, group_id: i32) -> Result<Vec<String>> {
+        let group_data = self.get_variable_group_by_id(group_id)?;
+        Ok(group_data.variables.keys().cloned().collect())
+    }
+
+    /// Fetch a variable group from Azure DevOps by ID, with its variables
+    ///
+    /// # Arguments
+    /// * `group_id` - The ID of the variable group
+    ///
+    /// # Returns
+    /// * `Result<VariableGroupData>` - The variable group data
+    pub fn get_variable_group_by_id(&self, group_id: i32) -> Result<VariableGroupData> {
         let url = format!(
             "{}/_apis/distributedtask/variablegroups/{}?api-version=7.0",
             self.project_url(),
             group_id
         );
 
-        let response = self
-            .http_client
-            .get(&url)
-            .header(AUTHORIZATION, self.auth_header.clone())
-            .header(ACCEPT, "application/json")
-            .send()
-            .with_context(|| {
-                format!(
-                    "Failed to send request for variable group ID {}",
-                    group_id
-                )
-            })?;
-
-        let status = response.status();
-        if !status.is_success() {
-            return Err(self.handle_response_error(
-                status,
-                &format!("variable group ID {}", group_id),
-            ));
-        }
-
-        let group_data: VariableGroupData = response.json().with_context(|| {
-            format!(
-                "Failed to parse response for variable group ID {}",
-                group_id
-            )
-        })?;
-
-        Ok(group_data.variables.keys().cloned().collect())
+        self.get_json(&url, &format!("variable group ID {}", group_id))
     }
 
     /// Look up a pipeline ID by name
@@ -260,45 +389,35 @@ impl AzureDevOpsClient {
     /// # Returns
     /// * `Result<i32>` - The pipeline ID if found
     pub fn get_pipeline_id_by_name(&self, pipeline_name: &str) -> Result<i32> {
-        let url = format!("{}/_apis/pipelines?api-version=7.0", self.project_url());
-
-        let response = self
-            .http_client
-            .get(&url)
-            .header(AUTHORIZATION, self.auth_header.clone())
-            .header(ACCEPT, "application/json")
-            .send()
-            .with_context(|| {
-                format!(
-                    "Failed to send request for pipeline '{}'",
-                    pipeline_name
-                )
-            })?;
-
-        let status = response.status();
-        if !status.is_success() {
-            return Err(self.handle_response_error(
-                status,
-                &format!("pipeline '{}'", pipeline_name),
-            ));
-        }
-
-        let pipelines_response: PipelinesResponse = response.json().with_context(|| {
-            format!(
-                "Failed to parse response when looking up pipeline '{}'",
-                pipeline_name
-            )
-        })?;
+        let pipelines = self.list_pipelines()?;
 
         // Find exact match by name
-        pipelines_response
-            .value
+        pipelines
             .iter()
             .find(|p| p.name == pipeline_name)
             .map(|p| p.id)
             .ok_or_else(|| anyhow::anyhow!("Pipeline '{}' not found", pipeline_name))
     }
 
+    /// List every pipeline in the project
+    ///
+    /// The pipelines are requested in pages of `PIPELINE_PAGE_SIZE`, following the continuation
+    /// token like `list_variable_groups`.
+    ///
+    /// # Returns
+    /// * `Result<Vec<PipelineInfo>>` - ID and name of each pipeline
+    pub fn list_pipelines(&self) -> Result<Vec<PipelineInfo>> {
+        let url = format!(
+            "{}/_apis/pipelines?$top={}&api-version=7.0",
+            self.project_url(),
+            PIPELINE_PAGE_SIZE
+        );
+
+        self.get_pages(&url, "pipelines", |body| {
+            Ok(parse_json::<PipelinesResponse>(body, "pipelines")?.value)
+        })
+    }
+
     /// Fetch pipeline definition variables from Azure DevOps by name
     ///
     /// First resolves the pipeline name to an ID, then fetches variables using the ID.
@@ -346,33 +465,8 @@ impl AzureDevOpsClient {
             pipeline_id
         );
 
-        let response = self
-            .http_client
-            .get(&url)
-            .header(AUTHORIZATION, self.auth_header.clone())
-            .header(ACCEPT, "application/json")
-            .send()
-            .with_context(|| {
-                format!(
-                    "Failed to send request for pipeline ID {}",
-                    pipeline_id
-                )
-            })?;
-
-        let status = response.status();
-        if !status.is_success() {
-            return Err(self.handle_response_error(
-                status,
-                &format!("pipeline ID {}", pipeline_id),
-            ));
-        }
-
-        let definition: BuildDefinitionResponse = response.json().with_context(|| {
-            format!(
-                "Failed to parse response for pipeline ID {}",
-                pipeline_id
-            )
-        })?;
+        let definition: BuildDefinitionResponse =
+            self.get_json(&url, &format!("pipeline ID {}", pipeline_id))?;
 
         Ok(definition.variables)
     }
@@ -390,6 +484,11 @@ impl AzureDevOpsClient {
     }
 }
 
+/// Parse a JSON response body
+fn parse_json<T: DeserializeOwned>(body: &str, context: &str) -> Result<T> {
+    serde_json::from_str(body).with_context(|| format!("Failed to parse response for {context}"))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,353 @@
+//! Sources of variable groups and pipeline definitions
+//!
+//! Validation only needs a few lookups from Azure DevOps. They are described by the
+//! `VariableBackend` trait so that the validator can run against the REST API
+//! (`AzureDevOpsClient`), an in-memory fixture (`InMemoryBackend`), or any source a library
+//! user provides. `GroupIndex` wraps a backend so that all variable groups are listed once and
+//! looked up in memory.
+
+use anyhow::Result;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use crate::azure::{AzureDevOpsClient, PipelineInfo, PipelineVariableValue, VariableGroupData};
+
+/// Lookups of variable groups and pipeline definition variables
+///
+/// Backends are `Sync` so that independent lookups can run on several threads.
+pub trait VariableBackend: Sync {
+    /// Fetch a variable group by its exact name, with its variables
+    fn get_variable_group(&self, group_name: &str) -> Result<VariableGroupData>;
+
+    /// Fetch a variable group by ID, with its variables
+    fn get_variable_group_by_id(&self, group_id: i32) -> Result<VariableGroupData>;
+
+    /// List every variable group of the project, with their variables
+    fn list_variable_groups(&self) -> Result<Vec<VariableGroupData>>;
+
+    /// List every pipeline of the project
+    fn list_pipelines(&self) -> Result<Vec<PipelineInfo>>;
+
+    /// Look up the ID of a pipeline by its exact name
+    fn get_pipeline_id_by_name(&self, pipeline_name: &str) -> Result<i32> {
+        self.list_pipelines()?
+            .into_iter()
+            .find(|p| p.name == pipeline_name)
+            .map(|p| p.id)
+            .ok_or_else(|| anyhow::anyhow!("Pipeline '{}' not found", pipeline_name))
+    }
+
+    /// Fetch the variables of a pipeline definition by pipeline ID
+    fn get_pipeline_variables_by_id(&self, pipeline_id: i32) -> Result<HashMap<String, PipelineVariableValue>>;
+
+    /// Fetch the variables of a pipeline definition by pipeline name
+    fn get_pipeline_variables(&self, pipeline_name: &str) -> Result<HashMap<String, PipelineVariableValue>> {
+        let pipeline_id = self.get_pipeline_id_by_name(pipeline_name)?;
+        self.get_pipeline_variables_by_id(pipeline_id)
+    }
+
+    /// Names of the variables of a group
+    fn get_variables_in_group(&self, group_id: i32) -> Result<Vec<String>> {
+        Ok(self.get_variable_group_by_id(group_id)?.variables.into_keys().collect())
+    }
+
+    /// How many independent lookups may run at the same time (1 when nothing is gained by it)
+    fn concurrency(&self) -> usize {
+        1
+    }
+}
+
+impl VariableBackend for AzureDevOpsClient {
+    fn get_variable_group(&self, group_name: &str) -> Result<VariableGroupData> {
+        AzureDevOpsClient::get_variable_group(self, group_name)
+    }
+
+    fn get_variable_group_by_id(&self, group_id: i32) -> Result<VariableGroupData> {
+        AzureDevOpsClient::get_variable_group_by_id(self, group_id)
+    }
+
+    fn list_variable_groups(&self) -> Result<Vec<VariableGroupData>> {
+        AzureDevOpsClient::list_variable_groups(self)
+    }
+
+    fn list_pipelines(&self) -> Result<Vec<PipelineInfo>> {
+        AzureDevOpsClient::list_pipelines(self)
+    }
+
+    fn get_pipeline_variables_by_id(&self, pipeline_id: i32) -> Result<HashMap<String, PipelineVariableValue>> {
+        AzureDevOpsClient::get_pipeline_variables_by_id(self, pipeline_id)
+    }
+
+    fn concurrency(&self) -> usize {
+        self.concurrency
+    }
+}
+
+/// Variable groups and pipeline definitions held in memory, e.g. as a test fixture
+#[derive(Debug, Clone, Default)]
+pub struct InMemoryBackend {
+    /// Variable groups of the project
+    pub groups: Vec<VariableGroupData>,
+    /// Pipelines of the project with the variables of their definitions
+    pub pipelines: Vec<(PipelineInfo, HashMap<String, PipelineVariableValue>)>,
+}
+
+impl InMemoryBackend {
+    /// Create a backend without groups or pipelines
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a variable group
+    pub fn with_group(mut self, group: VariableGroupData) -> Self {
+        self.groups.push(group);
+        self
+    }
+
+    /// Add a pipeline definition and its variables
+    pub fn with_pipeline(mut self, id: i32, name: &str, variables: HashMap<String, PipelineVariableValue>) -> Self {
+        let info = PipelineInfo {
+            id,
+            name: name.to_string(),
+        };
+        self.pipelines.push((info, variables));
+        self
+    }
+}
+
+impl VariableBackend for InMemoryBackend {
+    fn get_variable_group(&self, group_name: &str) -> Result<VariableGroupData> {
+        self.groups
+            .iter()
+            .find(|g| g.name == group_name)
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("Variable group '{}' not found", group_name))
+    }
+
+    fn get_variable_group_by_id(&self, group_id: i32) -> Result<VariableGroupData> {
+        self.groups
+            .iter()
+            .find(|g| g.id == group_id)
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("Variable group ID {} not found", group_id))
+    }
+
+    fn list_variable_groups(&self) -> Result<Vec<VariableGroupData>> {
+        Ok(self.groups.clone())
+    }
+
+    fn list_pipelines(&self) -> Result<Vec<PipelineInfo>> {
+        Ok(self.pipelines.iter().map(|(info, _)| info.clone()).collect())
+    }
+
+    fn get_pipeline_variables_by_id(&self, pipeline_id: i32) -> Result<HashMap<String, PipelineVariableValue>> {
+        self.pipelines
+            .iter()
+            .find(|(info, _)| info.id == pipeline_id)
+            .map(|(_, variables)| variables.clone())
+            .ok_or_else(|| anyhow::anyhow!("Pipeline ID {} not found", pipeline_id))
+    }
+}
+
+/// Variable groups of a backend, listed once and looked up in memory
+///
+/// Looking up each linked group by name and then by ID costs two requests per group; listing the
+/// project's groups is a single (paged) request that already contains every variable. Pipeline
+/// lookups are passed through. If listing fails, group lookups fall back to the wrapped backend.
+#[derive(Debug)]
+pub struct GroupIndex<B> {
+    /// The wrapped backend
+    backend: B,
+    /// Groups of the project, listed on first use (None if listing failed)
+    groups: OnceLock<Option<Vec<VariableGroupData>>>,
+}
+
+impl<B: VariableBackend> GroupIndex<B> {
+    /// Wrap a backend; its groups are listed on the first group lookup
+    pub fn new(backend: B) -> Self {
+        Self {
+            backend,
+            groups: OnceLock::new(),
+        }
+    }
+
+    /// Groups of the project, or None if they could not be listed
+    fn groups(&self) -> Option<&[VariableGroupData]> {
+        self.groups
+            .get_or_init(|| self.backend.list_variable_groups().ok())
+            .as_deref()
+    }
+}
+
+impl<B: VariableBackend> VariableBackend for GroupIndex<B> {
+    fn get_variable_group(&self, group_name: &str) -> Result<VariableGroupData> {
+        let Some(groups) = self.groups() else {
+            return self.backend.get_variable_group(group_name);
+        };
+        groups
+            .iter()
+            .find(|g| g.name == group_name)
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("Variable group '{}' not found", group_name))
+    }
+
+    fn get_variable_group_by_id(&self, group_id: i32) -> Result<VariableGroupData> {
+        let Some(groups) = self.groups() else {
+            return self.backend.get_variable_group_by_id(group_id);
+        };
+        groups
+            .iter()
+            .find(|g| g.id == group_id)
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("Variable group ID {} not found", group_id))
+    }
+
+    fn list_variable_groups(&self) -> Result<Vec<VariableGroupData>> {
+        match self.groups() {
+            Some(groups) => Ok(groups.to_vec()),
+            None => self.backend.list_variable_groups(),
+        }
+    }
+
+    fn list_pipelines(&self) -> Result<Vec<PipelineInfo>> {
+        self.backend.list_pipelines()
+    }
+
+    fn get_pipeline_id_by_name(&self, pipeline_name: &str) -> Result<i32> {
+        self.backend.get_pipeline_id_by_name(pipeline_name)
+    }
+
+    fn get_pipeline_variables_by_id(&self, pipeline_id: i32) -> Result<HashMap<String, PipelineVariableValue>> {
+        self.backend.get_pipeline_variables_by_id(pipeline_id)
+    }
+
+    fn concurrency(&self) -> usize {
+        self.backend.concurrency()
+    }
+}
+
+/// Test fixture: group `app-secrets` (ID 7) with the secret `ApiKey`, and pipeline 42 `deploy`
+/// whose `Region` is settable at queue time
+#[cfg(test)]
+pub(crate) fn test_backend() -> InMemoryBackend {
+    let variables = HashMap::from([(
+        "ApiKey".to_string(),
+        crate::azure::VariableValue {
+            value: None,
+            is_secret: Some(true),
+        },
+    )]);
+    let pipeline_variables = HashMap::from([(
+        "Region".to_string(),
+        PipelineVariableValue {
+            value: Some("westeurope".to_string()),
+            is_secret: None,
+            allow_override: true,
+        },
+    )]);
+
+    InMemoryBackend::new()
+        .with_group(VariableGroupData {
+            id: 7,
+            name: "app-secrets".to_string(),
+            variables,
+        })
+        .with_pipeline(42, "deploy", pipeline_variables)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn test_in_memory_group_lookups() {
+        let backend = test_backend();
+        assert_eq!(backend.get_variable_group("app-secrets").unwrap().id, 7);
+        assert_eq!(backend.get_variables_in_group(7).unwrap(), vec!["ApiKey"]);
+        assert_eq!(backend.list_variable_groups().unwrap().len(), 1);
+
+        let error = backend.get_variable_group("App-Secrets").unwrap_err();
+        assert_eq!(error.to_string(), "Variable group 'App-Secrets' not found");
+    }
+
+    #[test]
+    fn test_in_memory_pipeline_lookups() {
+        let backend = test_backend();
+        let variables = backend.get_pipeline_variables("deploy").unwrap();
+        assert!(variables["Region"].allow_override);
+        assert!(backend.get_pipeline_variables_by_id(1).is_err());
+    }
+
+    /// Counts the group requests reaching the wrapped backend
+    struct CountingBackend {
+        inner: InMemoryBackend,
+        lookups: AtomicUsize,
+        lists: AtomicUsize,
+        fail_list: bool,
+    }
+
+    impl VariableBackend for CountingBackend {
+        fn get_variable_group(&self, group_name: &str) -> Result<VariableGroupData> {
+            self.lookups.fetch_add(1, Ordering::Relaxed);
+            self.inner.get_variable_group(group_name)
+        }
+
+        fn get_variable_group_by_id(&self, group_id: i32) -> Result<VariableGroupData> {
+            self.lookups.fetch_add(1, Ordering::Relaxed);
+            self.inner.get_variable_group_by_id(group_id)
+        }
+
+        fn list_variable_groups(&self) -> Result<Vec<VariableGroupData>> {
+            self.lists.fetch_add(1, Ordering::Relaxed);
+            if self.fail_list {
+                anyhow::bail!("Access denied for variable groups.");
+            }
+            self.inner.list_variable_groups()
+        }
+
+        fn list_pipelines(&self) -> Result<Vec<PipelineInfo>> {
+            self.inner.list_pipelines()
+        }
+
+        fn get_pipeline_variables_by_id(&self, pipeline_id: i32) -> Result<HashMap<String, PipelineVariableValue>> {
+            self.inner.get_pipeline_variables_by_id(pipeline_id)
+        }
+    }
+
+    impl CountingBackend {
+        /// Number of list and single-group requests
+        fn counts(&self) -> (usize, usize) {
+            (self.lists.load(Ordering::Relaxed), self.lookups.load(Ordering::Relaxed))
+        }
+    }
+
+    fn counting(fail_list: bool) -> CountingBackend {
+        CountingBackend {
+            inner: test_backend(),
+            lookups: AtomicUsize::new(0),
+            lists: AtomicUsize::new(0),
+            fail_list,
+        }
+    }
+
+    #[test]
+    fn test_group_index_lists_once() {
+        let index = GroupIndex::new(counting(false));
+        assert_eq!(index.get_variable_group("app-secrets").unwrap().id, 7);
+        assert_eq!(index.get_variables_in_group(7).unwrap(), vec!["ApiKey"]);
+        assert!(index.get_variable_group("missing").is_err());
+        assert_eq!(index.list_variable_groups().unwrap().len(), 1);
+        assert_eq!(index.get_pipeline_id_by_name("deploy").unwrap(), 42);
+
+        assert_eq!(index.backend.counts(), (1, 0));
+    }
+
+    #[test]
+    fn test_group_index_falls_back_when_listing_fails() {
+        let index = GroupIndex::new(counting(true));
+        assert_eq!(index.get_variable_group("app-secrets").unwrap().id, 7);
+        assert_eq!(index.get_variables_in_group(7).unwrap(), vec!["ApiKey"]);
+        assert_eq!(index.backend.counts(), (1, 2));
+    }
+}
@@ -0,0 +1,377 @@
+//! On-disk cache of Azure DevOps API responses
+//!
+//! Linting many pipelines of one project repeats the same requests for the same variable groups
+//! and pipeline definitions. Responses are stored per request URL, which identifies the
+//! organization, project and endpoint. A response younger than the TTL is served without a
+//! request; an older one is revalidated with its `ETag` (`If-None-Match`) and only downloaded
+//! again when it changed.
+//!
+//! Entries are also keyed by a hash of the credential, so a response fetched with one token is
+//! never served to another, and the cache is readable only by its owner.
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Time-to-live of cached responses unless configured otherwise
+pub const DEFAULT_CACHE_TTL: Duration = Duration::from_secs(600);
+
+/// A response stored in the cache
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CachedResponse {
+    /// Request URL the response belongs to
+    pub url: String,
+    /// `ETag` header of the response, used to revalidate it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    /// Continuation token of a paged response, requesting the next page
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub continuation_token: Option<String>,
+    /// When the response was fetched or last revalidated, in seconds since the Unix epoch
+    pub fetched_at: u64,
+    /// Response body
+    pub body: String,
+}
+
+impl CachedResponse {
+    /// A response fetched now
+    ///
+    /// # Arguments
+    /// * `url` - Request URL
+    /// * `etag` - `ETag` header of the response
+    /// * `continuation_token` - Continuation token of a paged response
+    /// * `body` - Response body
+    pub fn new(url: &str, etag: Option<String>, continuation_token: Option<String>, body: String) -> Self {
+        Self {
+            url: url.to_string(),
+            etag,
+            continuation_token,
+            fetched_at: now(),
+            body,
+        }
+    }
+}
+
+/// Outcome of looking up a request in the cache
+#[derive(Debug, Clone, PartialEq)]
+pub enum CacheLookup {
+    /// A response younger than the TTL; no request is needed
+    Fresh(CachedResponse),
+    /// An expired response, to be revalidated or replaced
+    Stale(CachedResponse),
+    /// No usable response
+    Missing,
+}
+
+/// Counts of how requests were served, shared with the caller for reporting
+#[derive(Debug, Default)]
+pub struct CacheStatistics {
+    hits: AtomicUsize,
+    revalidated: AtomicUsize,
+    misses: AtomicUsize,
+}
+
+impl CacheStatistics {
+    /// Requests served from the cache without contacting Azure DevOps
+    pub fn hits(&self) -> usize {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    /// Requests answered with `304 Not Modified`, served from the cache
+    pub fn revalidated(&self) -> usize {
+        self.revalidated.load(Ordering::Relaxed)
+    }
+
+    /// Requests whose response was downloaded
+    pub fn misses(&self) -> usize {
+        self.misses.load(Ordering::Relaxed)
+    }
+
+    /// Describe the counts, e.g. `12 hit(s), 3 revalidated, 1 miss(es)`
+    pub fn summary(&self) -> String {
+        format!(
+            "{} hit(s), {} revalidated, {} miss(es)",
+            self.hits(),
+            self.revalidated(),
+            self.misses()
+        )
+    }
+}
+
+/// Cache of API responses in a directory
+#[derive(Debug, Clone)]
+pub struct ResponseCache {
+    /// Directory holding one file per cached request
+    dir: PathBuf,
+    /// How long a response is served without revalidating it
+    ttl: Duration,
+    /// Whether cached responses are ignored and replaced
+    refresh: bool,
+    /// Hash of the credential responses are fetched with, mixed into every key
+    credential: u64,
+    /// How requests were served
+    statistics: Arc<CacheStatistics>,
+}
+
+impl ResponseCache {
+    /// Create a cache in a directory, which is created when the first response is stored
+    ///
+    /// # Arguments
+    /// * `dir` - Cache directory
+    /// * `ttl` - How long a response is served without revalidating it
+    pub fn new(dir: impl Into<PathBuf>, ttl: Duration) -> Self {
+        Self {
+            dir: dir.into(),
+            ttl,
+            refresh: false,
+            credential: 0,
+            statistics: Arc::new(CacheStatistics::default()),
+        }
+    }
+
+    /// Ignore cached responses and replace them with fresh ones
+    pub fn with_refresh(mut self, refresh: bool) -> Self {
+        self.refresh = refresh;
+        self
+    }
+
+    /// Key entries by the credential they are fetched with, so that another identity never
+    /// sees them without Azure DevOps checking its permissions
+    ///
+    /// # Arguments
+    /// * `credential` - The credential, e.g. the `Authorization` header value; only its hash is kept
+    pub fn with_credential(mut self, credential: &[u8]) -> Self {
+        self.credential = fnv1a(credential);
+        self
+    }
+
+    /// Directory holding the cached responses
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Statistics of this cache, which keep counting after the cache is moved into a client
+    pub fn statistics(&self) -> Arc<CacheStatistics> {
+        Arc::clone(&self.statistics)
+    }
+
+    /// Look up the cached response of a request
+    ///
+    /// # Arguments
+    /// * `url` - Request URL
+    ///
+    /// # Returns
+    /// * `CacheLookup` - The fresh entry, the expired entry, or nothing (always nothing when refreshing)
+    pub fn lookup(&self, url: &str) -> CacheLookup {
+        if self.refresh {
+            return CacheLookup::Missing;
+        }
+        let Some(entry) = self.read(url) else {
+            return CacheLookup::Missing;
+        };
+
+        let age = now().saturating_sub(entry.fetched_at);
+        if age < self.ttl.as_secs() {
+            self.statistics.hits.fetch_add(1, Ordering::Relaxed);
+            CacheLookup::Fresh(entry)
+        } else {
+            CacheLookup::Stale(entry)
+        }
+    }
+
+    /// Record that Azure DevOps confirmed an expired response is unchanged
+    ///
+    /// # Arguments
+    /// * `entry` - The expired entry
+    ///
+    /// # Returns
+    /// * `CachedResponse` - The entry, now fresh
+    pub fn revalidated(&self, entry: CachedResponse) -> CachedResponse {
+        self.statistics.revalidated.fetch_add(1, Ordering::Relaxed);
+        let entry = CachedResponse {
+            fetched_at: now(),
+            ..entry
+        };
+        self.write(&entry);
+        entry
+    }
+
+    /// Store a downloaded response
+    ///
+    /// Failing to write the cache is not an error; the response is simply not cached.
+    ///
+    /// # Arguments
+    /// * `entry` - The downloaded response
+    pub fn store(&self, entry: &CachedResponse) {
+        self.statistics.misses.fetch_add(1, Ordering::Relaxed);
+        self.write(entry);
+    }
+
+    /// File of a request URL for the cache's credential
+    fn path(&self, url: &str) -> PathBuf {
+        let key = format!("{:016x} {url}", self.credential);
+        self.dir.join(format!("{:016x}.json", fnv1a(key.as_bytes())))
+    }
+
+    /// Read the entry of a request, ignoring unreadable files and hash collisions
+    fn read(&self, url: &str) -> Option<CachedResponse> {
+        let content = fs::read_to_string(self.path(url)).ok()?;
+        serde_json::from_str::<CachedResponse>(&content)
+            .ok()
+            .filter(|entry| entry.url == url)
+    }
+
+    /// Write an entry through a temporary file so that readers never see a partial entry
+    fn write(&self, entry: &CachedResponse) {
+        static WRITES: AtomicUsize = AtomicUsize::new(0);
+
+        let Ok(json) = serde_json::to_string(entry) else {
+            return;
+        };
+        let path = self.path(&entry.url);
+        let temporary = path.with_extension(format!(
+            "{}-{}.tmp",
+            std::process::id(),
+            WRITES.fetch_add(1, Ordering::Relaxed)
+        ));
+        let written = create_private_dir(&self.dir)
+            .and_then(|_| write_private_file(&temporary, json.as_bytes()))
+            .and_then(|_| fs::rename(&temporary, &path));
+        if written.is_err() {
+            let _ = fs::remove_file(&temporary);
+        }
+    }
+}
+
+/// Create a directory and its parents, accessible only by the owner on Unix (0700)
+fn create_private_dir(dir: &Path) -> io::Result<()> {
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+    builder.create(dir)
+}
+
+/// Write a file that is readable only by the owner on Unix (0600)
+fn write_private_file(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)?.write_all(contents)
+}
+
+/// Default cache directory: `AZDOLINT_CACHE_DIR`, else `azdolint` in the user's cache directory
+/// (`XDG_CACHE_HOME`, `~/.cache` or `%LOCALAPPDATA%`)
+///
+/// # Returns
+/// * `Option<PathBuf>` - The directory, or None if no cache location is known
+pub fn default_cache_dir() -> Option<PathBuf> {
+    let var = |name: &str| std::env::var_os(name).filter(|v| !v.is_empty()).map(PathBuf::from);
+
+    if let Some(dir) = var("AZDOLINT_CACHE_DIR") {
+        return Some(dir);
+    }
+    var("XDG_CACHE_HOME")
+        .or_else(|| var("LOCALAPPDATA"))
+        .or_else(|| var("HOME").map(|home| home.join(".cache")))
+        .map(|dir| dir.join("azdolint"))
+}
+
+/// Current time in seconds since the Unix epoch
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// 64-bit FNV-1a hash, stable across builds unlike `DefaultHasher`
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const URL: &str = "https://dev.azure.com/myorg/myproject/_apis/distributedtask/variablegroups/7?api-version=7.0";
+
+    fn cache_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("azdolint-cache-test-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn test_fresh_and_stale_entries() {
+        let dir = cache_dir("ttl");
+        let cache = ResponseCache::new(&dir, Duration::from_secs(600));
+        assert_eq!(cache.lookup(URL), CacheLookup::Missing);
+
+        cache.store(&CachedResponse::new(
+            URL,
+            Some("\"v1\"".to_string()),
+            Some("8".to_string()),
+            "{\"id\":7}".to_string(),
+        ));
+        let CacheLookup::Fresh(entry) = cache.lookup(URL) else {
+            panic!("expected a fresh entry");
+        };
+        assert_eq!((entry.body.as_str(), entry.continuation_token.as_deref()), ("{\"id\":7}", Some("8")));
+
+        let expired = ResponseCache::new(&dir, Duration::ZERO);
+        let CacheLookup::Stale(entry) = expired.lookup(URL) else {
+            panic!("expected a stale entry");
+        };
+        assert_eq!(entry.etag.as_deref(), Some("\"v1\""));
+        assert_eq!(expired.revalidated(entry).body, "{\"id\":7}");
+
+        let statistics = cache.statistics();
+        assert_eq!((statistics.hits(), statistics.misses()), (1, 1));
+        assert_eq!(expired.statistics().summary(), "0 hit(s), 1 revalidated, 0 miss(es)");
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_refresh_ignores_entries() {
+        let dir = cache_dir("refresh");
+        let cache = ResponseCache::new(&dir, Duration::from_secs(600));
+        cache.store(&CachedResponse::new(URL, None, None, "{}".to_string()));
+
+        let refreshing = ResponseCache::new(&dir, Duration::from_secs(600)).with_refresh(true);
+        assert_eq!(refreshing.lookup(URL), CacheLookup::Missing);
+        assert_eq!(cache.lookup(&URL.replace("/7?", "/8?")), CacheLookup::Missing);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_entries_are_private_to_a_credential() {
+        let dir = cache_dir("credential");
+        let cache = ResponseCache::new(dir.join("nested"), Duration::from_secs(600)).with_credential(b"Basic one");
+        cache.store(&CachedResponse::new(URL, None, None, "{}".to_string()));
+        assert!(matches!(cache.lookup(URL), CacheLookup::Fresh(_)));
+
+        let other = ResponseCache::new(dir.join("nested"), Duration::from_secs(600)).with_credential(b"Basic two");
+        assert_eq!(other.lookup(URL), CacheLookup::Missing);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+            assert_eq!(mode(cache.dir()), 0o700);
+            assert_eq!(mode(&cache.path(URL)), 0o600);
+        }
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
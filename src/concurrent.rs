@@ -0,0 +1,90 @@
+//! Bounded parallelism for independent lookups
+//!
+//! Requests to Azure DevOps are blocking, so lookups of several variable groups or pipeline
+//! definitions are spread over a few scoped threads. Results always come back in input order,
+//! so output does not depend on which request finishes first.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+/// Apply a function to every item, running at most `limit` calls at the same time
+///
+/// # Arguments
+/// * `items` - Inputs, e.g. variable group names
+/// * `limit` - Maximum number of concurrent calls; 0 and 1 run the calls one after another
+/// * `f` - Function applied to each item
+///
+/// # Returns
+/// * `Vec<R>` - One result per item, in the order of `items`
+pub fn map_concurrent<T, R, F>(items: &[T], limit: usize, f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    let workers = limit.min(items.len());
+    if workers <= 1 {
+        return items.iter().map(f).collect();
+    }
+
+    let next = AtomicUsize::new(0);
+    let (f, next) = (&f, &next);
+    let mut results: Vec<(usize, R)> = thread::scope(|scope| {
+        let handles: Vec<_> = (0..workers)
+            .map(|_| {
+                scope.spawn(move || {
+                    let mut done = Vec::new();
+                    loop {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(item) = items.get(index) else {
+                            break;
+                        };
+                        done.push((index, f(item)));
+                    }
+                    done
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic)))
+            .collect()
+    });
+
+    results.sort_by_key(|(index, _)| *index);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_results_keep_input_order() {
+        let items: Vec<u64> = (0..20).collect();
+        // Later items finish first
+        let results = map_concurrent(&items, 4, |item| {
+            thread::sleep(Duration::from_millis(20 - item));
+            item * 2
+        });
+        assert_eq!(results, items.iter().map(|i| i * 2).collect::<Vec<_>>());
+        assert_eq!(map_concurrent(&items, 0, |item| *item), items);
+    }
+
+    #[test]
+    fn test_limit_bounds_concurrent_calls() {
+        let running = AtomicUsize::new(0);
+        let peak = AtomicUsize::new(0);
+        let items: Vec<usize> = (0..24).collect();
+
+        map_concurrent(&items, 3, |_| {
+            let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+            peak.fetch_max(now, Ordering::SeqCst);
+            thread::sleep(Duration::from_millis(5));
+            running.fetch_sub(1, Ordering::SeqCst);
+        });
+        assert!(peak.load(Ordering::SeqCst) <= 3);
+        assert!(peak.load(Ordering::SeqCst) > 1);
+    }
+}
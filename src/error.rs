@@ -3,6 +3,8 @@
 use std::error::Error;
 use std::fmt;
 
+use crate::span::SourceSpan;
+
 /// Error when parsing a pipeline YAML file fails
 #[derive(Debug)]
 pub struct PipelineParseError {
@@ -167,6 +169,15 @@ impl OutputFormatter {
         format!("  [WARN] {message}")
     }
 
+    /// Format the location of a reference as `file:line:column` followed by its YAML path
+    pub fn location(span: &SourceSpan) -> String {
+        if span.yaml_path.is_empty() {
+            format!("         at {span}")
+        } else {
+            format!("         at {span} ({})", span.yaml_path)
+        }
+    }
+
     /// Format a section header
     pub fn section(title: &str) -> String {
         format!("\n{}\n{}", title, "-".repeat(title.len()))
@@ -0,0 +1,1191 @@
+//! Compile-time template expressions (`${{ }}`)
+//!
+//! Azure DevOps evaluates `${{ }}` expressions when it compiles a pipeline, before any
+//! variable is resolved. Given the parameter values a template receives, this module
+//! evaluates `${{ if }}` / `${{ elseif }}` / `${{ else }}` conditions and `${{ each }}` loops
+//! so that only the branches that would really be compiled are validated.
+//!
+//! Values that are not known at compile time for the linter (variables, pipeline metadata,
+//! parameters bound to unevaluated expressions) evaluate to "unknown". A branch whose
+//! condition is unknown is treated as active, so nothing is hidden that might be compiled.
+
+use anyhow::{anyhow, bail, Result};
+use serde_yaml::{Mapping, Value};
+use std::collections::HashSet;
+
+use crate::span::{PathSegment, YamlPath};
+
+/// Values available to template expressions
+#[derive(Debug, Clone, Default)]
+pub struct ExpressionContext {
+    /// Template parameters (`parameters.x`)
+    parameters: Mapping,
+    /// Loop variables bound by `${{ each }}`, innermost last (`None` if unknown)
+    locals: Vec<(String, Option<Value>)>,
+}
+
+impl ExpressionContext {
+    /// Create a context with the given parameter values
+    pub fn new(parameters: Mapping) -> Self {
+        Self {
+            parameters,
+            locals: Vec::new(),
+        }
+    }
+
+    /// Copy of the context with an additional loop variable
+    fn with_local(&self, name: &str, value: Option<Value>) -> Self {
+        let mut context = self.clone();
+        context.locals.push((name.to_string(), value));
+        context
+    }
+
+    /// Look up a top-level name (`parameters` or a loop variable)
+    fn lookup(&self, name: &str) -> Option<Value> {
+        if let Some((_, value)) = self.locals.iter().rev().find(|(local, _)| local == name) {
+            return value.clone();
+        }
+        if name.eq_ignore_ascii_case("parameters") {
+            return Some(Value::Mapping(self.parameters.clone()));
+        }
+        // variables, pipeline, resources, ... are only known at queue time
+        None
+    }
+}
+
+/// A `${{ }}` mapping key
+#[derive(Debug, Clone, PartialEq)]
+pub enum Directive {
+    /// `${{ if condition }}`
+    If(String),
+    /// `${{ elseif condition }}`
+    ElseIf(String),
+    /// `${{ else }}`
+    Else,
+    /// `${{ each variable in collection }}`
+    Each {
+        variable: String,
+        collection: String,
+    },
+    /// `${{ insert }}`
+    Insert,
+    /// Any other expression used as a key, e.g. `${{ parameters.name }}`
+    Expression(String),
+}
+
+/// Parse a mapping key as a template directive
+///
+/// # Returns
+/// * `Option<Directive>` - The directive, or `None` if the key is not a `${{ }}` expression
+pub fn parse_directive(key: &str) -> Option<Directive> {
+    let inner = key.trim().strip_prefix("${{")?.strip_suffix("}}")?.trim();
+
+    let directive = if inner == "else" {
+        Directive::Else
+    } else if inner == "insert" {
+        Directive::Insert
+    } else if let Some(condition) = strip_keyword(inner, "elseif") {
+        Directive::ElseIf(condition.to_string())
+    } else if let Some(condition) = strip_keyword(inner, "if") {
+        Directive::If(condition.to_string())
+    } else if let Some((variable, collection)) =
+        strip_keyword(inner, "each").and_then(|rest| rest.split_once(" in "))
+    {
+        Directive::Each {
+            variable: variable.trim().to_string(),
+            collection: collection.trim().to_string(),
+        }
+    } else {
+        Directive::Expression(inner.to_string())
+    };
+
+    Some(directive)
+}
+
+/// Strip a leading keyword followed by whitespace or `(`
+fn strip_keyword<'a>(text: &'a str, keyword: &str) -> Option<&'a str> {
+    let rest = text.strip_prefix(keyword)?;
+    if rest.starts_with(char::is_whitespace) || rest.starts_with('(') {
+        Some(rest.trim())
+    } else {
+        None
+    }
+}
+
+/// Evaluate an expression
+///
+/// # Arguments
+/// * `expression` - Expression text without the `${{ }}` delimiters
+/// * `context` - Parameter values and loop variables
+///
+/// # Returns
+/// * `Result<Option<Value>>` - The value, `None` if it is not known at compile time, or an
+///   error if the expression is malformed
+pub fn evaluate(expression: &str, context: &ExpressionContext) -> Result<Option<Value>> {
+    let tokens = tokenize(expression)?;
+    let mut parser = ExprParser {
+        tokens,
+        position: 0,
+    };
+    let expr = parser.expression()?;
+    if parser.position != parser.tokens.len() {
+        bail!("Unexpected input after expression: {expression}");
+    }
+    expr.evaluate(context)
+}
+
+/// Evaluate a condition to true, false or unknown (`None`)
+///
+/// Malformed conditions are treated as unknown.
+pub fn evaluate_condition(expression: &str, context: &ExpressionContext) -> Option<bool> {
+    evaluate(expression, context)
+        .ok()
+        .flatten()
+        .map(|value| truthy(&value))
+}
+
+/// Replace a value that is a single `${{ expression }}` by its compile-time value
+///
+/// Values that cannot be evaluated are returned unchanged, so they keep marking the value
+/// as unknown wherever they are used.
+pub fn resolve_value(value: &Value, context: &ExpressionContext) -> Value {
+    let Some(expression) = value
+        .as_str()
+        .and_then(|s| s.trim().strip_prefix("${{"))
+        .and_then(|s| s.strip_suffix("}}"))
+    else {
+        return value.clone();
+    };
+    if expression.contains("${{") {
+        return value.clone();
+    }
+
+    match evaluate(expression.trim(), context) {
+        Ok(Some(resolved)) => resolved,
+        _ => value.clone(),
+    }
+}
+
+/// A document with the branches that would not be compiled removed
+#[derive(Debug, Clone)]
+pub struct Evaluation {
+    /// The document with the value of every inactive `${{ if }}`, `${{ elseif }}`,
+    /// `${{ else }}` and `${{ each }}` key replaced by null (keys stay, so paths are stable)
+    pub value: Value,
+    /// Paths of the inactive directive keys
+    pub inactive: Vec<YamlPath>,
+}
+
+impl Evaluation {
+    /// Whether a path lies inside a branch that would not be compiled
+    pub fn is_inactive(&self, path: &YamlPath) -> bool {
+        self.inactive
+            .iter()
+            .any(|inactive| path.starts_with(inactive))
+    }
+}
+
+/// Evaluate the conditionals and loops of a document
+///
+/// A branch is active if it would be compiled for at least one iteration of the loops that
+/// enclose it. Branches whose condition is unknown are active.
+///
+/// # Arguments
+/// * `value` - Parsed pipeline or template document
+/// * `context` - Parameter values of the document
+///
+/// # Returns
+/// * `Evaluation` - The document without inactive branches and their paths
+pub fn prune_inactive(value: &Value, context: &ExpressionContext) -> Evaluation {
+    let mut state = PruneState::default();
+    visit(value, &YamlPath::root(), context, &mut state);
+
+    let inactive: Vec<YamlPath> = state
+        .seen
+        .into_iter()
+        .filter(|path| !state.active.contains(path))
+        .collect();
+
+    let mut pruned = value.clone();
+    for path in &inactive {
+        if let Some(node) = node_mut(&mut pruned, path) {
+            *node = Value::Null;
+        }
+    }
+
+    Evaluation {
+        value: pruned,
+        inactive,
+    }
+}
+
+/// Directive keys visited while evaluating a document
+#[derive(Default)]
+struct PruneState {
+    /// Every directive key visited, in document order
+    seen: Vec<YamlPath>,
+    /// Directive keys active in at least one evaluation
+    active: HashSet<YamlPath>,
+}
+
+impl PruneState {
+    fn record(&mut self, path: &YamlPath, active: bool) {
+        if !self.seen.contains(path) {
+            self.seen.push(path.clone());
+        }
+        if active {
+            self.active.insert(path.clone());
+        }
+    }
+}
+
+/// Outcome of an `if` / `elseif` chain so far: `Some(true)` once a branch was taken,
+/// `Some(false)` if none was, `None` if a condition was unknown
+type Chain = Option<Option<bool>>;
+
+fn visit(value: &Value, path: &YamlPath, context: &ExpressionContext, state: &mut PruneState) {
+    match value {
+        Value::Mapping(map) => {
+            visit_mapping(map, path, context, state, None);
+        }
+        Value::Sequence(items) => {
+            // `- ${{ if }}:` / `- ${{ else }}:` items form a chain across the sequence
+            let mut chain = None;
+            for (i, item) in items.iter().enumerate() {
+                chain = match item {
+                    Value::Mapping(map) => {
+                        visit_mapping(map, &path.index(i), context, state, chain)
+                    }
+                    _ => {
+                        visit(item, &path.index(i), context, state);
+                        None
+                    }
+                };
+            }
+        }
+        _ => {}
+    }
+}
+
+fn visit_mapping(
+    map: &Mapping,
+    path: &YamlPath,
+    context: &ExpressionContext,
+    state: &mut PruneState,
+    mut chain: Chain,
+) -> Chain {
+    for (key, value) in map {
+        let Some(key) = key.as_str() else {
+            chain = None;
+            continue;
+        };
+        let key_path = path.key(key);
+
+        match parse_directive(key) {
+            Some(Directive::If(condition)) => {
+                let result = evaluate_condition(&condition, context);
+                let active = result != Some(false);
+                state.record(&key_path, active);
+                if active {
+                    visit(value, &key_path, context, state);
+                }
+                chain = Some(result);
+            }
+            Some(Directive::ElseIf(condition)) => {
+                let taken = chain.unwrap_or(Some(false));
+                let (active, next) = if taken == Some(true) {
+                    (false, Some(true))
+                } else {
+                    let result = evaluate_condition(&condition, context);
+                    let next = match (taken, result) {
+                        (_, Some(true)) => Some(true),
+                        (Some(false), Some(false)) => Some(false),
+                        _ => None,
+                    };
+                    (result != Some(false), next)
+                };
+                state.record(&key_path, active);
+                if active {
+                    visit(value, &key_path, context, state);
+                }
+                chain = Some(next);
+            }
+            Some(Directive::Else) => {
+                let active = chain.unwrap_or(Some(false)) != Some(true);
+                state.record(&key_path, active);
+                if active {
+                    visit(value, &key_path, context, state);
+                }
+                chain = None;
+            }
+            Some(Directive::Each {
+                variable,
+                collection,
+            }) => {
+                let items: Option<Vec<Value>> = match evaluate(&collection, context) {
+                    Ok(Some(Value::Sequence(items))) => Some(items),
+                    Ok(Some(Value::Mapping(map))) => Some(
+                        map.iter()
+                            .map(|(k, v)| {
+                                let mut pair = Mapping::new();
+                                pair.insert(Value::String("key".to_string()), k.clone());
+                                pair.insert(Value::String("value".to_string()), v.clone());
+                                Value::Mapping(pair)
+                            })
+                            .collect(),
+                    ),
+                    Ok(Some(Value::Null)) => Some(Vec::new()),
+                    _ => None,
+                };
+
+                match items {
+                    Some(items) => {
+                        state.record(&key_path, !items.is_empty());
+                        for item in items {
+                            visit(
+                                value,
+                                &key_path,
+                                &context.with_local(&variable, Some(item)),
+                                state,
+                            );
+                        }
+                    }
+                    None => {
+                        state.record(&key_path, true);
+                        visit(
+                            value,
+                            &key_path,
+                            &context.with_local(&variable, None),
+                            state,
+                        );
+                    }
+                }
+                chain = None;
+            }
+            Some(Directive::Insert) | Some(Directive::Expression(_)) | None => {
+                visit(value, &key_path, context, state);
+                chain = None;
+            }
+        }
+    }
+    chain
+}
+
+/// Mutable access to the node at a path
+fn node_mut<'a>(value: &'a mut Value, path: &YamlPath) -> Option<&'a mut Value> {
+    let mut node = value;
+    for segment in path.segments() {
+        node = match segment {
+            PathSegment::Key(key) => node.as_mapping_mut()?.get_mut(key.as_str())?,
+            PathSegment::Index(index) => node.as_sequence_mut()?.get_mut(*index)?,
+        };
+    }
+    Some(node)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Number(f64),
+    Str(String),
+    LParen,
+    RParen,
+    LBracket,
+    RBracket,
+    Comma,
+    Dot,
+}
+
+fn tokenize(expression: &str) -> Result<Vec<Token>> {
+    let chars: Vec<char> = expression.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            _ if c.is_whitespace() => i += 1,
+            '(' | ')' | '[' | ']' | ',' | '.' => {
+                tokens.push(match c {
+                    '(' => Token::LParen,
+                    ')' => Token::RParen,
+                    '[' => Token::LBracket,
+                    ']' => Token::RBracket,
+                    ',' => Token::Comma,
+                    _ => Token::Dot,
+                });
+                i += 1;
+            }
+            '\'' => {
+                // Strings are single quoted, with '' as an escaped quote
+                let mut text = String::new();
+                i += 1;
+                loop {
+                    match chars.get(i) {
+                        Some('\'') if chars.get(i + 1) == Some(&'\'') => {
+                            text.push('\'');
+                            i += 2;
+                        }
+                        Some('\'') => {
+                            i += 1;
+                            break;
+                        }
+                        Some(ch) => {
+                            text.push(*ch);
+                            i += 1;
+                        }
+                        None => bail!("Unterminated string in expression: {expression}"),
+                    }
+                }
+                tokens.push(Token::Str(text));
+            }
+            _ if c.is_ascii_digit()
+                || (c == '-' && chars.get(i + 1).is_some_and(|n| n.is_ascii_digit())) =>
+            {
+                let start = i;
+                i += 1;
+                while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                    i += 1;
+                }
+                let text: String = chars[start..i].iter().collect();
+                let number = text
+                    .parse::<f64>()
+                    .map_err(|_| anyhow!("Invalid number '{text}' in expression: {expression}"))?;
+                tokens.push(Token::Number(number));
+            }
+            _ if c.is_alphabetic() || c == '_' => {
+                let start = i;
+                while i < chars.len()
+                    && (chars[i].is_alphanumeric() || chars[i] == '_' || chars[i] == '-')
+                {
+                    i += 1;
+                }
+                tokens.push(Token::Ident(chars[start..i].iter().collect()));
+            }
+            _ => bail!("Unexpected character '{c}' in expression: {expression}"),
+        }
+    }
+
+    Ok(tokens)
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Literal(Value),
+    Name(String),
+    Call(String, Vec<Expr>),
+    Property(Box<Expr>, String),
+    Index(Box<Expr>, Box<Expr>),
+}
+
+struct ExprParser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl ExprParser {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<()> {
+        match self.next() {
+            Some(token) if token == expected => Ok(()),
+            other => bail!("Expected {expected:?}, found {other:?}"),
+        }
+    }
+
+    fn expression(&mut self) -> Result<Expr> {
+        let mut expr = match self.next() {
+            Some(Token::Str(text)) => Expr::Literal(Value::String(text)),
+            Some(Token::Number(number)) => Expr::Literal(Value::Number(number_value(number))),
+            Some(Token::Ident(name)) if self.peek() == Some(&Token::LParen) => {
+                self.next();
+                let mut args = Vec::new();
+                if self.peek() != Some(&Token::RParen) {
+                    loop {
+                        args.push(self.expression()?);
+                        if self.peek() == Some(&Token::Comma) {
+                            self.next();
+                        } else {
+                            break;
+                        }
+                    }
+                }
+                self.expect(Token::RParen)?;
+                Expr::Call(name.to_ascii_lowercase(), args)
+            }
+            Some(Token::Ident(name)) => match name.to_ascii_lowercase().as_str() {
+                "true" => Expr::Literal(Value::Bool(true)),
+                "false" => Expr::Literal(Value::Bool(false)),
+                "null" => Expr::Literal(Value::Null),
+                _ => Expr::Name(name),
+            },
+            other => bail!("Unexpected token {other:?}"),
+        };
+
+        loop {
+            match self.peek() {
+                Some(Token::Dot) => {
+                    self.next();
+                    match self.next() {
+                        Some(Token::Ident(name)) => expr = Expr::Property(Box::new(expr), name),
+                        other => bail!("Expected property name, found {other:?}"),
+                    }
+                }
+                Some(Token::LBracket) => {
+                    self.next();
+                    let index = self.expression()?;
+                    self.expect(Token::RBracket)?;
+                    expr = Expr::Index(Box::new(expr), Box::new(index));
+                }
+                _ => return Ok(expr),
+            }
+        }
+    }
+}
+
+impl Expr {
+    fn evaluate(&self, context: &ExpressionContext) -> Result<Option<Value>> {
+        let value = match self {
+            Expr::Literal(value) => Some(value.clone()),
+            Expr::Name(name) => context.lookup(name),
+            Expr::Property(object, name) => object
+                .evaluate(context)?
+                .map(|object| member(&object, &Value::String(name.clone()))),
+            Expr::Index(object, index) => {
+                match (object.evaluate(context)?, index.evaluate(context)?) {
+                    (Some(object), Some(index)) => Some(member(&object, &index)),
+                    _ => None,
+                }
+            }
+            Expr::Call(name, args) => call(name, args, context)?,
+        };
+
+        // Parameters bound to expressions the caller could not evaluate stay unknown
+        Ok(value.filter(|v| !v.as_str().is_some_and(|s| s.contains("${{"))))
+    }
+}
+
+/// Property or index access; missing members are null
+fn member(object: &Value, index: &Value) -> Value {
+    match (object, index) {
+        (Value::Mapping(map), _) => {
+            let key = to_text(index).unwrap_or_default();
+            map.iter()
+                .find(|(k, _)| k.as_str().is_some_and(|k| k.eq_ignore_ascii_case(&key)))
+                .map(|(_, v)| v.clone())
+                .unwrap_or(Value::Null)
+        }
+        (Value::Sequence(items), _) => to_number(index)
+            .filter(|n| *n >= 0.0)
+            .and_then(|n| items.get(n as usize).cloned())
+            .unwrap_or(Value::Null),
+        _ => Value::Null,
+    }
+}
+
+fn call(name: &str, args: &[Expr], context: &ExpressionContext) -> Result<Option<Value>> {
+    // Logical functions tolerate unknown arguments when the result is already decided
+    match name {
+        "and" | "or" => {
+            let decisive = name == "or";
+            let mut unknown = false;
+            for arg in args {
+                match arg.evaluate(context)? {
+                    Some(value) if truthy(&value) == decisive => {
+                        return Ok(Some(Value::Bool(decisive)))
+                    }
+                    Some(_) => {}
+                    None => unknown = true,
+                }
+            }
+            return Ok(if unknown {
+                None
+            } else {
+                Some(Value::Bool(!decisive))
+            });
+        }
+        "coalesce" => {
+            for arg in args {
+                match arg.evaluate(context)? {
+                    Some(Value::Null) => {}
+                    Some(Value::String(s)) if s.is_empty() => {}
+                    Some(value) => return Ok(Some(value)),
+                    None => return Ok(None),
+                }
+            }
+            return Ok(Some(Value::Null));
+        }
+        _ => {}
+    }
+
+    let mut values = Vec::with_capacity(args.len());
+    for arg in args {
+        match arg.evaluate(context)? {
+            Some(value) => values.push(value),
+            None => return Ok(None),
+        }
+    }
+
+    let arity = |min: usize, max: usize| -> Result<()> {
+        if values.len() < min || values.len() > max {
+            bail!(
+                "Function '{name}' expects {min} to {max} arguments, got {}",
+                values.len()
+            );
+        }
+        Ok(())
+    };
+    let text = |value: &Value| to_text(value).unwrap_or_else(|| to_json(value, 0));
+
+    let result = match name {
+        "eq" => {
+            arity(2, 2)?;
+            Value::Bool(equals(&values[0], &values[1]))
+        }
+        "ne" => {
+            arity(2, 2)?;
+            Value::Bool(!equals(&values[0], &values[1]))
+        }
+        "not" => {
+            arity(1, 1)?;
+            Value::Bool(!truthy(&values[0]))
+        }
+        "xor" => {
+            arity(2, 2)?;
+            Value::Bool(truthy(&values[0]) != truthy(&values[1]))
+        }
+        "in" | "notin" => {
+            arity(1, usize::MAX)?;
+            let found = values[1..].iter().any(|v| equals(&values[0], v));
+            Value::Bool(found == (name == "in"))
+        }
+        "gt" | "ge" | "lt" | "le" => {
+            arity(2, 2)?;
+            let ordering = compare(&values[0], &values[1])?;
+            Value::Bool(match name {
+                "gt" => ordering.is_gt(),
+                "ge" => ordering.is_ge(),
+                "lt" => ordering.is_lt(),
+                _ => ordering.is_le(),
+            })
+        }
+        "contains" => {
+            arity(2, 2)?;
+            match &values[0] {
+                Value::Sequence(items) => {
+                    Value::Bool(items.iter().any(|item| equals(item, &values[1])))
+                }
+                haystack => Value::Bool(
+                    text(haystack)
+                        .to_lowercase()
+                        .contains(&text(&values[1]).to_lowercase()),
+                ),
+            }
+        }
+        "containsvalue" => {
+            arity(2, 2)?;
+            let found = match &values[0] {
+                Value::Sequence(items) => items.iter().any(|item| equals(item, &values[1])),
+                Value::Mapping(map) => map.values().any(|item| equals(item, &values[1])),
+                _ => false,
+            };
+            Value::Bool(found)
+        }
+        "startswith" => {
+            arity(2, 2)?;
+            Value::Bool(
+                text(&values[0])
+                    .to_lowercase()
+                    .starts_with(&text(&values[1]).to_lowercase()),
+            )
+        }
+        "endswith" => {
+            arity(2, 2)?;
+            Value::Bool(
+                text(&values[0])
+                    .to_lowercase()
+                    .ends_with(&text(&values[1]).to_lowercase()),
+            )
+        }
+        "format" => {
+            arity(1, usize::MAX)?;
+            let arguments: Vec<String> = values[1..].iter().map(text).collect();
+            Value::String(format_string(&text(&values[0]), &arguments)?)
+        }
+        "join" => {
+            arity(2, 2)?;
+            match &values[1] {
+                Value::Sequence(items) => {
+                    let parts: Vec<String> = items.iter().map(text).collect();
+                    Value::String(parts.join(&text(&values[0])))
+                }
+                other => Value::String(text(other)),
+            }
+        }
+        "length" => {
+            arity(1, 1)?;
+            let length = match &values[0] {
+                Value::String(s) => s.chars().count(),
+                Value::Sequence(items) => items.len(),
+                Value::Mapping(map) => map.len(),
+                Value::Null => 0,
+                other => bail!("Function 'length' cannot be applied to {}", text(other)),
+            };
+            Value::Number(length.into())
+        }
+        "converttojson" => {
+            arity(1, 1)?;
+            Value::String(to_json(&values[0], 0))
+        }
+        "lower" => {
+            arity(1, 1)?;
+            Value::String(text(&values[0]).to_lowercase())
+        }
+        "upper" => {
+            arity(1, 1)?;
+            Value::String(text(&values[0]).to_uppercase())
+        }
+        "replace" => {
+            arity(3, 3)?;
+            Value::String(text(&values[0]).replace(&text(&values[1]), &text(&values[2])))
+        }
+        "split" => {
+            arity(2, 2)?;
+            let parts = text(&values[0])
+                .split(text(&values[1]).as_str())
+                .map(|part| Value::String(part.to_string()))
+                .collect();
+            Value::Sequence(parts)
+        }
+        "iif" => {
+            arity(3, 3)?;
+            if truthy(&values[0]) {
+                values[1].clone()
+            } else {
+                values[2].clone()
+            }
+        }
+        // Functions evaluated at runtime (counter, variables-based, ...) are unknown
+        _ => return Ok(None),
+    };
+
+    Ok(Some(result))
+}
+
+/// Truthiness of a value: null, false, 0 and '' are false, everything else is true
+pub fn truthy(value: &Value) -> bool {
+    match value {
+        Value::Null => false,
+        Value::Bool(b) => *b,
+        Value::Number(n) => n.as_f64().is_some_and(|n| n != 0.0),
+        Value::String(s) => !s.is_empty(),
+        _ => true,
+    }
+}
+
+/// Equality with the right value converted to the type of the left one;
+/// strings compare ignoring case
+fn equals(left: &Value, right: &Value) -> bool {
+    match left {
+        Value::Null => match right {
+            Value::Null => true,
+            Value::String(s) => s.is_empty(),
+            _ => false,
+        },
+        Value::Bool(b) => to_bool(right) == Some(*b),
+        Value::Number(_) => match (to_number(left), to_number(right)) {
+            (Some(l), Some(r)) => l == r,
+            _ => false,
+        },
+        Value::String(s) => to_text(right).is_some_and(|r| r.eq_ignore_ascii_case(s)),
+        _ => left == right,
+    }
+}
+
+/// Ordering with the right value converted to the type of the left one
+fn compare(left: &Value, right: &Value) -> Result<std::cmp::Ordering> {
+    let ordering = match left {
+        Value::Number(_) | Value::Bool(_) | Value::Null => to_number(left)
+            .zip(to_number(right))
+            .and_then(|(l, r)| l.partial_cmp(&r)),
+        Value::String(s) => to_text(right).map(|r| s.to_lowercase().cmp(&r.to_lowercase())),
+        _ => None,
+    };
+    ordering.ok_or_else(|| anyhow!("Values cannot be compared"))
+}
+
+fn to_bool(value: &Value) -> Option<bool> {
+    match value {
+        Value::Bool(b) => Some(*b),
+        Value::String(s) if s.eq_ignore_ascii_case("true") => Some(true),
+        Value::String(s) if s.eq_ignore_ascii_case("false") => Some(false),
+        Value::Sequence(_) | Value::Mapping(_) => None,
+        other => Some(truthy(other)),
+    }
+}
+
+fn to_number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) if s.trim().is_empty() => Some(0.0),
+        Value::String(s) => s.trim().parse().ok(),
+        Value::Bool(b) => Some(if *b { 1.0 } else { 0.0 }),
+        Value::Null => Some(0.0),
+        _ => None,
+    }
+}
+
+/// Text of a scalar value (`None` for sequences and mappings)
+fn to_text(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(true) => Some("True".to_string()),
+        Value::Bool(false) => Some("False".to_string()),
+        Value::Null => Some(String::new()),
+        _ => None,
+    }
+}
+
+/// Integral numbers stay integers so they print without a fraction
+fn number_value(number: f64) -> serde_yaml::Number {
+    if number.fract() == 0.0 && number.abs() < i64::MAX as f64 {
+        (number as i64).into()
+    } else {
+        number.into()
+    }
+}
+
+/// Substitute `{0}`, `{1}`, ... in a format string; `{{` and `}}` are literal braces
+fn format_string(format: &str, arguments: &[String]) -> Result<String> {
+    let mut result = String::new();
+    let mut chars = format.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                result.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                result.push('}');
+            }
+            '{' => {
+                let mut index = String::new();
+                for c in chars.by_ref() {
+                    if c == '}' {
+                        break;
+                    }
+                    index.push(c);
+                }
+                let argument = index
+                    .trim()
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|i| arguments.get(i))
+                    .ok_or_else(|| {
+                        anyhow!("Invalid format placeholder '{{{index}}}' in '{format}'")
+                    })?;
+                result.push_str(argument);
+            }
+            _ => result.push(c),
+        }
+    }
+
+    Ok(result)
+}
+
+/// Serialize a value as indented JSON, the way `convertToJson` does
+fn to_json(value: &Value, indent: usize) -> String {
+    let pad = "  ".repeat(indent + 1);
+    let close = "  ".repeat(indent);
+    match value {
+        Value::Null => "null".to_string(),
+        Value::Bool(b) => b.to_string(),
+        Value::Number(n) => n.to_string(),
+        Value::String(s) => json_string(s),
+        Value::Sequence(items) if items.is_empty() => "[]".to_string(),
+        Value::Sequence(items) => {
+            let items: Vec<String> = items
+                .iter()
+                .map(|item| format!("{pad}{}", to_json(item, indent + 1)))
+                .collect();
+            format!("[\n{}\n{close}]", items.join(",\n"))
+        }
+        Value::Mapping(map) if map.is_empty() => "{}".to_string(),
+        Value::Mapping(map) => {
+            let entries: Vec<String> = map
+                .iter()
+                .map(|(k, v)| {
+                    let key = to_text(k).unwrap_or_else(|| to_json(k, indent + 1));
+                    format!("{pad}{}: {}", json_string(&key), to_json(v, indent + 1))
+                })
+                .collect();
+            format!("{{\n{}\n{close}}}", entries.join(",\n"))
+        }
+        Value::Tagged(tagged) => to_json(&tagged.value, indent),
+    }
+}
+
+fn json_string(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len() + 2);
+    escaped.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context() -> ExpressionContext {
+        let parameters: Mapping = serde_yaml::from_str(
+            "
+env: prod
+regions: [westeurope, northeurope]
+replicas: 3
+deploy: true
+settings:
+  name: contoso
+  tier: Premium
+pending: ${{ parameters.other }}
+",
+        )
+        .unwrap();
+        ExpressionContext::new(parameters)
+    }
+
+    fn eval(expression: &str) -> Option<Value> {
+        evaluate(expression, &context()).unwrap()
+    }
+
+    fn eval_str(expression: &str) -> String {
+        eval(expression).and_then(|v| to_text(&v)).unwrap()
+    }
+
+    #[test]
+    fn test_parse_directive() {
+        assert_eq!(
+            parse_directive("${{ if eq(parameters.env, 'prod') }}"),
+            Some(Directive::If("eq(parameters.env, 'prod')".to_string()))
+        );
+        assert_eq!(
+            parse_directive("${{ elseif ne(1, 2) }}"),
+            Some(Directive::ElseIf("ne(1, 2)".to_string()))
+        );
+        assert_eq!(parse_directive("${{ else }}"), Some(Directive::Else));
+        assert_eq!(
+            parse_directive("${{ each region in parameters.regions }}"),
+            Some(Directive::Each {
+                variable: "region".to_string(),
+                collection: "parameters.regions".to_string()
+            })
+        );
+        assert_eq!(parse_directive("${{ insert }}"), Some(Directive::Insert));
+        assert_eq!(
+            parse_directive("${{ parameters.name }}"),
+            Some(Directive::Expression("parameters.name".to_string()))
+        );
+        assert_eq!(parse_directive("script"), None);
+    }
+
+    #[test]
+    fn test_comparison_functions() {
+        assert_eq!(eval("eq(parameters.env, 'PROD')"), Some(Value::Bool(true)));
+        assert_eq!(eval("ne(parameters.env, 'dev')"), Some(Value::Bool(true)));
+        assert_eq!(
+            eval("eq(parameters.replicas, '3')"),
+            Some(Value::Bool(true))
+        );
+        assert_eq!(
+            eval("eq(parameters.deploy, 'true')"),
+            Some(Value::Bool(true))
+        );
+        assert_eq!(
+            eval("in(parameters.env, 'dev', 'prod')"),
+            Some(Value::Bool(true))
+        );
+        assert_eq!(
+            eval("notIn(parameters.env, 'dev', 'test')"),
+            Some(Value::Bool(true))
+        );
+        assert_eq!(eval("gt(parameters.replicas, 2)"), Some(Value::Bool(true)));
+        assert_eq!(eval("eq(parameters.missing, '')"), Some(Value::Bool(true)));
+    }
+
+    #[test]
+    fn test_logical_functions() {
+        assert_eq!(
+            eval("and(parameters.deploy, eq(parameters.env, 'prod'))"),
+            Some(Value::Bool(true))
+        );
+        assert_eq!(
+            eval("or(false, not(parameters.deploy))"),
+            Some(Value::Bool(false))
+        );
+        assert_eq!(eval("not(parameters.missing)"), Some(Value::Bool(true)));
+
+        // Unknown operands only matter if they can change the result
+        assert_eq!(
+            eval("and(false, eq(variables.x, 'y'))"),
+            Some(Value::Bool(false))
+        );
+        assert_eq!(
+            eval("or(true, eq(variables.x, 'y'))"),
+            Some(Value::Bool(true))
+        );
+        assert_eq!(eval("and(true, eq(variables.x, 'y'))"), None);
+        assert_eq!(eval("eq(parameters.pending, 'x')"), None);
+    }
+
+    #[test]
+    fn test_string_functions() {
+        assert_eq!(
+            eval("contains(parameters.settings.tier, 'prem')"),
+            Some(Value::Bool(true))
+        );
+        assert_eq!(
+            eval("contains(parameters.regions, 'WestEurope')"),
+            Some(Value::Bool(true))
+        );
+        assert_eq!(
+            eval("startsWith(parameters.env, 'pr')"),
+            Some(Value::Bool(true))
+        );
+        assert_eq!(
+            eval("endsWith(parameters['env'], 'OD')"),
+            Some(Value::Bool(true))
+        );
+        assert_eq!(
+            eval_str("format('{0}-{1}-{{x}}', parameters.settings.name, parameters.replicas)"),
+            "contoso-3-{x}"
+        );
+        assert_eq!(
+            eval_str("coalesce(parameters.missing, '', parameters.env)"),
+            "prod"
+        );
+        assert_eq!(
+            eval_str("join(';', parameters.regions)"),
+            "westeurope;northeurope"
+        );
+        assert_eq!(eval_str("length(parameters.regions)"), "2");
+        assert_eq!(eval_str("length('abc')"), "3");
+        assert_eq!(eval_str("parameters.regions[1]"), "northeurope");
+        assert_eq!(
+            eval_str("convertToJson(parameters.regions)"),
+            "[\n  \"westeurope\",\n  \"northeurope\"\n]"
+        );
+    }
+
+    #[test]
+    fn test_malformed_expression() {
+        assert!(evaluate("eq(parameters.env, 'prod'", &context()).is_err());
+        assert!(evaluate("eq(parameters.env)", &context()).is_err());
+        assert_eq!(evaluate_condition("eq(", &context()), None);
+    }
+
+    #[test]
+    fn test_prune_if_elseif_else() {
+        let yaml: Value = serde_yaml::from_str(
+            "
+variables:
+  - ${{ if eq(parameters.env, 'dev') }}:
+    - group: dev-secrets
+  - ${{ elseif eq(parameters.env, 'prod') }}:
+    - group: prod-secrets
+  - ${{ else }}:
+    - group: other-secrets
+  - ${{ if eq(variables['Build.Reason'], 'PullRequest') }}:
+    - group: pr-secrets
+",
+        )
+        .unwrap();
+        let evaluation = prune_inactive(&yaml, &context());
+
+        let inactive: Vec<String> = evaluation.inactive.iter().map(|p| p.to_string()).collect();
+        assert_eq!(
+            inactive,
+            vec![
+                "variables[0]['${{ if eq(parameters.env, 'dev') }}']",
+                "variables[2]['${{ else }}']"
+            ]
+        );
+        assert!(evaluation.value["variables"][0]["${{ if eq(parameters.env, 'dev') }}"].is_null());
+        assert!(
+            evaluation.value["variables"][1]["${{ elseif eq(parameters.env, 'prod') }}"]
+                .is_sequence()
+        );
+        // Unknown conditions keep their branch
+        assert!(evaluation.value["variables"][3]
+            ["${{ if eq(variables['Build.Reason'], 'PullRequest') }}"]
+            .is_sequence());
+    }
+
+    #[test]
+    fn test_prune_each_loops() {
+        let yaml: Value = serde_yaml::from_str(
+            "
+stages:
+  - ${{ each region in parameters.regions }}:
+    - stage: deploy_${{ region }}
+      jobs:
+        - ${{ if eq(region, 'northeurope') }}:
+          - job: north
+        - ${{ if eq(region, 'eastus') }}:
+          - job: east
+  - ${{ each item in parameters.empty }}:
+    - stage: never
+",
+        )
+        .unwrap();
+        let evaluation = prune_inactive(&yaml, &context());
+
+        let inactive: Vec<String> = evaluation.inactive.iter().map(|p| p.to_string()).collect();
+        assert_eq!(
+            inactive,
+            vec![
+                "stages[0]['${{ each region in parameters.regions }}'][0].jobs[1]['${{ if eq(region, 'eastus') }}']",
+                "stages[1]['${{ each item in parameters.empty }}']"
+            ]
+        );
+        assert!(evaluation.is_inactive(
+            &YamlPath::root()
+                .key("stages")
+                .index(1)
+                .key("${{ each item in parameters.empty }}")
+                .index(0)
+        ));
+    }
+
+    #[test]
+    fn test_resolve_value() {
+        let value = Value::String("${{ parameters.env }}".to_string());
+        assert_eq!(
+            resolve_value(&value, &context()),
+            Value::String("prod".to_string())
+        );
+
+        let unknown = Value::String("${{ variables.env }}".to_string());
+        assert_eq!(resolve_value(&unknown, &context()), unknown);
+    }
+}
@@ -1,4 +1,19 @@
 pub mod parser;
 pub mod azure;
+pub mod cache;
+pub mod concurrent;
+pub mod backend;
 pub mod validator;
 pub mod error;
+pub mod span;
+pub mod scope;
+pub mod template;
+pub mod parameters;
+pub mod expression;
+pub mod outputs;
+pub mod suggest;
+pub mod usage;
+pub mod precedence;
+pub mod secrets;
+pub mod overrides;
+pub mod snapshot;
@@ -1,13 +1,31 @@
-use clap::Parser;
+use clap::{Parser, Subcommand, ValueEnum};
+use regex::Regex;
+use std::cell::OnceCell;
+use std::path::PathBuf;
 use std::process;
+use std::sync::Arc;
+use std::time::Duration;
 
-use azdolint::azure::AzureDevOpsClient;
+use azdolint::azure::{AzureDevOpsClient, VariableGroupData, DEFAULT_CONCURRENCY};
+use azdolint::backend::{GroupIndex, VariableBackend};
+use azdolint::cache::{default_cache_dir, CacheStatistics, ResponseCache, DEFAULT_CACHE_TTL};
 use azdolint::error::OutputFormatter;
-use azdolint::parser::{
-    detect_template, extract_template_references, extract_variable_references,
-    extract_variable_references_from_content, parse_pipeline_file, resolve_template_path,
+use azdolint::outputs::{validate_output_references, OutputValidationResult};
+use azdolint::overrides::{check_overrides, parse_overrides, read_overrides_file};
+use azdolint::parser::{detect_template, extract_variable_references, parse_pipeline_file};
+use azdolint::precedence::{diagnose_precedence, PrecedenceReport};
+use azdolint::secrets::find_secret_misuse;
+use azdolint::snapshot::Snapshot;
+use azdolint::scope::{Scope, ROOT_LABEL};
+use azdolint::span::SourceSpan;
+use azdolint::template::expand_templates_with_parameters;
+use azdolint::usage::{find_unused_groups, find_unused_variables, VariableUsage};
+use azdolint::validator::{
+    attach_group_locations, attach_group_suggestions, attach_project_suggestions, attach_unlinked_groups,
+    attach_value_problems, fetch_group_variable_details, group_variable_pairs, pipeline_variables_from, resolve_in_chain,
+    validate_scoped_variables_against_available, validate_variable_groups, PipelineVariable,
+    VariableValidationResult, VariableSource, DEFAULT_PLACEHOLDER_PATTERN,
 };
-use azdolint::validator::{validate_variable_groups, validate_variables, VariableSource};
 
 /// Azure DevOps pipeline YAML validator
 ///
@@ -16,18 +34,47 @@ use azdolint::validator::{validate_variable_groups, validate_variables, Variable
 #[derive(Parser, Debug)]
 #[command(name = "azdo-linter")]
 #[command(about = "Validates Azure DevOps pipeline YAML variable references")]
+#[command(subcommand_negates_reqs = true, args_conflicts_with_subcommands = true)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Path to the Azure DevOps pipeline YAML file to validate
-    #[arg(short, long)]
-    pipeline_file: String,
+    #[arg(short, long, required = true)]
+    pipeline_file: Option<String>,
 
     /// Azure DevOps organization name (e.g., 'myorg' from https://dev.azure.com/myorg)
-    #[arg(short, long)]
-    organization: String,
+    #[arg(short, long, required_unless_present = "offline")]
+    organization: Option<String>,
 
     /// Azure DevOps project name
-    #[arg(short = 'j', long)]
-    project: String,
+    #[arg(short = 'j', long, required_unless_present = "offline")]
+    project: Option<String>,
+
+    /// Validate against a snapshot file written by `azdolint snapshot` instead of
+    /// Azure DevOps, without any HTTP requests
+    #[arg(long, value_name = "SNAPSHOT")]
+    offline: Option<String>,
+
+    /// Directory of the API response cache (default: azdolint in the user's cache directory)
+    #[arg(long, value_name = "PATH", env = "AZDOLINT_CACHE_DIR")]
+    cache_dir: Option<PathBuf>,
+
+    /// Seconds a cached API response is used before it is revalidated with Azure DevOps
+    #[arg(long, value_name = "SECONDS", default_value_t = DEFAULT_CACHE_TTL.as_secs())]
+    cache_ttl: u64,
+
+    /// Neither read nor write the API response cache
+    #[arg(long, default_value_t = false)]
+    no_cache: bool,
+
+    /// Ignore cached API responses and replace them with fresh ones
+    #[arg(long, default_value_t = false, conflicts_with = "no_cache")]
+    refresh: bool,
+
+    /// Maximum number of Azure DevOps API requests sent at the same time
+    #[arg(long, value_name = "N", default_value_t = DEFAULT_CONCURRENCY, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    concurrency: usize,
 
     /// Pipeline name in Azure DevOps (enables validation against pipeline-level variables)
     #[arg(short = 'n', long)]
@@ -41,11 +88,87 @@ struct Args {
     #[arg(long, env = "AZDO_PAT")]
     pat: Option<String>,
 
+    /// Value for a runtime parameter of the pipeline, used to evaluate `${{ }}` conditions
+    /// (repeatable, e.g. --parameter environment=prod)
+    #[arg(long = "parameter", value_name = "NAME=VALUE")]
+    parameters: Vec<String>,
+
+    /// How to report a variable referenced with different casing than its definition
+    #[arg(long, value_enum, default_value_t = CasingMismatch::Warn)]
+    casing_mismatch: CasingMismatch,
+
+    /// Regular expression for placeholder values of group variables, matched against the
+    /// trimmed value (e.g. 'TODO', 'changeme', '<fill in>')
+    #[arg(long, value_name = "REGEX", default_value = DEFAULT_PLACEHOLDER_PATTERN)]
+    placeholder_pattern: String,
+
+    /// Variable to be set when queueing a run; reports whether Azure DevOps accepts the value
+    /// and whether it takes effect (repeatable, requires --pipeline-id or --pipeline-name)
+    #[arg(long = "set", value_name = "NAME=VALUE")]
+    queue_time_variables: Vec<String>,
+
+    /// File with one NAME=VALUE queue-time variable per line, checked like --set
+    #[arg(long, value_name = "PATH")]
+    set_file: Option<String>,
+
     /// Enable verbose output for debugging
     #[arg(short, long, default_value_t = false)]
     verbose: bool,
 }
 
+/// Commands other than validating a pipeline
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Save the variable groups and pipeline definitions of a project to a JSON file for
+    /// validating with --offline
+    Snapshot(SnapshotArgs),
+}
+
+/// Arguments of the `snapshot` command
+#[derive(clap::Args, Debug)]
+struct SnapshotArgs {
+    /// Azure DevOps organization name (e.g., 'myorg' from https://dev.azure.com/myorg)
+    #[arg(short, long)]
+    organization: String,
+
+    /// Azure DevOps project name
+    #[arg(short = 'j', long)]
+    project: String,
+
+    /// Personal Access Token for Azure DevOps authentication (can also use AZDO_PAT env var)
+    #[arg(long, env = "AZDO_PAT")]
+    pat: Option<String>,
+
+    /// Path of the snapshot file to write
+    #[arg(long, value_name = "PATH", default_value = "azdolint-snapshot.json")]
+    output: String,
+
+    /// Also store the values of non-secret variables (secret values are never available)
+    #[arg(long, default_value_t = false)]
+    include_values: bool,
+
+    /// Maximum number of Azure DevOps API requests sent at the same time
+    #[arg(long, value_name = "N", default_value_t = DEFAULT_CONCURRENCY, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    concurrency: usize,
+
+    /// Enable verbose output for debugging
+    #[arg(short, long, default_value_t = false)]
+    verbose: bool,
+}
+
+/// Reporting of variable references whose casing differs from the definition
+///
+/// Azure DevOps resolves variable names case-insensitively, so such references always work.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum CasingMismatch {
+    /// Accept the reference silently
+    Ignore,
+    /// Accept the reference with a warning naming the canonical spelling
+    Warn,
+    /// Fail validation
+    Error,
+}
+
 /// Exit codes for the validator
 /// 0 = Success (all validations passed)
 /// 1 = Validation failures (some variable groups or variables not found)
@@ -57,10 +180,27 @@ const EXIT_ERROR: i32 = 2;
 fn main() {
     let args = Args::parse();
 
+    if let Some(Command::Snapshot(ref snapshot_args)) = args.command {
+        if let Err(e) = run_snapshot(snapshot_args) {
+            eprintln!("Error: {e}");
+            process::exit(EXIT_ERROR);
+        }
+        process::exit(EXIT_SUCCESS);
+    }
+
     if args.verbose {
-        println!("Pipeline file: {}", args.pipeline_file);
-        println!("Organization: {}", args.organization);
-        println!("Project: {}", args.project);
+        if let Some(ref pipeline_file) = args.pipeline_file {
+            println!("Pipeline file: {pipeline_file}");
+        }
+        if let Some(ref organization) = args.organization {
+            println!("Organization: {organization}");
+        }
+        if let Some(ref project) = args.project {
+            println!("Project: {project}");
+        }
+        if let Some(ref path) = args.offline {
+            println!("Offline snapshot: {path}");
+        }
         if let Some(id) = args.pipeline_id {
             println!("Pipeline ID: {id}");
         }
@@ -84,19 +224,62 @@ fn main() {
     }
 }
 
+/// Take a snapshot of the project's variable groups and pipeline definitions and write it to a file
+fn run_snapshot(args: &SnapshotArgs) -> Result<(), anyhow::Error> {
+    let client = AzureDevOpsClient::new(args.organization.clone(), args.project.clone(), args.pat.clone())?
+        .with_concurrency(args.concurrency);
+    if args.verbose {
+        println!(
+            "{}",
+            OutputFormatter::info(&format!("Taking snapshot of {}/{}", client.organization, client.project))
+        );
+    }
+
+    let (snapshot, warnings) = Snapshot::capture(&client, &args.organization, &args.project, args.include_values)?;
+    for warning in &warnings {
+        println!("{}", OutputFormatter::warning(warning));
+    }
+    snapshot.save(&args.output)?;
+
+    if args.verbose {
+        for group in &snapshot.variable_groups {
+            println!("       - group {} ({} variable(s))", group.name, group.variables.len());
+        }
+        for pipeline in &snapshot.pipelines {
+            println!("       - pipeline {} ({} variable(s))", pipeline.name, pipeline.variables.len());
+        }
+    }
+    println!(
+        "{}",
+        OutputFormatter::success(&format!(
+            "Snapshot of {} variable group(s) and {} pipeline(s) written to {}{}",
+            snapshot.variable_groups.len(),
+            snapshot.pipelines.len(),
+            args.output,
+            if snapshot.includes_values { " (with values)" } else { "" }
+        ))
+    );
+    Ok(())
+}
+
 /// Run the validation workflow and return whether any validation failures occurred
 fn run_validation(args: &Args) -> Result<bool, anyhow::Error> {
+    let pipeline_file = args
+        .pipeline_file
+        .as_deref()
+        .ok_or_else(|| anyhow::anyhow!("--pipeline-file is required"))?;
+
     println!("Azure DevOps Pipeline Validator");
     println!("================================");
     println!();
 
     // Parse the pipeline file
     if args.verbose {
-        println!("{}", OutputFormatter::info(&format!("Parsing pipeline file: {}", args.pipeline_file)));
+        println!("{}", OutputFormatter::info(&format!("Parsing pipeline file: {pipeline_file}")));
     }
 
     // Check if this is a template file
-    let template_info = detect_template(&args.pipeline_file)?;
+    let template_info = detect_template(pipeline_file)?;
     if template_info.is_template {
         println!(
             "{}",
@@ -122,10 +305,23 @@ fn run_validation(args: &Args) -> Result<bool, anyhow::Error> {
         return Ok(false); // Exit successfully, not a validation failure
     }
 
-    let pipeline = parse_pipeline_file(&args.pipeline_file)?;
+    let pipeline = parse_pipeline_file(pipeline_file)?;
 
-    // Extract variable groups from the pipeline (searches all levels: top, stage, job)
-    let variable_groups = pipeline.get_variable_groups();
+    // Expand the templates included by the pipeline (stages, jobs, steps and variables),
+    // keeping only the `${{ }}` branches that would be compiled with the given parameters
+    let expansion = expand_templates_with_parameters(pipeline_file, &parse_parameters(&args.parameters)?)?;
+    if args.verbose && !expansion.inactive.is_empty() {
+        println!(
+            "{}",
+            OutputFormatter::info(&format!("Skipping {} inactive template branch(es)", expansion.inactive.len()))
+        );
+        for path in &expansion.inactive {
+            println!("       - {path}");
+        }
+    }
+
+    // Extract variable groups from the pipeline and its templates (searches all levels: top, stage, job)
+    let variable_groups = expansion.variable_groups();
     if args.verbose {
         println!("{}", OutputFormatter::info(&format!("Found {} variable group(s) referenced", variable_groups.len())));
         for group in &variable_groups {
@@ -142,10 +338,10 @@ fn run_validation(args: &Args) -> Result<bool, anyhow::Error> {
         }
     }
 
-    // Extract variable references from the pipeline
+    // Variable references of the pipeline, with their locations, were extracted during expansion
     // (excludes PowerShell expressions, system variables, and runtime outputs)
-    let variable_references = extract_variable_references(&args.pipeline_file)?;
     if args.verbose {
+        let variable_references = extract_variable_references(pipeline_file)?;
         println!(
             "{}",
             OutputFormatter::info(&format!("Found {} variable reference(s) to validate", variable_references.len()))
@@ -155,23 +351,409 @@ fn run_validation(args: &Args) -> Result<bool, anyhow::Error> {
         }
     }
 
-    // Initialize Azure DevOps client with PAT authentication
-    let client = AzureDevOpsClient::new(
-        args.organization.clone(),
-        args.project.clone(),
-        args.pat.clone(),
-    )?;
+    let placeholder = Regex::new(&args.placeholder_pattern)
+        .map_err(|e| anyhow::anyhow!("Invalid --placeholder-pattern '{}': {e}", args.placeholder_pattern))?;
+
+    let mut queue_time_overrides = parse_overrides(&args.queue_time_variables)?;
+    if let Some(ref path) = args.set_file {
+        queue_time_overrides.extend(read_overrides_file(path)?);
+    }
+    if !queue_time_overrides.is_empty() && args.pipeline_id.is_none() && args.pipeline_name.is_none() {
+        anyhow::bail!("Checking queue-time variables (--set, --set-file) requires --pipeline-id or --pipeline-name");
+    }
+
+    // Validate against a snapshot when offline, otherwise initialize the Azure DevOps client
+    // with PAT authentication
+    let mut cache_statistics: Option<(Arc<CacheStatistics>, PathBuf)> = None;
+    let (backend, organization, project): (Box<dyn VariableBackend>, String, String) =
+        if let Some(ref path) = args.offline {
+            let snapshot = Snapshot::load(path)?;
+            for (given, recorded, what) in [
+                (&args.organization, &snapshot.organization, "organization"),
+                (&args.project, &snapshot.project, "project"),
+            ] {
+                if given.as_ref().is_some_and(|given| given != recorded) {
+                    println!(
+                        "{}",
+                        OutputFormatter::warning(&format!("Snapshot {path} was taken of {what} '{recorded}'"))
+                    );
+                }
+            }
+            if args.verbose {
+                println!(
+                    "{}",
+                    OutputFormatter::info(&format!(
+                        "Validating offline against snapshot {path} of {}/{}{}",
+                        snapshot.organization,
+                        snapshot.project,
+                        if snapshot.includes_values { "" } else { " (without values)" }
+                    ))
+                );
+            }
+            let (organization, project) = (snapshot.organization.clone(), snapshot.project.clone());
+            (Box::new(snapshot.into_backend()), organization, project)
+        } else {
+            let organization = args.organization.clone().unwrap_or_default();
+            let project = args.project.clone().unwrap_or_default();
+            let mut client = AzureDevOpsClient::new(organization.clone(), project.clone(), args.pat.clone())?
+                .with_concurrency(args.concurrency);
+            match args.cache_dir.clone().or_else(default_cache_dir) {
+                Some(dir) if !args.no_cache => {
+                    let cache = ResponseCache::new(dir, Duration::from_secs(args.cache_ttl)).with_refresh(args.refresh);
+                    cache_statistics = Some((cache.statistics(), cache.dir().to_path_buf()));
+                    client = client.with_cache(cache);
+                }
+                None if !args.no_cache && args.verbose => println!(
+                    "{}",
+                    OutputFormatter::info("No cache directory found (set --cache-dir); API responses are not cached")
+                ),
+                _ => {}
+            }
+            // All variable groups are listed once and looked up in memory by every phase
+            (Box::new(GroupIndex::new(client)), organization, project)
+        };
 
     // Fetch pipeline definition variables if pipeline ID or name provided
+    let pipeline_variables = fetch_pipeline_variables(args, backend.as_ref());
+    let pipeline_definition_vars: Vec<String> = pipeline_variables.iter().map(|v| v.name.clone()).collect();
+
+    // Every variable group of the project, for suggestions when something is missing
+    let project_groups_cell: OnceCell<Vec<VariableGroupData>> = OnceCell::new();
+    let project_groups = || {
+        project_groups_cell.get_or_init(|| match backend.list_variable_groups() {
+            Ok(groups) => groups,
+            Err(e) => {
+                println!(
+                    "{}",
+                    OutputFormatter::warning(&format!("Could not list the project's variable groups: {e}"))
+                );
+                Vec::new()
+            }
+        })
+    };
+    let project_variables_cell: OnceCell<Vec<(String, String)>> = OnceCell::new();
+    let project_variables = || project_variables_cell.get_or_init(|| group_variable_pairs(project_groups()));
+
+    println!("{}", OutputFormatter::section("Variable Groups"));
+
+    // Validate variable groups exist
+    let mut group_results = validate_variable_groups(variable_groups.clone(), backend.as_ref())?;
+    attach_group_locations(&mut group_results, &expansion.all_group_references());
+    if group_results.iter().any(|r| !r.exists) {
+        let names: Vec<String> = project_groups().iter().map(|g| g.name.clone()).collect();
+        attach_group_suggestions(&mut group_results, &names);
+    }
+
+    // Track counts for summary
+    let mut group_pass_count = 0;
+    let mut group_fail_count = 0;
+
+    // Print group validation results
+    for result in &group_results {
+        if result.exists {
+            group_pass_count += 1;
+            println!("{}", OutputFormatter::success(&format!("Variable group '{}' exists", result.group_name)));
+            if args.verbose {
+                print_locations(&result.locations);
+            }
+        } else {
+            group_fail_count += 1;
+            match &result.suggestion {
+                Some(suggestion) => println!(
+                    "{}",
+                    OutputFormatter::failure(&format!(
+                        "Variable group '{}' not found — did you mean '{suggestion}'?",
+                        result.group_name
+                    ))
+                ),
+                None => println!("{}", OutputFormatter::failure(&format!("Variable group '{}' not found", result.group_name))),
+            }
+            print_locations(&result.locations);
+            if let Some(ref error) = result.error {
+                if args.verbose {
+                    println!("         Error: {error}");
+                }
+            }
+            if result.suggestion.is_some() {
+                continue;
+            }
+            // Provide actionable suggestion
+            println!(
+                "         Suggestion: Create the variable group in Azure DevOps at:\n         https://dev.azure.com/{}/{}/_library?itemType=VariableGroups",
+                organization, project
+            );
+        }
+    }
+
+    if group_results.is_empty() {
+        println!("{}", OutputFormatter::info("No variable groups referenced in pipeline"));
+    }
+
+    println!("{}", OutputFormatter::section("Variable References"));
+
+    // Fetch the variables of all existing groups once for the pipeline and its templates
+    let group_variables = fetch_group_variable_details(&group_results, backend.as_ref());
+    let available_variables: Vec<(String, String)> =
+        group_variables.iter().map(|v| (v.name.clone(), v.group.clone())).collect();
+
+    // Validate each variable against the groups and inline variables visible in the scope
+    // (root, stage, job) where it is used, or against the pipeline definition
+    let mut variable_results = validate_scoped_variables_against_available(
+        &expansion.variable_references,
+        &expansion.scopes,
+        &available_variables,
+        &pipeline_definition_vars,
+    );
+    if variable_results.iter().any(|r| !r.exists) {
+        attach_unlinked_groups(&mut variable_results, project_variables(), &variable_groups);
+        attach_project_suggestions(&mut variable_results, project_variables(), &variable_groups);
+    }
+    attach_value_problems(&mut variable_results, &group_variables, &placeholder);
+
+    let (var_pass_count, var_fail_count) = print_variable_results(&variable_results, args);
+
+    if variable_results.is_empty() {
+        println!("{}", OutputFormatter::info("No variable references found in pipeline"));
+    }
+
+    // Validate output variable references against the steps that set them, across the
+    // pipeline and all of its templates. Dotted names that are regular variables are skipped.
+    let output_index = expansion.output_index();
+    let is_variable = |name: &str, chain: &[&Scope]| {
+        resolve_in_chain(name, chain, &available_variables, &pipeline_definition_vars).is_some()
+    };
+
+    let output_results = validate_output_references(
+        &expansion.output_references,
+        &expansion.scopes,
+        &output_index,
+        &is_variable,
+    );
+
+    let (output_pass_count, output_fail_count) = if output_results.is_empty() {
+        (0, 0)
+    } else {
+        println!("{}", OutputFormatter::section("Output Variables"));
+        print_output_results(&output_results, args)
+    };
+
+    // Report templates that could not be expanded
+    let mut template_pass_count = 0;
+    let mut template_fail_count = 0;
+
+    if !expansion.problems.is_empty() {
+        println!("{}", OutputFormatter::section("Template Expansion"));
+        for problem in &expansion.problems {
+            if problem.is_error() {
+                template_fail_count += 1;
+                println!("{}", OutputFormatter::failure(&problem.message));
+            } else {
+                println!("{}", OutputFormatter::warning(&problem.message));
+            }
+            println!("{}", OutputFormatter::location(&problem.reference.span));
+            if !problem.is_error() {
+                println!("         The template may be in a different repository or location.");
+            }
+        }
+    }
+
+    // Validate each expanded template in the context of the scope it is inserted into
+    for instance in &expansion.instances {
+        println!(
+            "{}",
+            OutputFormatter::section(&format!(
+                "Template: {} ({} in {})",
+                instance.reference.template_path,
+                instance.reference.kind.description(),
+                instance.inserted_into
+            ))
+        );
+        if args.verbose {
+            println!(
+                "{}",
+                OutputFormatter::info(&format!(
+                    "Resolved to {} (depth {}, included from {})",
+                    instance.resolved_path,
+                    instance.depth,
+                    instance.included_from.join(" -> ")
+                ))
+            );
+        }
+
+        // Parameters supplied by the reference must match the template's declarations
+        for finding in &instance.parameter_findings {
+            template_fail_count += 1;
+            println!("{}", OutputFormatter::failure(&finding.message));
+            println!("{}", OutputFormatter::location(&instance.reference.span));
+        }
+
+        let mut template_var_results = validate_scoped_variables_against_available(
+            &instance.variable_references,
+            &instance.scopes,
+            &available_variables,
+            &pipeline_definition_vars,
+        );
+        if template_var_results.iter().any(|r| !r.exists) {
+            attach_unlinked_groups(&mut template_var_results, project_variables(), &variable_groups);
+            attach_project_suggestions(&mut template_var_results, project_variables(), &variable_groups);
+        }
+        attach_value_problems(&mut template_var_results, &group_variables, &placeholder);
+
+        let template_output_results = validate_output_references(
+            &instance.output_references,
+            &instance.scopes,
+            &output_index,
+            &is_variable,
+        );
+
+        if template_var_results.is_empty() && template_output_results.is_empty() {
+            println!(
+                "{}",
+                OutputFormatter::info("No variable references found in template")
+            );
+            continue;
+        }
+
+        let (pass_count, fail_count) = print_variable_results(&template_var_results, args);
+        template_pass_count += pass_count;
+        template_fail_count += fail_count;
+
+        let (pass_count, fail_count) = print_output_results(&template_output_results, args);
+        template_pass_count += pass_count;
+        template_fail_count += fail_count;
+    }
+
+    // Queue-time values are only accepted for variables the pipeline settings let users
+    // override, and YAML definitions override them
+    let mut override_pass_count = 0;
+    let mut override_fail_count = 0;
+    if !queue_time_overrides.is_empty() {
+        println!("{}", OutputFormatter::section("Queue-Time Variables"));
+        for result in check_overrides(&queue_time_overrides, &expansion, &group_variables, &pipeline_variables) {
+            if result.is_accepted() {
+                override_pass_count += 1;
+                println!("{}", OutputFormatter::success(&result.message()));
+            } else {
+                override_fail_count += 1;
+                println!("{}", OutputFormatter::failure(&result.message()));
+            }
+        }
+    }
+
+    // Secrets are unavailable to conditions and template expressions, masked in display
+    // names, and not exposed to scripts unless mapped in `env:`
+    let mut secret_findings = find_secret_misuse(
+        &expansion.variable_references,
+        &expansion.environment_references,
+        &expansion.scopes,
+        &group_variables,
+        &pipeline_variables,
+    );
+    for instance in &expansion.instances {
+        secret_findings.extend(find_secret_misuse(
+            &instance.variable_references,
+            &instance.environment_references,
+            &instance.scopes,
+            &group_variables,
+            &pipeline_variables,
+        ));
+    }
+    let mut secret_fail_count = 0;
+    if !secret_findings.is_empty() {
+        println!("{}", OutputFormatter::section("Secret Variables"));
+        for finding in &secret_findings {
+            if finding.is_error() {
+                secret_fail_count += 1;
+                println!("{}", OutputFormatter::failure(&finding.message()));
+            } else {
+                println!("{}", OutputFormatter::warning(&finding.message()));
+            }
+            println!("{}", OutputFormatter::location(&finding.span));
+        }
+    }
+
+    // Explain which definition wins for variables defined in more than one place
+    let mut precedence_reports = diagnose_precedence(
+        &expansion.variable_references,
+        &expansion.scopes,
+        &group_variables,
+        &pipeline_variables,
+    );
+    for instance in &expansion.instances {
+        precedence_reports.extend(diagnose_precedence(
+            &instance.variable_references,
+            &instance.scopes,
+            &group_variables,
+            &pipeline_variables,
+        ));
+    }
+    if !precedence_reports.is_empty() {
+        println!("{}", OutputFormatter::section("Variable Precedence"));
+        print_precedence_reports(&precedence_reports, args);
+    }
+
+    // Report definitions nothing reads; they do not fail validation
+    let usage = VariableUsage::from_expansion(&expansion);
+    let unused_groups = find_unused_groups(&group_results, &available_variables, &usage);
+    let unused_variables = find_unused_variables(&expansion, &usage);
+    if !unused_groups.is_empty() || !unused_variables.is_empty() {
+        println!("{}", OutputFormatter::section("Unused Definitions"));
+        for group in &unused_groups {
+            println!(
+                "{}",
+                OutputFormatter::warning(&format!(
+                    "Variable group '{}' is linked but none of its variables is used",
+                    group.name
+                ))
+            );
+            print_locations(&group.locations);
+        }
+        for variable in &unused_variables {
+            println!(
+                "{}",
+                OutputFormatter::warning(&format!("Variable '{}' is defined but never used", variable.name))
+            );
+            print_locations(&variable.locations);
+        }
+    }
+
+    // Calculate totals
+    let total_passed =
+        group_pass_count + var_pass_count + output_pass_count + template_pass_count + override_pass_count;
+    let total_failed = group_fail_count
+        + var_fail_count
+        + output_fail_count
+        + template_fail_count
+        + secret_fail_count
+        + override_fail_count;
+
+    if let (true, Some((statistics, dir))) = (args.verbose, &cache_statistics) {
+        println!();
+        println!(
+            "{}",
+            OutputFormatter::info(&format!("API cache: {} ({})", statistics.summary(), dir.display()))
+        );
+    }
+
+    // Print summary using OutputFormatter
+    println!("{}", OutputFormatter::summary(total_passed, total_failed));
+
+    Ok(total_failed > 0)
+}
+
+/// Fetch the variables of the pipeline definition given by --pipeline-id or --pipeline-name
+///
+/// A pipeline that cannot be fetched is reported as a warning and yields no variables.
+fn fetch_pipeline_variables(args: &Args, backend: &dyn VariableBackend) -> Vec<PipelineVariable> {
     // Prefer pipeline_id over pipeline_name as it's more reliable
-    let pipeline_definition_vars: Vec<String> = if let Some(pipeline_id) = args.pipeline_id {
+    if let Some(pipeline_id) = args.pipeline_id {
         if args.verbose {
             println!(
                 "{}",
                 OutputFormatter::info(&format!("Fetching variables from pipeline ID: {pipeline_id}"))
             );
         }
-        match client.get_pipeline_variable_names_by_id(pipeline_id) {
+        match backend.get_pipeline_variables_by_id(pipeline_id).map(pipeline_variables_from) {
             Ok(vars) => {
                 if args.verbose {
                     println!(
@@ -182,7 +764,7 @@ fn run_validation(args: &Args) -> Result<bool, anyhow::Error> {
                         ))
                     );
                     for var in &vars {
-                        println!("       - {var}");
+                        println!("       - {}", var.name);
                     }
                 }
                 vars
@@ -203,7 +785,7 @@ fn run_validation(args: &Args) -> Result<bool, anyhow::Error> {
                 OutputFormatter::info(&format!("Fetching variables from pipeline: {pipeline_name}"))
             );
         }
-        match client.get_pipeline_variable_names(pipeline_name) {
+        match backend.get_pipeline_variables(pipeline_name).map(pipeline_variables_from) {
             Ok(vars) => {
                 if args.verbose {
                     println!(
@@ -214,7 +796,7 @@ fn run_validation(args: &Args) -> Result<bool, anyhow::Error> {
                         ))
                     );
                     for var in &vars {
-                        println!("       - {var}");
+                        println!("       - {}", var.name);
                     }
                 }
                 vars
@@ -230,96 +812,142 @@ fn run_validation(args: &Args) -> Result<bool, anyhow::Error> {
         }
     } else {
         Vec::new()
-    };
-
-    println!("{}", OutputFormatter::section("Variable Groups"));
-
-    // Validate variable groups exist
-    let group_results = validate_variable_groups(variable_groups, &client)?;
+    }
+}
 
-    // Track counts for summary
-    let mut group_pass_count = 0;
-    let mut group_fail_count = 0;
+/// Print variable validation results and return the number of passed and failed checks
+fn print_variable_results(results: &[VariableValidationResult], args: &Args) -> (usize, usize) {
+    let mut pass_count = 0;
+    let mut fail_count = 0;
 
-    // Print group validation results
-    for result in &group_results {
-        if result.exists {
-            group_pass_count += 1;
-            println!("{}", OutputFormatter::success(&format!("Variable group '{}' exists", result.group_name)));
-        } else {
-            group_fail_count += 1;
-            println!("{}", OutputFormatter::failure(&format!("Variable group '{}' not found", result.group_name)));
-            if let Some(ref error) = result.error {
-                if args.verbose {
-                    println!("         Error: {error}");
-                }
-            }
-            // Provide actionable suggestion
+    for result in results {
+        let used_in = scope_suffix(result.scope.as_deref());
+        let casing_mismatch = result.canonical_name.as_ref().filter(|_| result.exists).map(|canonical| {
+            format!(
+                "Variable '{}' is defined as '{canonical}'; Azure DevOps ignores case, but the reference should use the same spelling",
+                result.variable_name
+            )
+        });
+        if let (Some(message), CasingMismatch::Error) = (&casing_mismatch, args.casing_mismatch) {
+            fail_count += 1;
+            println!("{}", OutputFormatter::failure(&format!("{message}{used_in}")));
+            print_locations(&result.locations);
+            continue;
+        }
+        if let (true, Some(problem), VariableSource::Group(group)) =
+            (result.exists, &result.value_problem, &result.source)
+        {
+            fail_count += 1;
             println!(
-                "         Suggestion: Create the variable group in Azure DevOps at:\n         https://dev.azure.com/{}/{}/_library?itemType=VariableGroups",
-                args.organization, args.project
+                "{}",
+                OutputFormatter::failure(&format!(
+                    "Variable '{}' in group '{group}' has {problem}{used_in}",
+                    result.variable_name
+                ))
             );
+            print_locations(&result.locations);
+            continue;
         }
-    }
-
-    if group_results.is_empty() {
-        println!("{}", OutputFormatter::info("No variable groups referenced in pipeline"));
-    }
-
-    println!("{}", OutputFormatter::section("Variable References"));
-
-    // Validate variables exist in groups, are defined inline, or are on the pipeline definition
-    let variable_results = validate_variables(
-        variable_references,
-        &group_results,
-        &inline_variables,
-        &pipeline_definition_vars,
-        &client,
-    )?;
-
-    // Track counts for summary
-    let mut var_pass_count = 0;
-    let mut var_fail_count = 0;
-
-    // Print variable validation results
-    for result in &variable_results {
         if result.exists {
-            var_pass_count += 1;
+            pass_count += 1;
             match &result.source {
                 VariableSource::Group(group_name) => {
                     println!(
                         "{}",
-                        OutputFormatter::success(&format!("Variable '{}' found in group '{}'", result.variable_name, group_name))
+                        OutputFormatter::success(&format!("Variable '{}' found in group '{}'{used_in}", result.variable_name, group_name))
                     );
                 }
                 VariableSource::Inline => {
                     println!(
                         "{}",
-                        OutputFormatter::success(&format!("Variable '{}' defined inline in pipeline", result.variable_name))
+                        OutputFormatter::success(&format!("Variable '{}' defined inline in pipeline{used_in}", result.variable_name))
                     );
                 }
                 VariableSource::PipelineDefinition => {
                     println!(
                         "{}",
-                        OutputFormatter::success(&format!("Variable '{}' defined on pipeline", result.variable_name))
+                        OutputFormatter::success(&format!("Variable '{}' defined on pipeline{used_in}", result.variable_name))
+                    );
+                }
+                VariableSource::Matrix { job } => {
+                    println!(
+                        "{}",
+                        OutputFormatter::success(&format!("Variable '{}' defined by the matrix of {job}{used_in}", result.variable_name))
+                    );
+                }
+                VariableSource::RuntimeSet { step } => {
+                    println!(
+                        "{}",
+                        OutputFormatter::success(&format!("Variable '{}' set at runtime by {step}{used_in}", result.variable_name))
                     );
                 }
                 VariableSource::NotFound => {
                     // This shouldn't happen if exists is true, but handle it gracefully
-                    println!("{}", OutputFormatter::success(&format!("Variable '{}' found", result.variable_name)));
+                    println!("{}", OutputFormatter::success(&format!("Variable '{}' found{used_in}", result.variable_name)));
+                }
+            }
+            if args.verbose {
+                print_locations(&result.locations);
+            }
+            let casing_warning = casing_mismatch.filter(|_| args.casing_mismatch == CasingMismatch::Warn);
+            for warning in result.warnings.iter().chain(casing_warning.as_ref()) {
+                println!("{}", OutputFormatter::warning(warning));
+                if !args.verbose {
+                    print_locations(&result.locations);
                 }
             }
         } else {
-            var_fail_count += 1;
-            println!(
-                "{}",
-                OutputFormatter::failure(&format!("Variable '{}' not found in any referenced group", result.variable_name))
-            );
+            fail_count += 1;
+            if let Some((source, name)) = &result.suggestion {
+                println!(
+                    "{}",
+                    OutputFormatter::failure(&format!(
+                        "Variable '{}' not found — did you mean {}?{used_in}",
+                        result.variable_name,
+                        describe_suggestion(name, source, result.suggestion_unlinked)
+                    ))
+                );
+            } else if result.defined_in.is_empty() {
+                println!(
+                    "{}",
+                    OutputFormatter::failure(&format!("Variable '{}' not found in any referenced group{used_in}", result.variable_name))
+                );
+            } else {
+                println!(
+                    "{}",
+                    OutputFormatter::failure(&format!("Variable '{}' is not visible{used_in}", result.variable_name))
+                );
+            }
+            print_locations(&result.locations);
+            if !result.unlinked_groups.is_empty() {
+                let groups: Vec<String> = result.unlinked_groups.iter().map(|g| format!("'{g}'")).collect();
+                let (noun, verb) = if groups.len() == 1 { ("group", "is") } else { ("groups", "are") };
+                println!(
+                    "         Variable '{}' exists in {noun} {} which {verb} not linked in this scope",
+                    result.variable_name,
+                    groups.join(", ")
+                );
+            }
+            if !result.defined_in.is_empty() {
+                for definition in &result.defined_in {
+                    println!("         Defined in {definition}");
+                }
+                println!("         Suggestion: Link the group or define the variable in a scope that encloses this usage");
+                println!("         (pipeline root, the same stage, or the same job).");
+                continue;
+            }
             if let Some(ref error) = result.error {
                 if args.verbose {
                     println!("         Error: {error}");
                 }
             }
+            if !result.unlinked_groups.is_empty() {
+                println!("         Suggestion: Link one of these groups in a scope that encloses this usage.");
+                continue;
+            }
+            if result.suggestion.is_some() {
+                continue;
+            }
             // Provide actionable suggestion
             println!("         Suggestion: Add this variable to one of the referenced variable groups,");
             println!("         define it inline in the pipeline YAML, or add it to the pipeline definition.");
@@ -329,171 +957,102 @@ fn run_validation(args: &Args) -> Result<bool, anyhow::Error> {
         }
     }
 
-    if variable_results.is_empty() {
-        println!("{}", OutputFormatter::info("No variable references found in pipeline"));
-    }
-
-    // Validate templates referenced in the pipeline
-    let template_refs = extract_template_references(&args.pipeline_file)?;
-    let mut template_pass_count = 0;
-    let mut template_fail_count = 0;
-
-    if !template_refs.is_empty() {
-        for template_ref in &template_refs {
-            let resolved_path = resolve_template_path(&args.pipeline_file, &template_ref.template_path);
-
-            // Build section header
-            let stage_info = template_ref
-                .stage_name
-                .as_ref()
-                .map(|s| format!(" (stage: {s})"))
-                .unwrap_or_default();
-            let groups_info = if template_ref.available_groups.is_empty() {
-                String::new()
-            } else {
-                format!(", groups: {}", template_ref.available_groups.join(", "))
-            };
-
-            println!(
-                "{}",
-                OutputFormatter::section(&format!(
-                    "Template: {}{}{}",
-                    template_ref.template_path, stage_info, groups_info
-                ))
-            );
+    (pass_count, fail_count)
+}
 
-            // Check if template file exists
-            if !std::path::Path::new(&resolved_path).exists() {
-                println!(
-                    "{}",
-                    OutputFormatter::warning(&format!(
-                        "Template file not found: {} (resolved to: {})",
-                        template_ref.template_path, resolved_path
-                    ))
-                );
-                println!("         The template may be in a different repository or location.");
-                continue;
+/// Print the definition sites of shadowed variables; conflicts are warnings and never fail
+fn print_precedence_reports(reports: &[PrecedenceReport], args: &Args) {
+    for report in reports {
+        println!(
+            "{}",
+            OutputFormatter::info(&format!("{} (used in {})", report.explanation(), report.scope))
+        );
+        if args.verbose {
+            for site in report.sites.iter().rev() {
+                match &site.value {
+                    Some(value) => println!("         - {} = '{value}'", site.describe()),
+                    None => println!("         - {}", site.describe()),
+                }
             }
+        }
+        for warning in &report.warnings {
+            println!("{}", OutputFormatter::warning(warning));
+        }
+        if args.verbose || !report.warnings.is_empty() {
+            print_locations(&report.locations);
+        }
+    }
+}
 
-            // Read and extract variable references from template
-            let template_content = std::fs::read_to_string(&resolved_path)?;
-            let template_var_refs = extract_variable_references_from_content(&template_content)?;
+/// Print output variable validation results and return the number of passed and failed checks
+fn print_output_results(results: &[OutputValidationResult], args: &Args) -> (usize, usize) {
+    let mut pass_count = 0;
+    let mut fail_count = 0;
 
-            if template_var_refs.is_empty() {
-                println!(
-                    "{}",
-                    OutputFormatter::info("No variable references found in template")
-                );
-                continue;
+    for result in results {
+        let used_in = scope_suffix(Some(&result.scope));
+        match &result.finding {
+            None => {
+                pass_count += 1;
+                let message = match &result.producer {
+                    Some(producer) => format!("Output variable '{}' set by {producer}{used_in}", result.reference),
+                    None => format!("Output variable '{}' could not be resolved statically{used_in}", result.reference),
+                };
+                println!("{}", OutputFormatter::success(&message));
+                if args.verbose {
+                    print_locations(&result.locations);
+                }
             }
-
-            if args.verbose {
-                println!(
-                    "{}",
-                    OutputFormatter::info(&format!(
-                        "Found {} variable reference(s) in template",
-                        template_var_refs.len()
-                    ))
-                );
+            Some(finding) => {
+                fail_count += 1;
+                println!("{}", OutputFormatter::failure(&finding.message));
+                print_locations(&result.locations);
             }
+        }
+    }
 
-            // Validate template's variable groups exist (filter to only those we haven't validated yet)
-            let new_groups: Vec<String> = template_ref
-                .available_groups
-                .iter()
-                .filter(|g| !group_results.iter().any(|r| &r.group_name == *g))
-                .cloned()
-                .collect();
+    (pass_count, fail_count)
+}
 
-            let template_group_results = if !new_groups.is_empty() {
-                validate_variable_groups(new_groups, &client)?
-            } else {
-                Vec::new()
-            };
-
-            // Combine all group results for validation
-            let all_group_results: Vec<_> = group_results
-                .iter()
-                .chain(template_group_results.iter())
-                .filter(|r| template_ref.available_groups.contains(&r.group_name))
-                .cloned()
-                .collect();
-
-            // Validate template variables
-            let template_var_results = validate_variables(
-                template_var_refs,
-                &all_group_results,
-                &template_ref.available_inline_vars,
-                &pipeline_definition_vars,
-                &client,
-            )?;
-
-            // Print template variable validation results
-            for result in &template_var_results {
-                if result.exists {
-                    template_pass_count += 1;
-                    match &result.source {
-                        VariableSource::Group(group_name) => {
-                            println!(
-                                "{}",
-                                OutputFormatter::success(&format!(
-                                    "Variable '{}' found in group '{}'",
-                                    result.variable_name, group_name
-                                ))
-                            );
-                        }
-                        VariableSource::Inline => {
-                            println!(
-                                "{}",
-                                OutputFormatter::success(&format!(
-                                    "Variable '{}' defined inline in parent pipeline",
-                                    result.variable_name
-                                ))
-                            );
-                        }
-                        VariableSource::PipelineDefinition => {
-                            println!(
-                                "{}",
-                                OutputFormatter::success(&format!(
-                                    "Variable '{}' defined on pipeline",
-                                    result.variable_name
-                                ))
-                            );
-                        }
-                        VariableSource::NotFound => {
-                            println!(
-                                "{}",
-                                OutputFormatter::success(&format!("Variable '{}' found", result.variable_name))
-                            );
-                        }
-                    }
-                } else {
-                    template_fail_count += 1;
-                    println!(
-                        "{}",
-                        OutputFormatter::failure(&format!(
-                            "Variable '{}' not found in available groups",
-                            result.variable_name
-                        ))
-                    );
-                    if !template_ref.available_groups.is_empty() {
-                        println!(
-                            "         Available groups: {}",
-                            template_ref.available_groups.join(", ")
-                        );
-                    }
-                    println!("         Suggestion: Add this variable to one of the available variable groups.");
-                }
-            }
-        }
+/// Parse `NAME=VALUE` runtime parameter arguments
+///
+/// Values are read as YAML scalars, so `true` and `3` become a boolean and a number.
+fn parse_parameters(arguments: &[String]) -> Result<serde_yaml::Mapping, anyhow::Error> {
+    let mut parameters = serde_yaml::Mapping::new();
+    for argument in arguments {
+        let Some((name, value)) = argument.split_once('=') else {
+            anyhow::bail!("Invalid parameter '{argument}', expected NAME=VALUE");
+        };
+        let value = serde_yaml::from_str(value).unwrap_or_else(|_| serde_yaml::Value::String(value.to_string()));
+        parameters.insert(serde_yaml::Value::String(name.trim().to_string()), value);
     }
+    Ok(parameters)
+}
 
-    // Calculate totals
-    let total_passed = group_pass_count + var_pass_count + template_pass_count;
-    let total_failed = group_fail_count + var_fail_count + template_fail_count;
+/// Print the source locations of a reference, one per line
+fn print_locations(locations: &[SourceSpan]) {
+    for location in locations {
+        println!("{}", OutputFormatter::location(location));
+    }
+}
 
-    // Print summary using OutputFormatter
-    println!("{}", OutputFormatter::summary(total_passed, total_failed));
+/// Describe a suggested variable and where it is defined, e.g. `'ApiKey' in group 'app-secrets'`
+fn describe_suggestion(name: &str, source: &VariableSource, unlinked: bool) -> String {
+    match source {
+        VariableSource::Group(group) if unlinked => format!("'{name}' in group '{group}' (not linked)"),
+        VariableSource::Group(group) => format!("'{name}' in group '{group}'"),
+        VariableSource::Inline => format!("'{name}' defined inline"),
+        VariableSource::PipelineDefinition => format!("'{name}' defined on the pipeline"),
+        VariableSource::Matrix { job } => format!("'{name}' from the matrix of {job}"),
+        VariableSource::RuntimeSet { step } => format!("'{name}' set by {step}"),
+        VariableSource::NotFound => format!("'{name}'"),
+    }
+}
 
-    Ok(total_failed > 0)
+/// Describe the scope a variable is used in, omitted for the pipeline root
+fn scope_suffix(scope: Option<&str>) -> String {
+    match scope {
+        Some(label) if label != ROOT_LABEL => format!(" (used in {label})"),
+        _ => String::new(),
+    }
 }
@@ -0,0 +1,561 @@
+//! Output variables passed between steps, jobs and stages
+//!
+//! A step publishes an output variable with `##vso[task.setvariable variable=X;isOutput=true]`
+//! and is addressed through its `name:`. Later steps of the same job read it as `$(step.X)`,
+//! other jobs of the stage as `dependencies.Job.outputs['step.X']` and jobs of later stages
+//! as `stageDependencies.Stage.Job.outputs['step.X']`. Azure DevOps silently resolves a
+//! misspelled reference to an empty string, so every reference is checked against the
+//! stages, jobs and steps of the expanded pipeline and the `dependsOn` of its consumer.
+
+use crate::parser::{variable_names_match, OutputForm, OutputReference};
+use crate::scope::{Scope, ScopeKind, ScopeTree};
+use crate::span::SourceSpan;
+
+/// Kind of output variable finding
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFindingKind {
+    /// The referenced stage does not exist
+    UnknownStage,
+    /// The referenced job does not exist in the stage
+    UnknownJob,
+    /// No step of the job has the referenced `name:`
+    UnknownStep,
+    /// The step does not set the referenced variable
+    UnknownVariable,
+    /// The step sets the variable without `isOutput=true`
+    NotOutput,
+    /// The consuming job or stage does not depend on the producer
+    MissingDependency,
+}
+
+/// A problem with an output variable reference
+#[derive(Debug, Clone)]
+pub struct OutputFinding {
+    /// Kind of finding
+    pub kind: OutputFindingKind,
+    /// Description of the finding
+    pub message: String,
+}
+
+/// Result of validating an output variable reference
+#[derive(Debug, Clone)]
+pub struct OutputValidationResult {
+    /// The reference as written, e.g. `setVars.version`
+    pub reference: String,
+    /// Scope in which the reference is used
+    pub scope: String,
+    /// Label of the step that sets the variable (if it could be identified)
+    pub producer: Option<String>,
+    /// Problem with the reference (`None` if it resolves)
+    pub finding: Option<OutputFinding>,
+    /// Locations where the reference is used
+    pub locations: Vec<SourceSpan>,
+}
+
+/// A stage, job or step together with the names of the stage and job enclosing it
+struct Entry<'a> {
+    scope: &'a Scope,
+    stage: Option<&'a str>,
+    job: Option<&'a str>,
+    /// Stages or jobs this one depends on (`None` if they cannot be determined)
+    dependencies: Option<Vec<String>>,
+}
+
+/// Outcome of looking up a stage, job or step by name
+enum Lookup<'i, 'a> {
+    Found(&'i Entry<'a>),
+    /// Not found, but a candidate has a `${{ }}` name that may match after compilation
+    Unknown,
+    Missing,
+}
+
+/// Stages, jobs and steps of a pipeline and all of its templates
+pub struct OutputIndex<'a> {
+    entries: Vec<Entry<'a>>,
+}
+
+impl<'a> OutputIndex<'a> {
+    /// Index the scopes of a pipeline and its expanded templates
+    ///
+    /// A stage without `dependsOn` depends on the stage before it. For the first stage of
+    /// a stage template that stage is not known, so its dependencies are not checked.
+    ///
+    /// # Arguments
+    /// * `pipeline` - Scope tree of the pipeline
+    /// * `templates` - Scope trees of the expanded templates
+    pub fn new(pipeline: &'a ScopeTree, templates: &[&'a ScopeTree]) -> Self {
+        let mut entries = Vec::new();
+        let trees = std::iter::once((pipeline, true)).chain(templates.iter().map(|tree| (*tree, false)));
+
+        for (tree, is_pipeline) in trees {
+            let mut previous_stage: Option<Option<&str>> = None;
+            for scope in tree.scopes() {
+                let chain = tree.chain(&scope.path);
+                let enclosing = &chain[..chain.len().saturating_sub(1)];
+                let dependencies = match scope.kind {
+                    ScopeKind::Stage => {
+                        let dependencies = match (&scope.depends_on, previous_stage) {
+                            (Some(explicit), _) => Some(explicit.clone()),
+                            (None, Some(previous)) => previous.map(|p| vec![p.to_string()]),
+                            (None, None) if is_pipeline => Some(Vec::new()),
+                            (None, None) => None,
+                        };
+                        previous_stage = Some(scope.name.as_deref());
+                        dependencies
+                    }
+                    ScopeKind::Job => Some(scope.depends_on.clone().unwrap_or_default()),
+                    _ => None,
+                };
+
+                entries.push(Entry {
+                    scope,
+                    stage: innermost_name(enclosing, ScopeKind::Stage),
+                    job: innermost_name(enclosing, ScopeKind::Job),
+                    dependencies,
+                });
+            }
+        }
+
+        Self { entries }
+    }
+
+    /// Find a stage, job or step by name within its enclosing stage and job
+    ///
+    /// Names are matched case-insensitively, as Azure DevOps does.
+    fn find(&self, kind: ScopeKind, stage: Option<&str>, job: Option<&str>, name: &str) -> Lookup<'_, 'a> {
+        let candidates: Vec<&Entry> = self
+            .entries
+            .iter()
+            .filter(|entry| entry.scope.kind == kind)
+            .filter(|entry| kind == ScopeKind::Stage || names_match(entry.stage, stage))
+            .filter(|entry| kind != ScopeKind::Step || names_match(entry.job, job))
+            .collect();
+
+        if let Some(entry) = candidates
+            .iter()
+            .find(|entry| names_match(entry.scope.name.as_deref(), Some(name)))
+        {
+            return Lookup::Found(entry);
+        }
+        if candidates
+            .iter()
+            .any(|entry| entry.scope.name.as_deref().is_some_and(|n| n.contains("${{")))
+        {
+            return Lookup::Unknown;
+        }
+        Lookup::Missing
+    }
+
+    /// Dependencies of a stage, looked up by name
+    fn stage_dependencies(&self, stage: Option<&str>) -> Option<Vec<String>> {
+        match self.find(ScopeKind::Stage, None, None, stage?) {
+            Lookup::Found(entry) => entry.dependencies.clone(),
+            _ => None,
+        }
+    }
+
+    /// Resolve an output reference used where `chain` is visible
+    ///
+    /// Returns `None` for references that are not checked: dotted names used outside a job
+    /// or defined as regular variables, and dependency lookups at the pipeline root.
+    fn resolve(
+        &self,
+        reference: &OutputReference,
+        chain: &[&Scope],
+        is_variable: &dyn Fn(&str, &[&Scope]) -> bool,
+    ) -> Option<Result<Option<String>, OutputFinding>> {
+        let stage = chain.iter().rev().find(|scope| scope.kind == ScopeKind::Stage);
+        let job = chain.iter().rev().find(|scope| scope.kind == ScopeKind::Job);
+        let in_job = job.is_some() || chain.iter().any(|scope| scope.kind == ScopeKind::Step);
+        let stage_name = stage.and_then(|scope| scope.name.as_deref());
+        let job_name = job.and_then(|scope| scope.name.as_deref());
+        let stage_label = stage.map_or("the pipeline", |scope| scope.label.as_str());
+        let text = &reference.text;
+
+        let result = match &reference.form {
+            OutputForm::Step { step, variable } => {
+                if !in_job || is_variable(text, chain) {
+                    return None;
+                }
+                let job_label = job.map_or("the job", |scope| scope.label.as_str());
+                self.step_output(text, stage_name, job_name, job_label, step, variable)
+            }
+            OutputForm::Dependencies { name, outputs } if in_job => {
+                self.job_output(text, stage_name, stage_label, name, outputs)
+                    .and_then(|producer| {
+                        let dependencies = job.map(|scope| scope.depends_on.clone().unwrap_or_default());
+                        check_dependency(text, dependencies, name, "job", job.map(|scope| scope.label.as_str()))
+                            .map(|_| producer)
+                    })
+            }
+            OutputForm::Dependencies { name, outputs } if stage.is_some() => {
+                let (job, outputs) = outputs.split_once('.').unwrap_or((outputs, ""));
+                self.stage_output(text, name, job, outputs).and_then(|producer| {
+                    check_dependency(text, self.stage_dependencies(stage_name), name, "stage", Some(stage_label))
+                        .map(|_| producer)
+                })
+            }
+            OutputForm::StageDependencies { stage: producer_stage, job, outputs } if stage.is_some() => {
+                self.stage_output(text, producer_stage, job, outputs).and_then(|producer| {
+                    check_dependency(
+                        text,
+                        self.stage_dependencies(stage_name),
+                        producer_stage,
+                        "stage",
+                        Some(stage_label),
+                    )
+                    .map(|_| producer)
+                })
+            }
+            _ => return None,
+        };
+
+        Some(result)
+    }
+
+    /// Resolve the outputs of a job in another stage
+    fn stage_output(&self, text: &str, stage: &str, job: &str, outputs: &str) -> Result<Option<String>, OutputFinding> {
+        match self.find(ScopeKind::Stage, None, None, stage) {
+            Lookup::Found(entry) => self.job_output(text, Some(stage), &entry.scope.label, job, outputs),
+            Lookup::Unknown => Ok(None),
+            Lookup::Missing => Err(OutputFinding {
+                kind: OutputFindingKind::UnknownStage,
+                message: format!("'{text}' refers to stage '{stage}', which does not exist"),
+            }),
+        }
+    }
+
+    /// Resolve the `outputs['...']` key of a job within a stage
+    ///
+    /// Regular jobs publish `step.variable`. Deployment jobs prefix it with the job name
+    /// (`runOnce`) or the lifecycle hook and resource (`Deploy_vm1`), e.g. `Web.step.variable`.
+    fn job_output(
+        &self,
+        text: &str,
+        stage: Option<&str>,
+        stage_label: &str,
+        job: &str,
+        outputs: &str,
+    ) -> Result<Option<String>, OutputFinding> {
+        match self.find(ScopeKind::Job, stage, None, job) {
+            Lookup::Found(entry) => {
+                let label = &entry.scope.label;
+                let key = if entry.scope.deployment {
+                    outputs.split_once('.').map(|(_, key)| key)
+                } else {
+                    Some(outputs)
+                };
+                let Some((step, variable)) = key.and_then(|key| key.split_once('.')) else {
+                    let expected = if entry.scope.deployment {
+                        format!("'{job}.<step>.<variable>' for {label}")
+                    } else {
+                        format!("'<step>.<variable>' for {label}")
+                    };
+                    return Err(OutputFinding {
+                        kind: OutputFindingKind::UnknownStep,
+                        message: format!("'{text}' does not name a step; output variables are read as {expected}"),
+                    });
+                };
+                self.step_output(text, stage, Some(job), label, step, variable)
+            }
+            Lookup::Unknown => Ok(None),
+            Lookup::Missing => Err(OutputFinding {
+                kind: OutputFindingKind::UnknownJob,
+                message: format!("'{text}' refers to job '{job}', which does not exist in {stage_label}"),
+            }),
+        }
+    }
+
+    /// Resolve `step.variable` within a job
+    fn step_output(
+        &self,
+        text: &str,
+        stage: Option<&str>,
+        job: Option<&str>,
+        job_label: &str,
+        step: &str,
+        variable: &str,
+    ) -> Result<Option<String>, OutputFinding> {
+        let entry = match self.find(ScopeKind::Step, stage, job, step) {
+            Lookup::Found(entry) => entry,
+            Lookup::Unknown => return Ok(None),
+            Lookup::Missing => {
+                return Err(OutputFinding {
+                    kind: OutputFindingKind::UnknownStep,
+                    message: format!("'{text}' refers to step '{step}', but no step in {job_label} has that name"),
+                })
+            }
+        };
+
+        let scope = entry.scope;
+        let set: Vec<_> = scope.set_variables.iter().filter(|v| variable_names_match(&v.name, variable)).collect();
+        if set.iter().any(|v| v.is_output) || (set.is_empty() && scope.dynamic_variables) {
+            return Ok(Some(scope.label.clone()));
+        }
+
+        Err(if set.is_empty() {
+            OutputFinding {
+                kind: OutputFindingKind::UnknownVariable,
+                message: format!(
+                    "'{text}' refers to '{variable}', but {} does not set it as an output variable",
+                    scope.label
+                ),
+            }
+        } else {
+            OutputFinding {
+                kind: OutputFindingKind::NotOutput,
+                message: format!(
+                    "'{text}' refers to '{variable}', but {} sets it without isOutput=true",
+                    scope.label
+                ),
+            }
+        })
+    }
+}
+
+/// Validate output variable references against the stages, jobs and steps that set them
+///
+/// References are grouped per reference text and scope, so the same reference used in two
+/// jobs yields two results.
+///
+/// # Arguments
+/// * `references` - Output variable references of one document, with locations
+/// * `scopes` - Scope tree of that document
+/// * `index` - Stages, jobs and steps of the whole pipeline
+/// * `is_variable` - Whether a dotted name is a regular variable visible in a scope chain
+///   (variable names may contain dots), in which case it is not an output reference
+///
+/// # Returns
+/// * `Vec<OutputValidationResult>` - Validation results for each reference and scope
+pub fn validate_output_references(
+    references: &[OutputReference],
+    scopes: &ScopeTree,
+    index: &OutputIndex,
+    is_variable: &dyn Fn(&str, &[&Scope]) -> bool,
+) -> Vec<OutputValidationResult> {
+    let mut results: Vec<(String, OutputValidationResult)> = Vec::new();
+
+    for reference in references {
+        let chain = scopes.chain(&reference.span.yaml_path);
+        let Some(resolution) = index.resolve(reference, &chain, is_variable) else {
+            continue;
+        };
+
+        let scope = scopes.variable_scope(&reference.span.yaml_path);
+        let key = format!("{}@{}", reference.text, scope.path);
+        if let Some((_, result)) = results.iter_mut().find(|(k, _)| *k == key) {
+            result.locations.push(reference.span.clone());
+            continue;
+        }
+
+        let (producer, finding) = match resolution {
+            Ok(producer) => (producer, None),
+            Err(finding) => (None, Some(finding)),
+        };
+        results.push((
+            key,
+            OutputValidationResult {
+                reference: reference.text.clone(),
+                scope: scope.label.clone(),
+                producer,
+                finding,
+                locations: vec![reference.span.clone()],
+            },
+        ));
+    }
+
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+/// Whether two optional stage, job or step names are equal, ignoring case
+fn names_match(a: Option<&str>, b: Option<&str>) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => a.eq_ignore_ascii_case(b),
+        (a, b) => a == b,
+    }
+}
+
+/// Name of the innermost scope of a kind in a chain
+fn innermost_name<'a>(chain: &[&'a Scope], kind: ScopeKind) -> Option<&'a str> {
+    chain
+        .iter()
+        .rev()
+        .find(|scope| scope.kind == kind)
+        .and_then(|scope| scope.name.as_deref())
+}
+
+/// Check that a consumer depends on the job or stage producing an output variable
+///
+/// Dependencies that are unknown or computed by template expressions are not checked.
+fn check_dependency(
+    text: &str,
+    dependencies: Option<Vec<String>>,
+    producer: &str,
+    producer_kind: &str,
+    consumer: Option<&str>,
+) -> Result<(), OutputFinding> {
+    let (Some(dependencies), Some(consumer)) = (dependencies, consumer) else {
+        return Ok(());
+    };
+    if dependencies.iter().any(|d| d.eq_ignore_ascii_case(producer) || d.contains("${{")) {
+        return Ok(());
+    }
+
+    Err(OutputFinding {
+        kind: OutputFindingKind::MissingDependency,
+        message: format!(
+            "'{text}' is used in {consumer}, which does not depend on {producer_kind} '{producer}'; add it to dependsOn"
+        ),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::extract_output_reference_spans_from_content;
+    use serde_yaml::Value;
+
+    const PIPELINE: &str = "
+stages:
+  - stage: Build
+    jobs:
+      - job: Compile
+        steps:
+          - script: |
+              echo \"##vso[task.setvariable variable=version;isOutput=true]1.0\"
+              echo \"##vso[task.setvariable variable=local]x\"
+            name: setVars
+          - script: echo $(setVars.version) $(setVars.verison) $(setVar.version) $(setVars.local)
+          - task: AzureCLI@2
+            name: cli
+          - script: echo $(cli.anything) $(SetVars.version)
+      - job: Lint
+        dependsOn: compile
+        variables:
+          version: $[ dependencies.compile.outputs['SETVARS.version'] ]
+        steps:
+          - script: echo $(version)
+      - job: Test
+        dependsOn: Compile
+        variables:
+          version: $[ dependencies.Compile.outputs['setVars.version'] ]
+        steps:
+          - script: echo $(version)
+      - job: Package
+        variables:
+          version: $[ dependencies.Compile.outputs['setVars.version'] ]
+          other: $[ dependencies.Compyle.outputs['setVars.version'] ]
+        steps:
+          - script: echo $(version)
+  - stage: Deploy
+    condition: eq(dependencies.Build.outputs['Compile.setVars.version'], '1.0')
+    jobs:
+      - job: Release
+        variables:
+          version: $[ stageDependencies.Build.Compile.outputs['setVars.version'] ]
+          cased: $[ stageDependencies.build.compile.outputs['setvars.version'] ]
+          missing: $[ stageDependencies.Biuld.Compile.outputs['setVars.version'] ]
+        steps:
+          - script: echo $(version)
+  - stage: Verify
+    dependsOn: []
+    jobs:
+      - job: Check
+        variables:
+          version: $[ stageDependencies.Build.Compile.outputs['setVars.version'] ]
+        steps:
+          - script: echo $(version)
+";
+
+    fn validate() -> Vec<OutputValidationResult> {
+        let yaml: Value = serde_yaml::from_str(PIPELINE).unwrap();
+        let tree = ScopeTree::from_value(&yaml);
+        let index = OutputIndex::new(&tree, &[]);
+        let references = extract_output_reference_spans_from_content(PIPELINE, "azure-pipelines.yml").unwrap();
+        validate_output_references(&references, &tree, &index, &|_, _| false)
+    }
+
+    fn finding(results: &[OutputValidationResult], reference: &str) -> Option<OutputFindingKind> {
+        results
+            .iter()
+            .find(|r| r.reference == reference)
+            .unwrap_or_else(|| panic!("no result for {reference}"))
+            .finding
+            .as_ref()
+            .map(|f| f.kind)
+    }
+
+    #[test]
+    fn test_same_job_step_outputs() {
+        let results = validate();
+
+        assert_eq!(finding(&results, "setVars.version"), None);
+        assert_eq!(finding(&results, "setVars.verison"), Some(OutputFindingKind::UnknownVariable));
+        assert_eq!(finding(&results, "setVar.version"), Some(OutputFindingKind::UnknownStep));
+        assert_eq!(finding(&results, "setVars.local"), Some(OutputFindingKind::NotOutput));
+        // Tasks declare their own output variables
+        assert_eq!(finding(&results, "cli.anything"), None);
+        // Step names are case-insensitive
+        assert_eq!(finding(&results, "SetVars.version"), None);
+
+        let producer = results.iter().find(|r| r.reference == "setVars.version").unwrap();
+        assert_eq!(
+            producer.producer.as_deref(),
+            Some("stage 'Build' / job 'Compile' / step 'setVars'")
+        );
+    }
+
+    #[test]
+    fn test_job_dependencies() {
+        let results = validate();
+        let reference = "dependencies.Compile.outputs['setVars.version']";
+        let kinds: Vec<Option<OutputFindingKind>> = results
+            .iter()
+            .filter(|r| r.reference == reference)
+            .map(|r| r.finding.as_ref().map(|f| f.kind))
+            .collect();
+
+        // Test depends on Compile, Package does not
+        assert_eq!(kinds, vec![None, Some(OutputFindingKind::MissingDependency)]);
+        assert_eq!(
+            finding(&results, "dependencies.Compyle.outputs['setVars.version']"),
+            Some(OutputFindingKind::UnknownJob)
+        );
+        // Job names in dependsOn and dependencies are case-insensitive
+        assert_eq!(finding(&results, "dependencies.compile.outputs['SETVARS.version']"), None);
+    }
+
+    #[test]
+    fn test_stage_dependencies() {
+        let results = validate();
+        let reference = "stageDependencies.Build.Compile.outputs['setVars.version']";
+        let kinds: Vec<Option<OutputFindingKind>> = results
+            .iter()
+            .filter(|r| r.reference == reference)
+            .map(|r| r.finding.as_ref().map(|f| f.kind))
+            .collect();
+
+        // Deploy implicitly depends on the previous stage, Verify opts out with `dependsOn: []`
+        assert_eq!(kinds, vec![None, Some(OutputFindingKind::MissingDependency)]);
+        assert_eq!(
+            finding(&results, "stageDependencies.Biuld.Compile.outputs['setVars.version']"),
+            Some(OutputFindingKind::UnknownStage)
+        );
+        assert_eq!(finding(&results, "stageDependencies.build.compile.outputs['setvars.version']"), None);
+        assert_eq!(
+            finding(&results, "dependencies.Build.outputs['Compile.setVars.version']"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_dotted_regular_variables_are_skipped() {
+        let yaml: Value = serde_yaml::from_str(PIPELINE).unwrap();
+        let tree = ScopeTree::from_value(&yaml);
+        let index = OutputIndex::new(&tree, &[]);
+        let references = extract_output_reference_spans_from_content(PIPELINE, "azure-pipelines.yml").unwrap();
+        let results = validate_output_references(&references, &tree, &index, &|name, _| name == "setVar.version");
+
+        assert!(!results.iter().any(|r| r.reference == "setVar.version"));
+    }
+}
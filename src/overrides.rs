@@ -0,0 +1,239 @@
+//! Checks for variables a user intends to set when queueing a run
+//!
+//! Azure DevOps only accepts queue-time values for variables that are defined in the pipeline
+//! settings and marked "Let users override this value when running this pipeline". An accepted
+//! value is still overridden by any YAML definition of the same name, and runtime `parameters`
+//! are supplied separately from variables.
+
+use anyhow::{Context, Result};
+use std::fs;
+
+use crate::parser::variable_names_match;
+use crate::scope::{Definition, Scope};
+use crate::template::TemplateExpansion;
+use crate::validator::{GroupVariable, PipelineVariable};
+
+/// A variable value to be set when queueing a run
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueueTimeOverride {
+    /// Name of the variable
+    pub name: String,
+    /// Intended value
+    pub value: String,
+}
+
+/// What happens to a queue-time value
+#[derive(Debug, Clone, PartialEq)]
+pub enum OverrideStatus {
+    /// Accepted, and not overridden by the YAML
+    Accepted,
+    /// Rejected: the pipeline settings define the variable but do not allow overriding it
+    NotSettable,
+    /// Rejected: the pipeline settings do not define the variable
+    NotDefined,
+    /// Accepted but ignored, because YAML definitions override queue-time values
+    Shadowed {
+        /// YAML definitions of the variable, e.g. `inline variable in stage 'Deploy'`
+        definitions: Vec<String>,
+    },
+    /// The name is a runtime parameter of the pipeline, which is not set as a variable
+    RuntimeParameter,
+}
+
+/// Result of checking one queue-time value
+#[derive(Debug, Clone)]
+pub struct OverrideResult {
+    /// The checked value
+    pub requested: QueueTimeOverride,
+    /// What happens to it
+    pub status: OverrideStatus,
+}
+
+impl OverrideResult {
+    /// Whether the value takes effect in the run
+    pub fn is_accepted(&self) -> bool {
+        self.status == OverrideStatus::Accepted
+    }
+
+    /// Describe the outcome
+    pub fn message(&self) -> String {
+        let name = &self.requested.name;
+        match &self.status {
+            OverrideStatus::Accepted => format!("Variable '{name}' can be set at queue time"),
+            OverrideStatus::NotSettable => format!(
+                "Variable '{name}' cannot be set at queue time: the pipeline settings do not let users override its value"
+            ),
+            OverrideStatus::NotDefined => format!(
+                "Variable '{name}' cannot be set at queue time: it is not defined in the pipeline settings"
+            ),
+            OverrideStatus::Shadowed { definitions } => format!(
+                "Queue-time value of '{name}' is ignored: YAML definitions override it ({})",
+                definitions.join(", ")
+            ),
+            OverrideStatus::RuntimeParameter => format!(
+                "'{name}' is a runtime parameter, not a variable; supply it as a parameter instead (--parameter {name}={})",
+                self.requested.value
+            ),
+        }
+    }
+}
+
+/// Parse `NAME=VALUE` arguments
+///
+/// # Arguments
+/// * `arguments` - Values of `--set`
+///
+/// # Returns
+/// * `Result<Vec<QueueTimeOverride>>` - Overrides in the order given
+pub fn parse_overrides(arguments: &[String]) -> Result<Vec<QueueTimeOverride>> {
+    arguments
+        .iter()
+        .map(|argument| {
+            let Some((name, value)) = argument.split_once('=') else {
+                anyhow::bail!("Invalid queue-time variable '{argument}', expected NAME=VALUE");
+            };
+            Ok(QueueTimeOverride {
+                name: name.trim().to_string(),
+                value: value.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Read `NAME=VALUE` lines from a file; blank lines and lines starting with `#` are skipped
+///
+/// # Arguments
+/// * `path` - Path to the file
+///
+/// # Returns
+/// * `Result<Vec<QueueTimeOverride>>` - Overrides in file order
+pub fn read_overrides_file(path: &str) -> Result<Vec<QueueTimeOverride>> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read queue-time variables file: {path}"))?;
+    let lines: Vec<String> = content
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| line.to_string())
+        .collect();
+    parse_overrides(&lines).with_context(|| format!("Invalid queue-time variables file: {path}"))
+}
+
+/// Check what happens to each intended queue-time value
+///
+/// # Arguments
+/// * `overrides` - Values the user intends to set when queueing
+/// * `expansion` - The expanded pipeline, for its runtime parameters and YAML definitions
+/// * `group_variables` - Variables of the linked groups
+/// * `pipeline_variables` - Variables of the pipeline settings
+///
+/// # Returns
+/// * `Vec<OverrideResult>` - One result per override, in the order given
+pub fn check_overrides(
+    overrides: &[QueueTimeOverride],
+    expansion: &TemplateExpansion,
+    group_variables: &[GroupVariable],
+    pipeline_variables: &[PipelineVariable],
+) -> Vec<OverrideResult> {
+    let scopes: Vec<&Scope> = expansion
+        .scopes
+        .scopes()
+        .iter()
+        .chain(expansion.instances.iter().flat_map(|i| i.scopes.scopes()))
+        .collect();
+
+    overrides
+        .iter()
+        .map(|requested| {
+            let name = requested.name.as_str();
+            let variable = pipeline_variables.iter().find(|v| variable_names_match(&v.name, name));
+            let is_parameter = expansion
+                .parameters
+                .keys()
+                .filter_map(|key| key.as_str())
+                .any(|parameter| variable_names_match(parameter, name));
+
+            let status = match variable {
+                None if is_parameter => OverrideStatus::RuntimeParameter,
+                None => OverrideStatus::NotDefined,
+                Some(variable) if !variable.allow_override => OverrideStatus::NotSettable,
+                Some(_) => {
+                    let definitions = yaml_definitions(name, &scopes, group_variables);
+                    if definitions.is_empty() {
+                        OverrideStatus::Accepted
+                    } else {
+                        OverrideStatus::Shadowed { definitions }
+                    }
+                }
+            };
+
+            OverrideResult {
+                requested: requested.clone(),
+                status,
+            }
+        })
+        .collect()
+}
+
+/// YAML definitions of a variable anywhere in the pipeline, as inline variables or in linked groups
+fn yaml_definitions(name: &str, scopes: &[&Scope], group_variables: &[GroupVariable]) -> Vec<String> {
+    let mut definitions: Vec<String> = Vec::new();
+    for scope in scopes {
+        for definition in &scope.definitions {
+            let description = match definition {
+                Definition::Inline { name: defined, .. } if variable_names_match(defined, name) => {
+                    format!("inline variable in {}", scope.label)
+                }
+                Definition::Group(group)
+                    if group_variables
+                        .iter()
+                        .any(|v| v.group == *group && variable_names_match(&v.name, name)) =>
+                {
+                    format!("group '{group}' in {}", scope.label)
+                }
+                _ => continue,
+            };
+            if !definitions.contains(&description) {
+                definitions.push(description);
+            }
+        }
+    }
+    definitions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_overrides() {
+        let overrides = parse_overrides(&["region = westeurope".to_string(), "empty=".to_string()]).unwrap();
+        assert_eq!(overrides[0].name, "region");
+        assert_eq!(overrides[0].value, " westeurope");
+        assert_eq!(overrides[1].value, "");
+        assert!(parse_overrides(&["missing-separator".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_override_messages() {
+        let result = |status| OverrideResult {
+            requested: QueueTimeOverride {
+                name: "environment".to_string(),
+                value: "prod".to_string(),
+            },
+            status,
+        };
+        assert!(result(OverrideStatus::Accepted).is_accepted());
+        assert_eq!(
+            result(OverrideStatus::RuntimeParameter).message(),
+            "'environment' is a runtime parameter, not a variable; supply it as a parameter instead (--parameter environment=prod)"
+        );
+        assert_eq!(
+            result(OverrideStatus::Shadowed {
+                definitions: vec!["inline variable in stage 'Deploy'".to_string()]
+            })
+            .message(),
+            "Queue-time value of 'environment' is ignored: YAML definitions override it (inline variable in stage 'Deploy')"
+        );
+    }
+}
@@ -0,0 +1,388 @@
+//! Template parameter declarations and checks
+//!
+//! Templates declare their parameters with a `name`, an optional `type`, `default` and
+//! `values:` allow-list. Every `template:` reference is checked against those declarations
+//! the same way Azure DevOps does when it compiles the pipeline: required parameters must be
+//! supplied, unknown parameters are rejected, and values must match the declared type and
+//! allowed values.
+
+use serde_yaml::{Mapping, Value};
+
+/// Declared type of a template parameter
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParameterType {
+    String,
+    Number,
+    Boolean,
+    Object,
+    Step,
+    StepList,
+    Job,
+    JobList,
+    Deployment,
+    DeploymentList,
+    Stage,
+    StageList,
+}
+
+impl ParameterType {
+    /// Parse a `type:` value, returning `None` for types the checker does not know
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "string" => Some(Self::String),
+            "number" => Some(Self::Number),
+            "boolean" => Some(Self::Boolean),
+            "object" => Some(Self::Object),
+            "step" => Some(Self::Step),
+            "stepList" => Some(Self::StepList),
+            "job" => Some(Self::Job),
+            "jobList" => Some(Self::JobList),
+            "deployment" => Some(Self::Deployment),
+            "deploymentList" => Some(Self::DeploymentList),
+            "stage" => Some(Self::Stage),
+            "stageList" => Some(Self::StageList),
+            _ => None,
+        }
+    }
+
+    /// Name of the type as written in YAML
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::String => "string",
+            Self::Number => "number",
+            Self::Boolean => "boolean",
+            Self::Object => "object",
+            Self::Step => "step",
+            Self::StepList => "stepList",
+            Self::Job => "job",
+            Self::JobList => "jobList",
+            Self::Deployment => "deployment",
+            Self::DeploymentList => "deploymentList",
+            Self::Stage => "stage",
+            Self::StageList => "stageList",
+        }
+    }
+
+    /// Whether a value is compatible with the type
+    fn accepts(&self, value: &Value) -> bool {
+        match self {
+            Self::String => !matches!(value, Value::Sequence(_) | Value::Mapping(_)),
+            Self::Number => match value {
+                Value::Number(_) => true,
+                Value::String(s) => s.trim().parse::<f64>().is_ok(),
+                _ => false,
+            },
+            Self::Boolean => match value {
+                Value::Bool(_) => true,
+                Value::String(s) => s.eq_ignore_ascii_case("true") || s.eq_ignore_ascii_case("false"),
+                _ => false,
+            },
+            Self::Object => true,
+            Self::Step | Self::Job | Self::Deployment | Self::Stage => value.is_mapping(),
+            Self::StepList | Self::JobList | Self::DeploymentList | Self::StageList => match value {
+                Value::Sequence(items) => items.iter().all(|item| item.is_mapping()),
+                _ => false,
+            },
+        }
+    }
+}
+
+/// A parameter declared by a template
+#[derive(Debug, Clone)]
+pub struct ParameterDeclaration {
+    /// Parameter name
+    pub name: String,
+    /// Declared type (`None` if omitted or unknown)
+    pub param_type: Option<ParameterType>,
+    /// Default value (parameters without a default are required)
+    pub default: Option<Value>,
+    /// Allowed values (`values:`), if restricted
+    pub values: Option<Vec<Value>>,
+}
+
+/// Kind of parameter finding
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParameterFindingKind {
+    /// A parameter without a default was not supplied
+    Missing,
+    /// A supplied parameter is not declared by the template
+    Unknown,
+    /// A supplied value does not match the declared type
+    TypeMismatch,
+    /// A supplied value is not in the `values:` allow-list
+    NotAllowed,
+}
+
+/// A problem with the parameters passed to a template
+#[derive(Debug, Clone)]
+pub struct ParameterFinding {
+    /// Kind of finding
+    pub kind: ParameterFindingKind,
+    /// Name of the parameter
+    pub parameter: String,
+    /// Description of the finding
+    pub message: String,
+}
+
+/// Read the parameter declarations of a template
+///
+/// Supports the list syntax (`- name: x` with `type`, `default` and `values`) and the
+/// legacy map syntax (`x: default`), where every parameter has a default and no type.
+///
+/// # Arguments
+/// * `template` - Parsed template document
+///
+/// # Returns
+/// * `Vec<ParameterDeclaration>` - Declared parameters in declaration order
+pub fn parameter_declarations(template: &Value) -> Vec<ParameterDeclaration> {
+    match template.get("parameters") {
+        Some(Value::Sequence(declarations)) => declarations
+            .iter()
+            .filter_map(|declaration| {
+                let name = declaration.get("name")?.as_str()?.to_string();
+                Some(ParameterDeclaration {
+                    name,
+                    param_type: declaration
+                        .get("type")
+                        .and_then(|t| t.as_str())
+                        .and_then(ParameterType::parse),
+                    default: declaration.get("default").cloned(),
+                    values: declaration
+                        .get("values")
+                        .and_then(|v| v.as_sequence())
+                        .cloned(),
+                })
+            })
+            .collect(),
+        Some(Value::Mapping(declarations)) => declarations
+            .iter()
+            .filter_map(|(name, default)| {
+                Some(ParameterDeclaration {
+                    name: name.as_str()?.to_string(),
+                    param_type: None,
+                    default: Some(default.clone()),
+                    values: None,
+                })
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Check the parameters supplied to a template against its declarations
+///
+/// Values that are template expressions (`${{ ... }}`) are only known once the pipeline is
+/// compiled, so their type and allowed values are not checked.
+///
+/// # Arguments
+/// * `declarations` - Parameters declared by the template
+/// * `supplied` - Parameters passed by the `template:` reference
+///
+/// # Returns
+/// * `Vec<ParameterFinding>` - Missing, unknown, mistyped and disallowed parameters
+pub fn check_parameters(declarations: &[ParameterDeclaration], supplied: &Mapping) -> Vec<ParameterFinding> {
+    let mut findings = Vec::new();
+
+    for declaration in declarations {
+        let name = &declaration.name;
+        let Some(value) = supplied.get(name.as_str()) else {
+            if declaration.default.is_none() {
+                findings.push(ParameterFinding {
+                    kind: ParameterFindingKind::Missing,
+                    parameter: name.clone(),
+                    message: format!("parameter '{name}' is required but not supplied"),
+                });
+            }
+            continue;
+        };
+
+        if is_expression(value) {
+            continue;
+        }
+
+        if let Some(param_type) = declaration.param_type {
+            if !param_type.accepts(value) {
+                findings.push(ParameterFinding {
+                    kind: ParameterFindingKind::TypeMismatch,
+                    parameter: name.clone(),
+                    message: format!(
+                        "parameter '{name}' value {} is not a valid {}",
+                        describe_value(value),
+                        param_type.name()
+                    ),
+                });
+                continue;
+            }
+        }
+
+        if let Some(ref allowed) = declaration.values {
+            if !allowed.iter().any(|a| scalar_text(a) == scalar_text(value)) {
+                let allowed: Vec<String> = allowed.iter().filter_map(scalar_text).collect();
+                findings.push(ParameterFinding {
+                    kind: ParameterFindingKind::NotAllowed,
+                    parameter: name.clone(),
+                    message: format!(
+                        "parameter '{name}' value {} not in allowed values [{}]",
+                        describe_value(value),
+                        allowed.join(", ")
+                    ),
+                });
+            }
+        }
+    }
+
+    for key in supplied.keys().filter_map(|k| k.as_str()) {
+        // Conditionally inserted parameters (`${{ if ... }}:`) cannot be attributed
+        if key.starts_with("${{") {
+            continue;
+        }
+        if !declarations.iter().any(|d| d.name == key) {
+            findings.push(ParameterFinding {
+                kind: ParameterFindingKind::Unknown,
+                parameter: key.to_string(),
+                message: format!("parameter '{key}' is not declared by the template"),
+            });
+        }
+    }
+
+    findings
+}
+
+/// Whether a value is a compile-time template expression
+fn is_expression(value: &Value) -> bool {
+    value.as_str().is_some_and(|s| s.contains("${{"))
+}
+
+/// Text of a scalar value as Azure DevOps compares it (`None` for sequences and mappings)
+fn scalar_text(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        Value::Null => Some(String::new()),
+        _ => None,
+    }
+}
+
+/// Short description of a value for messages, e.g. `'prd'` or `a sequence`
+fn describe_value(value: &Value) -> String {
+    match value {
+        Value::Sequence(_) => "a sequence".to_string(),
+        Value::Mapping(_) => "a mapping".to_string(),
+        _ => format!("'{}'", scalar_text(value).unwrap_or_default()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEMPLATE: &str = "
+parameters:
+  - name: environment
+    type: string
+    values: [dev, test, prod]
+  - name: replicas
+    type: number
+    default: 1
+  - name: runTests
+    type: boolean
+    default: true
+  - name: extraSteps
+    type: stepList
+    default: []
+  - name: region
+";
+
+    fn check(supplied: &str) -> Vec<ParameterFinding> {
+        let template: Value = serde_yaml::from_str(TEMPLATE).unwrap();
+        let supplied: Mapping = serde_yaml::from_str(supplied).unwrap();
+        check_parameters(&parameter_declarations(&template), &supplied)
+    }
+
+    #[test]
+    fn test_parameter_declarations() {
+        let template: Value = serde_yaml::from_str(TEMPLATE).unwrap();
+        let declarations = parameter_declarations(&template);
+
+        assert_eq!(declarations.len(), 5);
+        assert_eq!(declarations[0].param_type, Some(ParameterType::String));
+        assert_eq!(declarations[0].values.as_ref().unwrap().len(), 3);
+        assert!(declarations[0].default.is_none());
+        assert_eq!(declarations[3].param_type, Some(ParameterType::StepList));
+        assert_eq!(declarations[4].param_type, None);
+    }
+
+    #[test]
+    fn test_legacy_map_declarations_have_defaults() {
+        let template: Value = serde_yaml::from_str("parameters:\n  environment: dev\n").unwrap();
+        let declarations = parameter_declarations(&template);
+
+        assert_eq!(declarations[0].name, "environment");
+        assert!(declarations[0].default.is_some());
+    }
+
+    #[test]
+    fn test_valid_parameters() {
+        let findings = check(
+            "environment: prod\nreplicas: '3'\nrunTests: 'False'\nextraSteps:\n  - script: echo hi\nregion: westeurope\n",
+        );
+        assert!(findings.is_empty(), "{findings:?}");
+    }
+
+    #[test]
+    fn test_missing_required_parameters() {
+        let findings = check("environment: dev\n");
+
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].kind, ParameterFindingKind::Missing);
+        assert_eq!(findings[0].message, "parameter 'region' is required but not supplied");
+    }
+
+    #[test]
+    fn test_unknown_parameter() {
+        let findings = check("environment: dev\nregion: eu\nenviroment: dev\n");
+
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].kind, ParameterFindingKind::Unknown);
+        assert_eq!(findings[0].parameter, "enviroment");
+    }
+
+    #[test]
+    fn test_value_not_in_allowed_values() {
+        let findings = check("environment: prd\nregion: eu\n");
+
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].kind, ParameterFindingKind::NotAllowed);
+        assert_eq!(
+            findings[0].message,
+            "parameter 'environment' value 'prd' not in allowed values [dev, test, prod]"
+        );
+    }
+
+    #[test]
+    fn test_type_mismatches() {
+        let findings = check(
+            "environment: [dev]\nreplicas: many\nrunTests: yes please\nextraSteps: echo\nregion: eu\n",
+        );
+
+        let kinds: Vec<(&str, ParameterFindingKind)> =
+            findings.iter().map(|f| (f.parameter.as_str(), f.kind)).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                ("environment", ParameterFindingKind::TypeMismatch),
+                ("replicas", ParameterFindingKind::TypeMismatch),
+                ("runTests", ParameterFindingKind::TypeMismatch),
+                ("extraSteps", ParameterFindingKind::TypeMismatch),
+            ]
+        );
+        assert_eq!(findings[1].message, "parameter 'replicas' value 'many' is not a valid number");
+    }
+
+    #[test]
+    fn test_expressions_are_not_checked() {
+        let findings = check("environment: ${{ parameters.env }}\nreplicas: ${{ parameters.count }}\nregion: eu\n");
+        assert!(findings.is_empty());
+    }
+}
@@ -4,6 +4,11 @@ use anyhow::{Context, Result};
 use regex::Regex;
 use serde::Deserialize;
 use std::fs;
+use std::ops::Range;
+use std::sync::LazyLock;
+
+use crate::scope::{ScopeKind, ScopeTree};
+use crate::span::{LineIndex, PathSegment, SourceSpan, YamlPath};
 
 /// Represents a variable group reference in the pipeline
 #[derive(Debug, Deserialize)]
@@ -79,20 +84,268 @@ impl Variables {
     }
 }
 
+/// Represents an entry in a `jobs` list
+/// Azure DevOps supports two kinds of jobs:
+/// - deployment: 'Name' (deployment job with an environment and a strategy)
+/// - job: 'Name' (regular job, also matches template references and conditionals)
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum JobEntry {
+    /// Deployment job: - deployment: 'Name'
+    Deployment(Box<Deployment>),
+    /// Regular job: - job: 'Name'
+    Job(Job),
+}
+
+impl JobEntry {
+    /// Job-level variables of either kind of job
+    pub fn variables(&self) -> &Option<Variables> {
+        match self {
+            JobEntry::Deployment(deployment) => &deployment.variables,
+            JobEntry::Job(job) => &job.variables,
+        }
+    }
+}
+
 /// Represents a job in a stage
 #[derive(Debug, Deserialize)]
 pub struct Job {
+    /// Job name
+    #[serde(default)]
+    pub job: Option<String>,
     /// Job-level variables (supports both list and map formats)
     #[serde(default)]
     pub variables: Option<Variables>,
+    /// Steps run by the job
+    #[serde(default)]
+    pub steps: Option<Vec<serde_yaml::Value>>,
+    /// Matrix or parallel strategy of the job
+    #[serde(default)]
+    pub strategy: Option<Box<JobStrategy>>,
+}
+
+/// Strategy of a regular job
+#[derive(Debug, Deserialize)]
+pub struct JobStrategy {
+    /// Matrix legs (a mapping, or a `$[ ]` / `${{ }}` expression producing one)
+    #[serde(default)]
+    pub matrix: Option<serde_yaml::Value>,
+    /// Number of identical parallel copies of the job
+    #[serde(default)]
+    pub parallel: Option<serde_yaml::Value>,
+    /// Maximum number of legs run at the same time
+    #[serde(default, rename = "maxParallel")]
+    pub max_parallel: Option<serde_yaml::Value>,
+}
+
+impl JobStrategy {
+    /// Parsed matrix of the strategy (if any)
+    pub fn matrix(&self) -> Option<Matrix> {
+        self.matrix.as_ref().map(Matrix::parse)
+    }
+}
+
+/// A leg of a job matrix and the variables it defines
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatrixLeg {
+    /// Leg name
+    pub name: String,
+    /// Variables defined by the leg
+    pub variables: Vec<String>,
+}
+
+/// Variables defined by the legs of a `strategy: matrix`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Matrix {
+    /// Legs of a matrix written in YAML
+    pub legs: Vec<MatrixLeg>,
+    /// Expression computing the matrix, e.g. `$[ dependencies.Plan.outputs['legs.matrix'] ]`;
+    /// such a matrix cannot be validated statically
+    pub expression: Option<String>,
+}
+
+impl Matrix {
+    /// Parse the value of a `matrix:` key
+    ///
+    /// Legs generated by `${{ each }}` loops and variables inserted by `${{ if }}` conditionals
+    /// are included; leg names may still contain `${{ }}` expressions.
+    pub fn parse(value: &serde_yaml::Value) -> Self {
+        let mut matrix = Self::default();
+        match value {
+            serde_yaml::Value::String(expression) => matrix.expression = Some(expression.clone()),
+            serde_yaml::Value::Mapping(legs) => Self::collect_legs(legs, &mut matrix.legs),
+            _ => {}
+        }
+        matrix
+    }
+
+    fn collect_legs(legs: &serde_yaml::Mapping, collected: &mut Vec<MatrixLeg>) {
+        for (name, leg) in legs {
+            let (Some(name), Some(leg)) = (name.as_str(), leg.as_mapping()) else {
+                continue;
+            };
+            if name.starts_with("${{") {
+                Self::collect_legs(leg, collected);
+                continue;
+            }
+
+            let mut variables = Vec::new();
+            Self::collect_variables(leg, &mut variables);
+            collected.push(MatrixLeg {
+                name: name.to_string(),
+                variables,
+            });
+        }
+    }
+
+    fn collect_variables(leg: &serde_yaml::Mapping, variables: &mut Vec<String>) {
+        for (key, value) in leg {
+            let Some(key) = key.as_str() else {
+                continue;
+            };
+            if key.starts_with("${{") {
+                if let Some(nested) = value.as_mapping() {
+                    Self::collect_variables(nested, variables);
+                }
+            } else if !variables.iter().any(|v| variable_names_match(v, key)) {
+                variables.push(key.to_string());
+            }
+        }
+    }
+
+    /// Variables defined by any leg, in first-seen order
+    pub fn variables(&self) -> Vec<&str> {
+        let mut variables: Vec<&str> = Vec::new();
+        for name in self.legs.iter().flat_map(|leg| leg.variables.iter()) {
+            if !variables.iter().any(|v| variable_names_match(v, name)) {
+                variables.push(name);
+            }
+        }
+        variables
+    }
+
+    /// Names of the legs that do not define a variable
+    pub fn legs_missing(&self, variable: &str) -> Vec<&str> {
+        self.legs
+            .iter()
+            .filter(|leg| !leg.variables.iter().any(|v| variable_names_match(v, variable)))
+            .map(|leg| leg.name.as_str())
+            .collect()
+    }
 }
 
 /// Represents a deployment job in a stage
 #[derive(Debug, Deserialize)]
 pub struct Deployment {
+    /// Deployment job name
+    pub deployment: String,
+    /// Target environment (a name, or a mapping with `name` and `resourceType`)
+    #[serde(default)]
+    pub environment: Option<serde_yaml::Value>,
     /// Deployment-level variables (supports both list and map formats)
     #[serde(default)]
     pub variables: Option<Variables>,
+    /// Deployment strategy holding the lifecycle hooks
+    #[serde(default)]
+    pub strategy: Option<DeploymentStrategy>,
+}
+
+impl Deployment {
+    /// Lifecycle hooks of the deployment strategy, as `(hook name, hook)` pairs
+    /// e.g. `("deploy", ...)` or `("on.failure", ...)`
+    pub fn hooks(&self) -> Vec<(&'static str, &LifecycleHook)> {
+        self.strategy
+            .as_ref()
+            .and_then(|strategy| strategy.lifecycle())
+            .map(|hooks| hooks.hooks())
+            .unwrap_or_default()
+    }
+}
+
+/// Deployment strategy of a deployment job
+/// Exactly one of `runOnce`, `rolling` or `canary` is expected
+#[derive(Debug, Deserialize)]
+pub struct DeploymentStrategy {
+    /// Run each lifecycle hook once
+    #[serde(default, rename = "runOnce")]
+    pub run_once: Option<LifecycleHooks>,
+    /// Deploy to a subset of the environment's resources at a time
+    #[serde(default)]
+    pub rolling: Option<LifecycleHooks>,
+    /// Deploy to an increasing share of the environment
+    #[serde(default)]
+    pub canary: Option<LifecycleHooks>,
+}
+
+impl DeploymentStrategy {
+    /// Lifecycle hooks of whichever strategy is configured
+    pub fn lifecycle(&self) -> Option<&LifecycleHooks> {
+        self.named_lifecycle().map(|(_, hooks)| hooks)
+    }
+
+    /// Key and lifecycle hooks of whichever strategy is configured, e.g. `("runOnce", ...)`
+    pub fn named_lifecycle(&self) -> Option<(&'static str, &LifecycleHooks)> {
+        [("runOnce", &self.run_once), ("rolling", &self.rolling), ("canary", &self.canary)]
+            .into_iter()
+            .find_map(|(name, hooks)| Some((name, hooks.as_ref()?)))
+    }
+}
+
+/// Lifecycle hooks of a deployment strategy
+#[derive(Debug, Deserialize)]
+pub struct LifecycleHooks {
+    /// Runs before the deployment, e.g. to initialize resources
+    #[serde(default, rename = "preDeploy")]
+    pub pre_deploy: Option<LifecycleHook>,
+    /// Runs the deployment
+    #[serde(default)]
+    pub deploy: Option<LifecycleHook>,
+    /// Routes traffic to the updated version
+    #[serde(default, rename = "routeTraffic")]
+    pub route_traffic: Option<LifecycleHook>,
+    /// Runs after traffic is routed, e.g. to monitor health
+    #[serde(default, rename = "postRouteTraffic")]
+    pub post_route_traffic: Option<LifecycleHook>,
+    /// Hooks run on failure or success of the deployment
+    #[serde(default)]
+    pub on: Option<OutcomeHooks>,
+}
+
+impl LifecycleHooks {
+    /// Configured hooks in execution order, as `(hook name, hook)` pairs
+    pub fn hooks(&self) -> Vec<(&'static str, &LifecycleHook)> {
+        let on = self.on.as_ref();
+        [
+            ("preDeploy", self.pre_deploy.as_ref()),
+            ("deploy", self.deploy.as_ref()),
+            ("routeTraffic", self.route_traffic.as_ref()),
+            ("postRouteTraffic", self.post_route_traffic.as_ref()),
+            ("on.failure", on.and_then(|on| on.failure.as_ref())),
+            ("on.success", on.and_then(|on| on.success.as_ref())),
+        ]
+        .into_iter()
+        .filter_map(|(name, hook)| Some((name, hook?)))
+        .collect()
+    }
+}
+
+/// The `on:` hooks of a deployment strategy
+#[derive(Debug, Deserialize)]
+pub struct OutcomeHooks {
+    /// Runs when the deployment fails
+    #[serde(default)]
+    pub failure: Option<LifecycleHook>,
+    /// Runs when the deployment succeeds
+    #[serde(default)]
+    pub success: Option<LifecycleHook>,
+}
+
+/// A lifecycle hook of a deployment strategy
+#[derive(Debug, Deserialize)]
+pub struct LifecycleHook {
+    /// Steps run by the hook
+    #[serde(default)]
+    pub steps: Option<Vec<serde_yaml::Value>>,
 }
 
 /// Represents a stage in the pipeline
@@ -104,9 +357,9 @@ pub struct Stage {
     /// Stage-level variables (supports both list and map formats)
     #[serde(default)]
     pub variables: Option<Variables>,
-    /// Jobs in the stage
+    /// Jobs and deployment jobs in the stage
     #[serde(default)]
-    pub jobs: Option<Vec<Job>>,
+    pub jobs: Option<Vec<JobEntry>>,
 }
 
 /// Top-level pipeline structure
@@ -119,9 +372,41 @@ pub struct Pipeline {
     /// Stages in the pipeline
     #[serde(default)]
     pub stages: Option<Vec<Stage>>,
+    /// Jobs of a single-stage pipeline written without `stages:`
+    #[serde(default)]
+    pub jobs: Option<Vec<JobEntry>>,
+    /// Steps of a single-job pipeline written without `stages:` or `jobs:`
+    #[serde(default)]
+    pub steps: Option<Vec<serde_yaml::Value>>,
+    /// Template the pipeline extends (if any)
+    #[serde(default)]
+    pub extends: Option<Extends>,
+}
+
+/// The `extends:` section of a pipeline
+#[derive(Debug, Deserialize)]
+pub struct Extends {
+    /// Path to the extended template
+    pub template: String,
+    /// Parameters passed to the template
+    #[serde(default)]
+    pub parameters: Option<serde_yaml::Mapping>,
 }
 
 impl Pipeline {
+    /// Jobs of the pipeline, in the stages or at the root of a single-stage pipeline
+    ///
+    /// A single-job pipeline (root `steps:`) has no job entries; its job-level variables are
+    /// the root variables.
+    pub fn jobs(&self) -> Vec<&JobEntry> {
+        self.stages
+            .iter()
+            .flatten()
+            .flat_map(|stage| stage.jobs.iter().flatten())
+            .chain(self.jobs.iter().flatten())
+            .collect()
+    }
+
     /// Extract all variable group names referenced in the pipeline
     /// Searches top-level variables, stage-level variables, and job-level variables
     ///
@@ -137,30 +422,26 @@ impl Pipeline {
         if let Some(ref stages) = self.stages {
             for stage in stages {
                 Self::collect_groups_from_variables(&stage.variables, &mut groups);
-
-                // Collect from job-level variables
-                if let Some(ref jobs) = stage.jobs {
-                    for job in jobs {
-                        Self::collect_groups_from_variables(&job.variables, &mut groups);
-                    }
-                }
             }
         }
 
+        // Collect from job-level variables, including root jobs
+        for job in self.jobs() {
+            Self::collect_groups_from_variables(job.variables(), &mut groups);
+        }
+
         groups
     }
 
     /// Helper function to collect variable groups from a variables section
-    fn collect_groups_from_variables(variables: &Option<Variables>, groups: &mut Vec<String>) {
+    pub(crate) fn collect_groups_from_variables(variables: &Option<Variables>, groups: &mut Vec<String>) {
         if let Some(ref vars) = variables {
             match vars {
                 Variables::List(entries) => {
                     for entry in entries {
                         match entry {
-                            VariableEntry::Group { group } => {
-                                if !groups.contains(group) {
-                                    groups.push(group.clone());
-                                }
+                            VariableEntry::Group { group } if !groups.contains(group) => {
+                                groups.push(group.clone());
                             }
                             VariableEntry::Conditional(value) => {
                                 Self::extract_groups_from_value(value, groups);
@@ -191,30 +472,26 @@ impl Pipeline {
         if let Some(ref stages) = self.stages {
             for stage in stages {
                 Self::collect_inline_variables(&stage.variables, &mut names);
-
-                // Collect from job-level variables
-                if let Some(ref jobs) = stage.jobs {
-                    for job in jobs {
-                        Self::collect_inline_variables(&job.variables, &mut names);
-                    }
-                }
             }
         }
 
+        // Collect from job-level variables, including root jobs
+        for job in self.jobs() {
+            Self::collect_inline_variables(job.variables(), &mut names);
+        }
+
         names
     }
 
     /// Helper function to collect inline variable names from a variables section
-    fn collect_inline_variables(variables: &Option<Variables>, names: &mut Vec<String>) {
+    pub(crate) fn collect_inline_variables(variables: &Option<Variables>, names: &mut Vec<String>) {
         if let Some(ref vars) = variables {
             match vars {
                 Variables::List(entries) => {
                     for entry in entries {
                         match entry {
-                            VariableEntry::Named { name, .. } => {
-                                if !names.contains(name) {
-                                    names.push(name.clone());
-                                }
+                            VariableEntry::Named { name, .. } if !names.contains(name) => {
+                                names.push(name.clone());
                             }
                             VariableEntry::Conditional(value) => {
                                 Self::extract_inline_variables_from_value(value, names);
@@ -366,14 +643,17 @@ const SYSTEM_VARIABLE_PREFIXES: &[&str] = &[
 ];
 
 /// Check if a variable name is a system/predefined Azure DevOps variable
+/// The prefix is matched case-insensitively, like all variable names (e.g. `system.debug`)
 pub fn is_system_variable(name: &str) -> bool {
-    SYSTEM_VARIABLE_PREFIXES
-        .iter()
-        .any(|prefix| name.starts_with(prefix))
+    SYSTEM_VARIABLE_PREFIXES.iter().any(|prefix| {
+        name.get(..prefix.len())
+            .is_some_and(|start| start.eq_ignore_ascii_case(prefix))
+    })
 }
 
 /// Check if a variable name is a runtime output variable
-/// These are set dynamically during pipeline execution and cannot be validated statically
+/// These are set by a named step during pipeline execution and are validated separately,
+/// see `extract_output_reference_spans_from_content`
 /// Examples: outputs.registryName, agentIp.value, domains.domainId
 fn is_runtime_output_variable(name: &str) -> bool {
     // Must contain a dot to be a potential runtime output
@@ -460,6 +740,7 @@ pub struct TemplateInfo {
 /// Templates are characterized by:
 /// - Having a top-level `parameters:` section
 /// - NOT having a `trigger:` key (templates don't define triggers)
+/// - NOT having an `extends:` key (pipelines extending a template declare runtime parameters)
 ///
 /// # Arguments
 /// * `path` - Path to the YAML pipeline file
@@ -487,9 +768,10 @@ pub fn detect_template(path: &str) -> Result<TemplateInfo> {
     let has_parameters = mapping.contains_key(serde_yaml::Value::String("parameters".to_string()));
     let has_trigger = mapping.contains_key(serde_yaml::Value::String("trigger".to_string()));
     let has_pr = mapping.contains_key(serde_yaml::Value::String("pr".to_string()));
+    let has_extends = mapping.contains_key(serde_yaml::Value::String("extends".to_string()));
 
-    // A template has parameters but no trigger/pr
-    let is_template = has_parameters && !has_trigger && !has_pr;
+    // A template has parameters but no trigger/pr, and does not extend another template
+    let is_template = has_parameters && !has_trigger && !has_pr && !has_extends;
 
     // Extract parameter names if this is a template
     let parameter_names = if is_template {
@@ -527,23 +809,71 @@ fn extract_parameter_names(yaml: &serde_yaml::Value) -> Vec<String> {
     names
 }
 
-/// A template reference found in a pipeline's jobs section
+/// Kind of list a template is inserted into
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TemplateKind {
+    /// `stages: - template: ...`
+    Stages,
+    /// `jobs: - template: ...`
+    Jobs,
+    /// `steps: - template: ...` (including deployment lifecycle hooks)
+    Steps,
+    /// `variables: - template: ...`
+    Variables,
+    /// `extends: template: ...`
+    Extends,
+}
+
+impl TemplateKind {
+    /// Template kind for a list key, if templates can be inserted into it
+    fn from_key(key: &str) -> Option<Self> {
+        match key {
+            "stages" => Some(Self::Stages),
+            "jobs" => Some(Self::Jobs),
+            "steps" => Some(Self::Steps),
+            "variables" => Some(Self::Variables),
+            "extends" => Some(Self::Extends),
+            _ => None,
+        }
+    }
+
+    /// Short description used in output, e.g. `job template`
+    pub fn description(&self) -> &'static str {
+        match self {
+            Self::Stages => "stage template",
+            Self::Jobs => "job template",
+            Self::Steps => "step template",
+            Self::Variables => "variables template",
+            Self::Extends => "extends template",
+        }
+    }
+}
+
+/// A template reference found in a pipeline's stages, jobs, steps or variables lists
 #[derive(Debug, Clone)]
 pub struct TemplateReference {
     /// Path to the template file (as specified in YAML)
     pub template_path: String,
     /// Name of the stage containing this template reference
     pub stage_name: Option<String>,
-    /// Variable groups available in this template's scope (top-level + stage-level)
+    /// Variable groups available in this template's scope (top-level + stage-level + job-level)
     pub available_groups: Vec<String>,
     /// Inline variables available in this template's scope
     pub available_inline_vars: Vec<String>,
+    /// Kind of list the template is inserted into
+    pub kind: TemplateKind,
+    /// Parameters passed to the template (empty if none)
+    pub parameters: serde_yaml::Mapping,
+    /// Location of the template path in the including file
+    pub span: SourceSpan,
 }
 
 /// Extract template references from a pipeline file
 ///
-/// Parses the raw YAML to find `- template: path` entries in jobs sections,
-/// collecting the variable groups available at each template's scope.
+/// Parses the raw YAML to find `- template: path` entries in `stages`, `jobs`, `steps`
+/// and `variables` lists at any depth (including inside `${{ }}` conditionals), and the
+/// `extends: template: path` entry point, collecting the variable groups available at each
+/// template's scope.
 ///
 /// # Arguments
 /// * `path` - Path to the pipeline YAML file
@@ -557,99 +887,123 @@ pub fn extract_template_references(path: &str) -> Result<Vec<TemplateReference>>
     let yaml: serde_yaml::Value = serde_yaml::from_str(&content)
         .with_context(|| format!("Failed to parse YAML in pipeline file: {path}"))?;
 
-    let mut references = Vec::new();
-
-    let mapping = match yaml.as_mapping() {
-        Some(m) => m,
-        None => return Ok(references),
-    };
+    let scopes = ScopeTree::from_value(&yaml);
+    Ok(template_references_in(&yaml, &LineIndex::new(&content), path, &scopes))
+}
 
-    // Collect top-level variable groups and inline variables
-    let mut top_level_groups = Vec::new();
-    let mut top_level_inline_vars = Vec::new();
-    if let Some(vars) = mapping.get(serde_yaml::Value::String("variables".to_string())) {
-        collect_groups_from_yaml_value(vars, &mut top_level_groups);
-        collect_inline_vars_from_yaml_value(vars, &mut top_level_inline_vars);
-    }
-
-    // Process stages
-    if let Some(stages) = mapping.get(serde_yaml::Value::String("stages".to_string())) {
-        if let Some(stages_seq) = stages.as_sequence() {
-            for stage in stages_seq {
-                if let Some(stage_map) = stage.as_mapping() {
-                    // Get stage name
-                    let stage_name = stage_map
-                        .get(serde_yaml::Value::String("stage".to_string()))
-                        .and_then(|v| v.as_str())
-                        .map(|s| s.to_string());
-
-                    // Collect stage-level variable groups
-                    let mut stage_groups = top_level_groups.clone();
-                    let mut stage_inline_vars = top_level_inline_vars.clone();
-                    if let Some(vars) = stage_map.get(serde_yaml::Value::String("variables".to_string())) {
-                        collect_groups_from_yaml_value(vars, &mut stage_groups);
-                        collect_inline_vars_from_yaml_value(vars, &mut stage_inline_vars);
+/// Find the template references of a parsed document, resolving available variables
+/// through the document's scope tree
+pub(crate) fn template_references_in(
+    yaml: &serde_yaml::Value,
+    index: &LineIndex,
+    file: &str,
+    scopes: &ScopeTree,
+) -> Vec<TemplateReference> {
+    let mut entries = Vec::new();
+    collect_template_entries(yaml, &YamlPath::root(), None, &mut entries);
+
+    entries
+        .into_iter()
+        .map(|(kind, item_path, template_path, parameters)| {
+            let chain = scopes.chain(&item_path);
+
+            let mut available_groups = Vec::new();
+            let mut available_inline_vars = Vec::new();
+            for scope in &chain {
+                for group in &scope.groups {
+                    if !available_groups.contains(group) {
+                        available_groups.push(group.clone());
                     }
-
-                    // Process jobs in this stage
-                    if let Some(jobs) = stage_map.get(serde_yaml::Value::String("jobs".to_string())) {
-                        if let Some(jobs_seq) = jobs.as_sequence() {
-                            for job in jobs_seq {
-                                if let Some(job_map) = job.as_mapping() {
-                                    // Check if this is a template reference
-                                    if let Some(template_val) = job_map.get(serde_yaml::Value::String("template".to_string())) {
-                                        if let Some(template_path) = template_val.as_str() {
-                                            references.push(TemplateReference {
-                                                template_path: template_path.to_string(),
-                                                stage_name: stage_name.clone(),
-                                                available_groups: stage_groups.clone(),
-                                                available_inline_vars: stage_inline_vars.clone(),
-                                            });
-                                        }
-                                    }
-                                }
-                            }
-                        }
+                }
+                for var in &scope.inline_variables {
+                    if !available_inline_vars.contains(var) {
+                        available_inline_vars.push(var.clone());
                     }
                 }
             }
-        }
-    }
 
-    Ok(references)
+            let stage_name = chain
+                .iter()
+                .rev()
+                .find(|scope| scope.kind == ScopeKind::Stage)
+                .and_then(|scope| scope.name.clone());
+
+            let value_path = item_path.key("template");
+            let span = index
+                .scalar_values()
+                .find(|(path, _)| **path == value_path)
+                .map(|(_, range)| index.span(file, range))
+                .unwrap_or_else(|| SourceSpan {
+                    file: file.to_string(),
+                    yaml_path: value_path,
+                    ..SourceSpan::default()
+                });
+
+            TemplateReference {
+                template_path,
+                stage_name,
+                available_groups,
+                available_inline_vars,
+                kind,
+                parameters,
+                span,
+            }
+        })
+        .collect()
 }
 
-/// Helper to collect variable groups from a YAML value
-fn collect_groups_from_yaml_value(value: &serde_yaml::Value, groups: &mut Vec<String>) {
-    if let Some(seq) = value.as_sequence() {
-        for item in seq {
-            if let Some(map) = item.as_mapping() {
-                if let Some(serde_yaml::Value::String(group_name)) =
-                    map.get(serde_yaml::Value::String("group".to_string()))
-                {
-                    if !groups.contains(group_name) {
-                        groups.push(group_name.clone());
-                    }
-                }
+/// A `template:` entry found in a document: kind, item path, template path and parameters
+type TemplateEntry = (TemplateKind, YamlPath, String, serde_yaml::Mapping);
+
+/// Recursively collect every `- template:` list item and the `extends:` template
+///
+/// `list_kind` is set while walking the items of a `stages`, `jobs`, `steps` or `variables`
+/// list (or the `extends` mapping); template expressions (`${{ if }}`, `${{ each }}`) keep
+/// the enclosing list kind.
+fn collect_template_entries(
+    value: &serde_yaml::Value,
+    path: &YamlPath,
+    list_kind: Option<TemplateKind>,
+    entries: &mut Vec<TemplateEntry>,
+) {
+    match value {
+        serde_yaml::Value::Sequence(seq) => {
+            for (i, item) in seq.iter().enumerate() {
+                collect_template_entries(item, &path.index(i), list_kind, entries);
             }
         }
-    }
-}
-
-/// Helper to collect inline variable names from a YAML value
-fn collect_inline_vars_from_yaml_value(value: &serde_yaml::Value, vars: &mut Vec<String>) {
-    if let Some(seq) = value.as_sequence() {
-        for item in seq {
-            if let Some(map) = item.as_mapping() {
-                if let Some(serde_yaml::Value::String(var_name)) =
-                    map.get(serde_yaml::Value::String("name".to_string()))
-                {
-                    if !vars.contains(var_name) {
-                        vars.push(var_name.clone());
-                    }
+        serde_yaml::Value::Mapping(map) => {
+            if let (Some(kind), Some(template)) = (
+                list_kind,
+                map.get("template").and_then(|t| t.as_str()),
+            ) {
+                let parameters = map
+                    .get("parameters")
+                    .and_then(|p| p.as_mapping())
+                    .cloned()
+                    .unwrap_or_default();
+                entries.push((kind, path.clone(), template.to_string(), parameters));
+
+                // Stages, jobs and steps passed as parameters may include templates themselves
+                if let Some(params) = map.get("parameters") {
+                    collect_template_entries(params, &path.key("parameters"), None, entries);
                 }
+                return;
+            }
+
+            for (key, val) in map {
+                let Some(key) = key.as_str() else {
+                    continue;
+                };
+                let child_kind = if key.starts_with("${{") {
+                    list_kind
+                } else {
+                    TemplateKind::from_key(key)
+                };
+                collect_template_entries(val, &path.key(key), child_kind, entries);
             }
         }
+        _ => {}
     }
 }
 
@@ -667,9 +1021,166 @@ pub fn resolve_template_path(parent_path: &str, template_ref: &str) -> String {
     let parent = Path::new(parent_path);
     let parent_dir = parent.parent().unwrap_or(Path::new("."));
 
+    // Paths starting with '/' are relative to the repository root
+    if let Some(repo_relative) = template_ref.strip_prefix('/') {
+        let repo_root = parent_dir
+            .ancestors()
+            .find(|dir| dir.join(".git").exists())
+            .unwrap_or(parent_dir);
+        return repo_root.join(repo_relative).to_string_lossy().to_string();
+    }
+
     parent_dir.join(template_ref).to_string_lossy().to_string()
 }
 
+/// Syntax used to reference a variable
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VariableSyntax {
+    /// Macro syntax `$(name)`, expanded just before a task runs
+    Macro,
+    /// Runtime expression `$[ variables.name ]` or `$[ variables['name'] ]`, or a reference in a
+    /// `condition:`
+    RuntimeExpression,
+    /// Template expression `${{ variables.name }}`, expanded when the pipeline is compiled
+    TemplateExpression,
+}
+
+/// A variable reference together with its location in the source file
+#[derive(Debug, Clone, PartialEq)]
+pub struct VariableReference {
+    /// Name of the referenced variable
+    pub name: String,
+    /// Location of the whole `$(variableName)` expression, or of `variables.name` inside
+    /// a runtime or template expression
+    pub span: SourceSpan,
+    /// Syntax of the reference
+    pub syntax: VariableSyntax,
+}
+
+/// A `- group: name` link together with its location in the source file
+#[derive(Debug, Clone, PartialEq)]
+pub struct GroupReference {
+    /// Name of the linked variable group
+    pub name: String,
+    /// Location of the group name
+    pub span: SourceSpan,
+}
+
+/// A variable defined in YAML (`- name: x` or `x: value` under `variables:`) with its location
+#[derive(Debug, Clone, PartialEq)]
+pub struct VariableDefinition {
+    /// Name of the defined variable
+    pub name: String,
+    /// Location of the variable name
+    pub span: SourceSpan,
+}
+
+/// Extract every inline variable definition from raw YAML content, with locations
+///
+/// Both the list form (`- name: x`) and the map form (`x: value`) are recognized, also inside
+/// `${{ if }}` blocks. Names computed by template expressions are skipped.
+///
+/// # Arguments
+/// * `content` - Raw YAML content
+/// * `file` - File name recorded in the returned spans
+///
+/// # Returns
+/// * `Vec<VariableDefinition>` - One entry per definition, in file order
+pub fn extract_variable_definitions_from_content(content: &str, file: &str) -> Vec<VariableDefinition> {
+    let index = LineIndex::new(content);
+
+    // List form: the value of `name` in an item of a `variables` sequence
+    let listed = index.scalar_values().filter(|(path, _)| {
+        let segments = path.segments();
+        path.last_key() == Some("name")
+            && segments.len() >= 2
+            && matches!(segments[segments.len() - 2], PathSegment::Index(_))
+            && in_variables_section(&segments[..segments.len() - 2], true)
+    });
+    // Map form: a key directly inside a `variables` mapping
+    let mapped = index.keys().filter(|(path, _)| {
+        let segments = path.segments();
+        path.last_key().is_some_and(|key| !key.starts_with("${{"))
+            && in_variables_section(&segments[..segments.len() - 1], false)
+    });
+
+    let mut definitions: Vec<VariableDefinition> = listed
+        .chain(mapped)
+        .map(|(_, range)| VariableDefinition {
+            name: index.text(range.clone()).to_string(),
+            span: index.span(file, range),
+        })
+        .filter(|definition| !definition.name.contains("${{") && !definition.name.is_empty())
+        .collect();
+    definitions.sort_by_key(|definition| definition.span.byte_range.start);
+    definitions
+}
+
+/// Whether a path points into a `variables` section, looking through `${{ }}` blocks
+/// (and, for the list form, the sequences they contain)
+fn in_variables_section(segments: &[PathSegment], list: bool) -> bool {
+    let mut end = segments.len();
+    while end > 0 {
+        match &segments[end - 1] {
+            PathSegment::Key(key) if key.starts_with("${{") => end -= 1,
+            PathSegment::Index(_) if list => end -= 1,
+            _ => break,
+        }
+    }
+    end > 0 && segments[end - 1] == PathSegment::Key("variables".to_string())
+}
+
+/// An environment variable read by a script, e.g. `$API_KEY` or `%API_KEY%`
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnvironmentReference {
+    /// Name of the environment variable
+    pub name: String,
+    /// Location of the whole reference
+    pub span: SourceSpan,
+}
+
+/// `$env:NAME`, `${NAME}`, `$NAME` or `%NAME%` in a script
+static ENVIRONMENT_REFERENCE_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)\$env:(\w+)|\$\{(\w+)\}|\$([A-Za-z_]\w*)|%(\w+)%").expect("valid environment reference regex")
+});
+
+/// Extract the environment variables read by scripts in raw YAML content
+///
+/// Pipeline variables are exposed to scripts as environment variables, so `$NAME`, `${NAME}`,
+/// `$env:NAME` and `%NAME%` are collected anywhere in the file, including keys no shell runs
+/// (e.g. `displayName`). Callers keep the references whose YAML path is a script.
+///
+/// # Arguments
+/// * `content` - Raw YAML content
+/// * `file` - Path of the file, used for locations
+///
+/// # Returns
+/// * `Vec<EnvironmentReference>` - One entry per occurrence, in file order
+pub fn extract_environment_references(content: &str, file: &str) -> Vec<EnvironmentReference> {
+    let index = LineIndex::new(content);
+    ENVIRONMENT_REFERENCE_RE
+        .captures_iter(content)
+        .filter_map(|captures| {
+            let whole = captures.get(0)?;
+            let name = (1..=4).find_map(|i| captures.get(i))?;
+            Some(EnvironmentReference {
+                name: name.as_str().to_string(),
+                span: index.span(file, whole.range()),
+            })
+        })
+        .collect()
+}
+
+/// Name of the environment variable Azure DevOps creates for a pipeline variable
+///
+/// Dots and spaces are replaced with underscores; the agent also upper-cases the name, but
+/// environment names are compared case-insensitively here.
+pub fn environment_variable_name(name: &str) -> String {
+    name.chars()
+        .map(|c| if c == '.' || c == ' ' { '_' } else { c })
+        .collect()
+}
+
 /// Extract variable references from raw YAML content string
 /// Filters out PowerShell expressions, system variables, and runtime output variables
 ///
@@ -679,15 +1190,60 @@ pub fn resolve_template_path(parent_path: &str, template_ref: &str) -> String {
 /// # Returns
 /// * `Result<Vec<String>>` - Unique list of variable names referenced (excluding system/runtime vars)
 pub fn extract_variable_references_from_content(content: &str) -> Result<Vec<String>> {
+    let mut variables = Vec::new();
+
+    for reference in extract_variable_reference_spans_from_content(content, "")? {
+        if !variables.contains(&reference.name) {
+            variables.push(reference.name);
+        }
+    }
+
+    Ok(variables)
+}
+
+/// Extract every variable reference occurrence from a pipeline file, with locations
+///
+/// # Arguments
+/// * `path` - Path to the YAML pipeline file
+///
+/// # Returns
+/// * `Result<Vec<VariableReference>>` - One entry per occurrence, in file order
+pub fn extract_variable_reference_spans(path: &str) -> Result<Vec<VariableReference>> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read pipeline file: {path}"))?;
+
+    extract_variable_reference_spans_from_content(&content, path)
+}
+
+/// Extract every variable reference occurrence from raw YAML content, with locations
+/// Applies the same filtering as `extract_variable_references_from_content`
+///
+/// # Arguments
+/// * `content` - Raw YAML content
+/// * `file` - File name recorded in the returned spans
+///
+/// # Returns
+/// * `Result<Vec<VariableReference>>` - One entry per occurrence, in file order
+pub fn extract_variable_reference_spans_from_content(
+    content: &str,
+    file: &str,
+) -> Result<Vec<VariableReference>> {
     // Regex pattern to match $(variableName) syntax
     // Captures the variable name inside the parentheses
     let re = Regex::new(r"\$\(([^)]+)\)")
         .with_context(|| "Failed to compile variable reference regex")?;
 
-    let mut variables = Vec::new();
+    // Regex pattern to match variables.name and variables['name'] inside expressions
+    let expression_re = Regex::new(
+        r#"\bvariables(?:\.([A-Za-z_][A-Za-z0-9_]*(?:\.[A-Za-z_][A-Za-z0-9_]*)*)|\[\s*'([^']+)'\s*\]|\[\s*"([^"]+)"\s*\])"#,
+    )
+    .with_context(|| "Failed to compile expression variable regex")?;
+
+    let index = LineIndex::new(content);
+    let mut references = Vec::new();
 
     for cap in re.captures_iter(content) {
-        if let Some(var_name) = cap.get(1) {
+        if let (Some(whole), Some(var_name)) = (cap.get(0), cap.get(1)) {
             let name = var_name.as_str();
 
             // Skip variables that shouldn't be validated
@@ -695,12 +1251,299 @@ pub fn extract_variable_references_from_content(content: &str) -> Result<Vec<Str
                 continue;
             }
 
-            let name_string = name.to_string();
-            if !variables.contains(&name_string) {
-                variables.push(name_string);
+            references.push(VariableReference {
+                name: name.to_string(),
+                span: index.span(file, whole.range()),
+                syntax: VariableSyntax::Macro,
+            });
+        }
+    }
+
+    // Conditions are runtime expressions without the `$[ ]` delimiters
+    let conditions = index
+        .scalar_values()
+        .filter(|(path, value)| path.last_key() == Some("condition") && !index.text(value.clone()).contains('$'))
+        .map(|(_, value)| (VariableSyntax::RuntimeExpression, value));
+
+    for (syntax, body) in expression_bodies(content).into_iter().chain(conditions) {
+        for cap in expression_re.captures_iter(&content[body.clone()]) {
+            let (Some(whole), Some(var_name)) = (
+                cap.get(0),
+                cap.get(1).or_else(|| cap.get(2)).or_else(|| cap.get(3)),
+            ) else {
+                continue;
+            };
+            let name = var_name.as_str().trim();
+
+            if should_skip_variable(name) {
+                continue;
             }
+
+            let start = body.start + whole.start();
+            references.push(VariableReference {
+                name: name.to_string(),
+                span: index.span(file, start..start + whole.len()),
+                syntax,
+            });
         }
     }
 
-    Ok(variables)
+    references.sort_by_key(|r| r.span.byte_range.start);
+
+    Ok(references)
+}
+
+/// Byte ranges of the bodies of `$[ ... ]` runtime expressions and `${{ ... }}` template
+/// expressions, in file order
+fn expression_bodies(content: &str) -> Vec<(VariableSyntax, Range<usize>)> {
+    let bytes = content.as_bytes();
+    let mut bodies = Vec::new();
+    let mut i = 0;
+
+    while i + 1 < bytes.len() {
+        if bytes[i] != b'$' {
+            i += 1;
+            continue;
+        }
+
+        if content[i..].starts_with("${{") {
+            let start = i + 3;
+            match content[start..].find("}}") {
+                Some(end) => {
+                    bodies.push((VariableSyntax::TemplateExpression, start..start + end));
+                    i = start + end + 2;
+                }
+                None => break,
+            }
+        } else if bytes[i + 1] == b'[' {
+            // Match the closing bracket, allowing nested indexers and quoted strings
+            let start = i + 2;
+            let mut depth = 1;
+            let mut quote: Option<u8> = None;
+            let mut end = None;
+            for (offset, &b) in bytes[start..].iter().enumerate() {
+                match quote {
+                    Some(q) if b == q => quote = None,
+                    Some(_) => {}
+                    None => match b {
+                        b'\'' | b'"' => quote = Some(b),
+                        b'[' => depth += 1,
+                        b']' => {
+                            depth -= 1;
+                            if depth == 0 {
+                                end = Some(start + offset);
+                                break;
+                            }
+                        }
+                        b'\n' => break,
+                        _ => {}
+                    },
+                }
+            }
+            match end {
+                Some(end) => {
+                    bodies.push((VariableSyntax::RuntimeExpression, start..end));
+                    i = end + 1;
+                }
+                None => i = start,
+            }
+        } else {
+            i += 1;
+        }
+    }
+
+    bodies
+}
+
+/// Compare two variable names the way Azure DevOps does, ignoring case
+///
+/// # Arguments
+/// * `a` - First variable name
+/// * `b` - Second variable name
+///
+/// # Returns
+/// * `bool` - True if both names refer to the same variable
+pub fn variable_names_match(a: &str, b: &str) -> bool {
+    a.eq_ignore_ascii_case(b) || (!a.is_ascii() && a.to_lowercase() == b.to_lowercase())
+}
+
+/// A variable set at runtime by a `##vso[task.setvariable]` logging command
+#[derive(Debug, Clone, PartialEq)]
+pub struct SetVariable {
+    /// Name of the variable (`variable=` property)
+    pub name: String,
+    /// Whether the variable is an output variable (`isOutput=true`)
+    pub is_output: bool,
+}
+
+/// `##vso[task.setvariable ...]` logging command, capturing its properties
+static SET_VARIABLE_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)##vso\[task\.setvariable\b([^\]]*)\]").expect("valid setvariable regex"));
+
+/// Extract the `##vso[task.setvariable ...]` logging commands from a script
+///
+/// # Arguments
+/// * `text` - Script text (or any string value of a step)
+///
+/// # Returns
+/// * `Vec<SetVariable>` - One entry per command with a `variable=` property, in order
+pub fn extract_set_variables(text: &str) -> Vec<SetVariable> {
+    SET_VARIABLE_RE
+        .captures_iter(text)
+        .filter_map(|cap| {
+            let mut name = None;
+            let mut is_output = false;
+            for property in cap[1].split(';') {
+                let Some((key, value)) = property.split_once('=') else {
+                    continue;
+                };
+                match key.trim().to_ascii_lowercase().as_str() {
+                    "variable" => name = Some(value.trim().to_string()),
+                    "isoutput" => is_output = value.trim().eq_ignore_ascii_case("true"),
+                    _ => {}
+                }
+            }
+            Some(SetVariable {
+                name: name.filter(|n| !n.is_empty())?,
+                is_output,
+            })
+        })
+        .collect()
+}
+
+/// How an output variable reference addresses the step that sets it
+#[derive(Debug, Clone, PartialEq)]
+pub enum OutputForm {
+    /// `$(step.variable)`, read by a later step of the same job
+    Step { step: String, variable: String },
+    /// `dependencies.Name.outputs['...']`, naming a job of the same stage when used in a
+    /// job, or a stage when used in a stage condition
+    Dependencies { name: String, outputs: String },
+    /// `stageDependencies.Stage.Job.outputs['step.variable']`, read by a job of a later stage
+    StageDependencies { stage: String, job: String, outputs: String },
+}
+
+/// An output variable reference together with its location in the source file
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputReference {
+    /// The reference as written, e.g. `dependencies.Build.outputs['setVars.version']`
+    pub text: String,
+    /// Step, job and stage the reference addresses
+    pub form: OutputForm,
+    /// Location of the reference
+    pub span: SourceSpan,
+}
+
+/// Extract every output variable reference occurrence from raw YAML content, with locations
+///
+/// Finds dotted macros (`$(step.variable)`) that are not system variables, and
+/// `dependencies`/`stageDependencies` `outputs[...]` lookups in expressions and conditions.
+///
+/// # Arguments
+/// * `content` - Raw YAML content
+/// * `file` - File name recorded in the returned spans
+///
+/// # Returns
+/// * `Result<Vec<OutputReference>>` - One entry per occurrence, in file order
+pub fn extract_output_reference_spans_from_content(content: &str, file: &str) -> Result<Vec<OutputReference>> {
+    let macro_re = Regex::new(r"\$\(([^)]+)\)")
+        .with_context(|| "Failed to compile variable reference regex")?;
+    let dependencies_re = Regex::new(
+        r#"\b(?:stageDependencies\.(\w+)\.(\w+)|dependencies\.(\w+))\.outputs\[\s*(?:'([^']+)'|"([^"]+)")\s*\]"#,
+    )
+    .with_context(|| "Failed to compile output variable regex")?;
+
+    let index = LineIndex::new(content);
+    let mut references = Vec::new();
+
+    for cap in macro_re.captures_iter(content) {
+        let (Some(whole), Some(name)) = (cap.get(0), cap.get(1)) else {
+            continue;
+        };
+        let name = name.as_str();
+        if !is_runtime_output_variable(name)
+            || name.starts_with('$')
+            || name.starts_with('[')
+            || looks_like_shell_command(name)
+        {
+            continue;
+        }
+        let Some((step, variable)) = name.split_once('.') else {
+            continue;
+        };
+
+        references.push(OutputReference {
+            text: name.to_string(),
+            form: OutputForm::Step {
+                step: step.to_string(),
+                variable: variable.to_string(),
+            },
+            span: index.span(file, whole.range()),
+        });
+    }
+
+    for cap in dependencies_re.captures_iter(content) {
+        let Some(whole) = cap.get(0) else {
+            continue;
+        };
+        let outputs = cap.get(4).or_else(|| cap.get(5)).map_or("", |m| m.as_str()).trim().to_string();
+        let form = match (cap.get(1), cap.get(2), cap.get(3)) {
+            (Some(stage), Some(job), _) => OutputForm::StageDependencies {
+                stage: stage.as_str().to_string(),
+                job: job.as_str().to_string(),
+                outputs,
+            },
+            (_, _, Some(name)) => OutputForm::Dependencies {
+                name: name.as_str().to_string(),
+                outputs,
+            },
+            _ => continue,
+        };
+
+        references.push(OutputReference {
+            text: whole.as_str().to_string(),
+            form,
+            span: index.span(file, whole.range()),
+        });
+    }
+
+    references.sort_by_key(|r| r.span.byte_range.start);
+
+    Ok(references)
+}
+
+/// Extract every variable group link from a pipeline file, with locations
+///
+/// # Arguments
+/// * `path` - Path to the YAML pipeline file
+///
+/// # Returns
+/// * `Result<Vec<GroupReference>>` - One entry per `- group:` line, in file order
+pub fn extract_group_references(path: &str) -> Result<Vec<GroupReference>> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read pipeline file: {path}"))?;
+
+    Ok(extract_group_references_from_content(&content, path))
+}
+
+/// Extract every variable group link from raw YAML content, with locations
+/// Only `group:` keys inside a `variables` section are considered
+///
+/// # Arguments
+/// * `content` - Raw YAML content
+/// * `file` - File name recorded in the returned spans
+///
+/// # Returns
+/// * `Vec<GroupReference>` - One entry per `- group:` line, in file order
+pub fn extract_group_references_from_content(content: &str, file: &str) -> Vec<GroupReference> {
+    let index = LineIndex::new(content);
+
+    index
+        .scalar_values()
+        .filter(|(path, _)| path.last_key() == Some("group") && path.contains_key("variables"))
+        .map(|(_, range)| GroupReference {
+            name: index.text(range.clone()).to_string(),
+            span: index.span(file, range),
+        })
+        .collect()
 }
@@ -0,0 +1,398 @@
+//! Variable precedence and shadowing diagnostics
+//!
+//! Azure DevOps lets a name be defined in several places, and the most specific definition
+//! wins: matrix legs over job variables, job over stage, stage over the pipeline root. Inside
+//! one `variables` section a later entry, inline variable or group, overrides an earlier one.
+//! Any YAML definition overrides a value given at queue time, which in turn overrides the
+//! value saved in the pipeline settings.
+
+use crate::parser::{variable_names_match, VariableReference};
+use crate::scope::{Definition, Scope, ScopeTree};
+use crate::span::SourceSpan;
+use crate::validator::{GroupVariable, PipelineVariable, VariableSource};
+
+/// A place where a variable is defined
+#[derive(Debug, Clone, PartialEq)]
+pub struct DefinitionSite {
+    /// Kind of definition (inline, group, matrix or pipeline definition)
+    pub source: VariableSource,
+    /// Label of the scope that defines the variable (None for the pipeline settings)
+    pub scope: Option<String>,
+    /// Spelling of the name at this site
+    pub name: String,
+    /// Value at this site, when it is known and not secret
+    pub value: Option<String>,
+    /// Whether the value is secret
+    pub is_secret: bool,
+    /// Whether the pipeline settings let users override the value at queue time
+    pub settable_at_queue_time: bool,
+}
+
+impl DefinitionSite {
+    /// Describe the site, e.g. `group 'db-prod' in stage 'Deploy'`
+    pub fn describe(&self) -> String {
+        let scope = self.scope.as_deref().unwrap_or_default();
+        match &self.source {
+            VariableSource::Group(group) => format!("group '{group}' in {scope}"),
+            VariableSource::Inline => format!("inline variable in {scope}"),
+            VariableSource::Matrix { job } => format!("matrix of {job}"),
+            VariableSource::PipelineDefinition if self.settable_at_queue_time => {
+                "pipeline settings (settable at queue time)".to_string()
+            }
+            VariableSource::PipelineDefinition => "pipeline settings".to_string(),
+            VariableSource::RuntimeSet { step } => format!("{step} at runtime"),
+            VariableSource::NotFound => scope.to_string(),
+        }
+    }
+}
+
+/// Every definition of a referenced variable that is visible where it is used
+#[derive(Debug, Clone)]
+pub struct PrecedenceReport {
+    /// Name of the variable as referenced
+    pub variable_name: String,
+    /// Label of the scope the variable is used in
+    pub scope: String,
+    /// Visible definitions, lowest precedence first; the last one wins
+    pub sites: Vec<DefinitionSite>,
+    /// Definitions that conflict in ways that are easy to get wrong
+    pub warnings: Vec<String>,
+    /// Locations where the variable is referenced
+    pub locations: Vec<SourceSpan>,
+}
+
+impl PrecedenceReport {
+    /// The definition whose value the variable has at runtime
+    pub fn winner(&self) -> &DefinitionSite {
+        self.sites.last().expect("reports have at least two definition sites")
+    }
+
+    /// Explain which definition wins, e.g.
+    /// `'Region' resolves to inline variable in job 'Deploy', overriding group 'shared' in pipeline root`
+    pub fn explanation(&self) -> String {
+        let overridden: Vec<String> = self.sites[..self.sites.len() - 1]
+            .iter()
+            .rev()
+            .map(DefinitionSite::describe)
+            .collect();
+        format!(
+            "'{}' resolves to {}, overriding {}",
+            self.variable_name,
+            self.winner().describe(),
+            overridden.join(", ")
+        )
+    }
+}
+
+/// List the definitions of each referenced variable that are visible where it is used
+///
+/// Only variables defined in more than one place are reported. References are grouped per
+/// variable and scope.
+///
+/// # Arguments
+/// * `references` - Variable references found in the pipeline, with locations
+/// * `scopes` - Scope tree of the pipeline
+/// * `group_variables` - Variables of the linked groups, with values
+/// * `pipeline_variables` - Variables of the pipeline definition
+///
+/// # Returns
+/// * `Vec<PrecedenceReport>` - One report per shadowed variable and scope
+pub fn diagnose_precedence(
+    references: &[VariableReference],
+    scopes: &ScopeTree,
+    group_variables: &[GroupVariable],
+    pipeline_variables: &[PipelineVariable],
+) -> Vec<PrecedenceReport> {
+    let mut reports: Vec<PrecedenceReport> = Vec::new();
+    let mut seen: Vec<(String, String)> = Vec::new();
+
+    for reference in references {
+        let scope = scopes.variable_scope(&reference.span.yaml_path);
+        let key = (reference.name.to_lowercase(), scope.label.clone());
+        if seen.contains(&key) {
+            if let Some(report) = reports
+                .iter_mut()
+                .find(|r| r.scope == scope.label && variable_names_match(&r.variable_name, &reference.name))
+            {
+                report.locations.push(reference.span.clone());
+            }
+            continue;
+        }
+        seen.push(key);
+
+        let sites = definition_sites(&reference.name, &scopes.chain(&scope.path), group_variables, pipeline_variables);
+        if sites.len() < 2 {
+            continue;
+        }
+
+        reports.push(PrecedenceReport {
+            variable_name: reference.name.clone(),
+            scope: scope.label.clone(),
+            warnings: conflicts(&reference.name, &sites),
+            sites,
+            locations: vec![reference.span.clone()],
+        });
+    }
+
+    reports
+}
+
+/// Definitions of a variable visible through a scope chain, lowest precedence first
+///
+/// # Arguments
+/// * `var_name` - Name of the variable
+/// * `chain` - Scopes visible where the variable is used, outermost first
+/// * `group_variables` - Variables of the linked groups, with values
+/// * `pipeline_variables` - Variables of the pipeline definition
+///
+/// # Returns
+/// * `Vec<DefinitionSite>` - Every definition; the last one wins
+pub fn definition_sites(
+    var_name: &str,
+    chain: &[&Scope],
+    group_variables: &[GroupVariable],
+    pipeline_variables: &[PipelineVariable],
+) -> Vec<DefinitionSite> {
+    let mut sites = Vec::new();
+
+    if let Some(variable) = pipeline_variables
+        .iter()
+        .find(|v| variable_names_match(&v.name, var_name))
+    {
+        sites.push(DefinitionSite {
+            source: VariableSource::PipelineDefinition,
+            scope: None,
+            name: variable.name.clone(),
+            value: variable.value.clone().filter(|_| !variable.is_secret),
+            is_secret: variable.is_secret,
+            settable_at_queue_time: variable.allow_override,
+        });
+    }
+
+    for scope in chain {
+        for definition in &scope.definitions {
+            match definition {
+                Definition::Group(group) => {
+                    let found = group_variables
+                        .iter()
+                        .find(|v| v.group == *group && variable_names_match(&v.name, var_name));
+                    if let Some(variable) = found {
+                        sites.push(DefinitionSite {
+                            source: VariableSource::Group(group.clone()),
+                            scope: Some(scope.label.clone()),
+                            name: variable.name.clone(),
+                            value: variable.value.clone().filter(|_| !variable.is_secret),
+                            is_secret: variable.is_secret,
+                            settable_at_queue_time: false,
+                        });
+                    }
+                }
+                Definition::Inline { name, value } if variable_names_match(name, var_name) => {
+                    sites.push(DefinitionSite {
+                        source: VariableSource::Inline,
+                        scope: Some(scope.label.clone()),
+                        name: name.clone(),
+                        value: value.clone(),
+                        is_secret: false,
+                        settable_at_queue_time: false,
+                    });
+                }
+                Definition::Inline { .. } => {}
+            }
+        }
+
+        let matrix_name = scope
+            .matrix
+            .as_ref()
+            .and_then(|matrix| matrix.variables().into_iter().find(|v| variable_names_match(v, var_name)));
+        if let Some(name) = matrix_name {
+            sites.push(DefinitionSite {
+                source: VariableSource::Matrix {
+                    job: scope.label.clone(),
+                },
+                scope: Some(scope.label.clone()),
+                name: name.to_string(),
+                value: None,
+                is_secret: false,
+                settable_at_queue_time: false,
+            });
+        }
+    }
+
+    sites
+}
+
+/// Warnings for definitions whose precedence is easy to get wrong
+fn conflicts(var_name: &str, sites: &[DefinitionSite]) -> Vec<String> {
+    let mut warnings = Vec::new();
+
+    // Two groups with different values: which one wins depends on scope and link order
+    let groups: Vec<&DefinitionSite> = sites
+        .iter()
+        .filter(|site| matches!(site.source, VariableSource::Group(_)))
+        .collect();
+    for (i, earlier) in groups.iter().enumerate() {
+        for later in &groups[i + 1..] {
+            if let (Some(a), Some(b)) = (&earlier.value, &later.value) {
+                if a != b && earlier.source != later.source {
+                    warnings.push(format!(
+                        "'{var_name}' is defined with different values in {} ('{a}') and {} ('{b}'); the latter wins",
+                        earlier.describe(),
+                        later.describe()
+                    ));
+                }
+            }
+        }
+    }
+
+    // A group and an inline variable in the same section: only the order decides
+    for (i, earlier) in sites.iter().enumerate() {
+        for later in &sites[i + 1..] {
+            let mixed = matches!(
+                (&earlier.source, &later.source),
+                (VariableSource::Group(_), VariableSource::Inline) | (VariableSource::Inline, VariableSource::Group(_))
+            );
+            let values_differ = earlier.value.is_none() || later.value.is_none() || earlier.value != later.value;
+            if mixed && earlier.scope == later.scope && values_differ {
+                warnings.push(format!(
+                    "'{var_name}' is defined both as {} and as {}; only the order of the entries decides, and the later one wins",
+                    earlier.describe(),
+                    later.describe()
+                ));
+            }
+        }
+    }
+
+    // A queue-time value cannot override a YAML definition
+    if let Some(settings) = sites.iter().find(|site| site.settable_at_queue_time) {
+        if sites.len() > 1 {
+            warnings.push(format!(
+                "'{}' can be set at queue time in the pipeline settings, but the YAML definition in {} always overrides the queued value",
+                settings.name,
+                sites.last().and_then(|site| site.scope.as_deref()).unwrap_or_default()
+            ));
+        }
+    }
+
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PIPELINE: &str = "\
+variables:
+  - name: Region
+    value: westeurope
+  - group: shared
+  - group: overrides
+stages:
+  - stage: Deploy
+    variables:
+      Region: northeurope
+    jobs:
+      - job: Web
+        steps:
+          - script: echo $(Region) $(Tier)
+";
+
+    fn group_variable(name: &str, group: &str, value: &str) -> GroupVariable {
+        GroupVariable {
+            name: name.to_string(),
+            group: group.to_string(),
+            value: Some(value.to_string()),
+            is_secret: false,
+        }
+    }
+
+    fn reports(group_variables: &[GroupVariable], pipeline_variables: &[PipelineVariable]) -> Vec<PrecedenceReport> {
+        let yaml: serde_yaml::Value = serde_yaml::from_str(PIPELINE).unwrap();
+        let scopes = ScopeTree::from_value(&yaml);
+        let references =
+            crate::parser::extract_variable_reference_spans_from_content(PIPELINE, "azure-pipelines.yml").unwrap();
+        diagnose_precedence(&references, &scopes, group_variables, pipeline_variables)
+    }
+
+    #[test]
+    fn test_most_specific_definition_wins() {
+        let reports = reports(&[group_variable("region", "shared", "eastus")], &[]);
+        assert_eq!(reports.len(), 1);
+
+        let region = &reports[0];
+        let sources: Vec<&VariableSource> = region.sites.iter().map(|s| &s.source).collect();
+        assert_eq!(
+            sources,
+            vec![
+                &VariableSource::Inline,
+                &VariableSource::Group("shared".to_string()),
+                &VariableSource::Inline,
+            ]
+        );
+        assert_eq!(region.winner().scope.as_deref(), Some("stage 'Deploy'"));
+        assert_eq!(
+            region.explanation(),
+            "'Region' resolves to inline variable in stage 'Deploy', overriding group 'shared' in pipeline root, inline variable in pipeline root"
+        );
+        // The root group follows the root inline variable, so only their order decides
+        assert_eq!(region.warnings.len(), 1);
+        assert!(region.warnings[0].contains("only the order of the entries decides"));
+    }
+
+    #[test]
+    fn test_different_values_in_two_groups() {
+        let reports = reports(
+            &[group_variable("Tier", "shared", "basic"), group_variable("Tier", "overrides", "premium")],
+            &[],
+        );
+        let tier = reports.iter().find(|r| r.variable_name == "Tier").unwrap();
+        assert_eq!(tier.winner().source, VariableSource::Group("overrides".to_string()));
+        assert_eq!(
+            tier.warnings,
+            vec![
+                "'Tier' is defined with different values in group 'shared' in pipeline root ('basic') and group 'overrides' in pipeline root ('premium'); the latter wins"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_queue_time_value_is_overridden_by_yaml() {
+        let pipeline_variables = vec![PipelineVariable {
+            name: "Tier".to_string(),
+            value: Some("standard".to_string()),
+            is_secret: false,
+            allow_override: true,
+        }];
+        let reports = reports(&[group_variable("Tier", "shared", "basic")], &pipeline_variables);
+        let tier = reports.iter().find(|r| r.variable_name == "Tier").unwrap();
+
+        assert_eq!(tier.sites[0].describe(), "pipeline settings (settable at queue time)");
+        assert!(tier.warnings.iter().any(|w| w.contains("always overrides the queued value")));
+    }
+
+    #[test]
+    fn test_validation_reports_the_same_winner() {
+        let pipeline_variables = vec![PipelineVariable {
+            name: "Tier".to_string(),
+            value: Some("standard".to_string()),
+            is_secret: false,
+            allow_override: true,
+        }];
+        let reports = reports(&[group_variable("Tier", "shared", "basic")], &pipeline_variables);
+        let tier = reports.iter().find(|r| r.variable_name == "Tier").unwrap();
+        assert_eq!(tier.winner().source, VariableSource::Group("shared".to_string()));
+
+        let yaml: serde_yaml::Value = serde_yaml::from_str(PIPELINE).unwrap();
+        let scopes = ScopeTree::from_value(&yaml);
+        let references =
+            crate::parser::extract_variable_reference_spans_from_content(PIPELINE, "azure-pipelines.yml").unwrap();
+        let results = crate::validator::validate_scoped_variables_against_available(
+            &references,
+            &scopes,
+            &[("Tier".to_string(), "shared".to_string())],
+            &["Tier".to_string()],
+        );
+        let validated = results.iter().find(|r| r.variable_name == "Tier").unwrap();
+        assert_eq!(validated.source, tier.winner().source);
+    }
+}
@@ -0,0 +1,733 @@
+//! Variable scopes of a pipeline (root, stages, jobs and steps)
+//!
+//! Azure DevOps resolves a `$(variable)` against the variables visible where it is
+//! used: root variables, then the enclosing stage, then the enclosing job. The scope
+//! tree records which groups and inline variables each of those levels defines, keyed
+//! by YAML path so references can be attributed through their source spans.
+
+use anyhow::{Context, Result};
+use serde_yaml::{Mapping, Value};
+use std::fs;
+
+use crate::parser::{
+    extract_set_variables, variable_names_match, DeploymentStrategy, Matrix, Pipeline, SetVariable, Variables,
+};
+use crate::span::YamlPath;
+
+/// Level of a scope in the pipeline hierarchy
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScopeKind {
+    /// The pipeline root
+    Root,
+    /// A stage
+    Stage,
+    /// A job
+    Job,
+    /// A step (defines no variables, used to attribute references)
+    Step,
+}
+
+/// A `variables` entry of a scope
+#[derive(Debug, Clone, PartialEq)]
+pub enum Definition {
+    /// Variable group link: `- group: name`
+    Group(String),
+    /// Inline variable: `- name: x` / `value: y`, or `x: y` in map form
+    Inline {
+        /// Name of the variable
+        name: String,
+        /// Value of the variable, if it is a scalar
+        value: Option<String>,
+    },
+}
+
+/// A stage, job or step together with the variables defined directly on it
+#[derive(Debug, Clone)]
+pub struct Scope {
+    /// Level of the scope
+    pub kind: ScopeKind,
+    /// Stage or job name, or the `name:` of a step (if any)
+    pub name: Option<String>,
+    /// YAML path of the node that opens the scope
+    pub path: YamlPath,
+    /// Human readable description including enclosing scopes, e.g. `stage 'Build' / job 'Compile'`
+    pub label: String,
+    /// Variable groups linked directly at this scope
+    pub groups: Vec<String>,
+    /// Inline variables defined directly at this scope
+    pub inline_variables: Vec<String>,
+    /// Groups and inline variables of this scope in declaration order; later entries
+    /// override earlier ones
+    pub definitions: Vec<Definition>,
+    /// Explicit `dependsOn` of a stage or job (`None` if omitted)
+    pub depends_on: Option<Vec<String>>,
+    /// Whether a job is a deployment job (`- deployment:`)
+    pub deployment: bool,
+    /// Matrix of a job's `strategy`, whose legs define job-scoped variables
+    pub matrix: Option<Matrix>,
+    /// Variables a step sets with `##vso[task.setvariable]` logging commands
+    pub set_variables: Vec<SetVariable>,
+    /// Whether a step may set variables that are not visible in the YAML (tasks, or
+    /// logging commands with computed names)
+    pub dynamic_variables: bool,
+    /// Environment variables a step maps explicitly in its `env:` section
+    pub env: Vec<String>,
+}
+
+/// All scopes of a pipeline or template document, outermost first
+///
+/// A template document also carries the scopes of the place it is inserted into, so
+/// references inside the template see the stage and job variables of its caller.
+#[derive(Debug, Clone)]
+pub struct ScopeTree {
+    /// Scopes enclosing the document (empty for the pipeline itself)
+    inherited: Vec<Scope>,
+    /// Scopes defined by the document, starting with its root
+    scopes: Vec<Scope>,
+    /// Steps of the enclosing job that run before the document (for step templates)
+    preceding_steps: Vec<Scope>,
+}
+
+/// Label used for the pipeline root scope
+pub const ROOT_LABEL: &str = "pipeline root";
+
+impl ScopeTree {
+    /// Build the scope tree of a pipeline file
+    ///
+    /// # Arguments
+    /// * `path` - Path to the YAML pipeline file
+    ///
+    /// # Returns
+    /// * `Result<ScopeTree>` - Scopes of the pipeline
+    pub fn from_file(path: &str) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read pipeline file: {path}"))?;
+
+        let yaml: Value = serde_yaml::from_str(&content)
+            .with_context(|| format!("Failed to parse YAML in pipeline file: {path}"))?;
+
+        Ok(Self::from_value(&yaml))
+    }
+
+    /// Build the scope tree of a parsed YAML document
+    pub fn from_value(yaml: &Value) -> Self {
+        Self::build(yaml, Vec::new(), ROOT_LABEL.to_string(), "")
+    }
+
+    /// Build the scope tree of a template document inserted below the given scopes
+    ///
+    /// # Arguments
+    /// * `yaml` - Parsed template document
+    /// * `inherited` - Scopes visible at the insertion point, outermost first
+    /// * `label` - Label of the template root, e.g. `stage 'Build' / template 'jobs.yml'`
+    pub fn for_template(yaml: &Value, inherited: Vec<Scope>, label: String) -> Self {
+        let prefix = label.clone();
+        Self::build(yaml, inherited, label, &prefix)
+    }
+
+    fn build(yaml: &Value, inherited: Vec<Scope>, root_label: String, prefix: &str) -> Self {
+        let mut tree = Self {
+            inherited,
+            scopes: Vec::new(),
+            preceding_steps: Vec::new(),
+        };
+        let root_path = YamlPath::root();
+
+        let mut root = new_scope(ScopeKind::Root, None, root_path.clone(), root_label);
+        let Some(mapping) = yaml.as_mapping() else {
+            tree.scopes.push(root);
+            return tree;
+        };
+        collect_definitions(mapping, &mut root);
+        tree.scopes.push(root);
+
+        // Stage templates and full pipelines have stages, job templates and single-stage
+        // pipelines have root jobs, step templates and single-job pipelines have root steps
+        if let Some(stages) = mapping.get("stages") {
+            tree.walk_stages(stages, &root_path.key("stages"), prefix);
+        }
+        if let Some(jobs) = mapping.get("jobs") {
+            tree.walk_jobs(jobs, &root_path.key("jobs"), prefix);
+        }
+        if let Some(steps) = mapping.get("steps") {
+            tree.walk_steps(steps, &root_path.key("steps"), prefix);
+        }
+
+        // Stages, jobs and steps passed to an `extends` template by a pipeline that extends it
+        if let Some(parameters) = mapping
+            .get("extends")
+            .and_then(|extends| extends.get("parameters"))
+            .and_then(|parameters| parameters.as_mapping())
+        {
+            let parameters_path = root_path.key("extends").key("parameters");
+            for (key, value) in parameters {
+                match key.as_str() {
+                    Some("stages") => tree.walk_stages(value, &parameters_path.key("stages"), prefix),
+                    Some("jobs") => tree.walk_jobs(value, &parameters_path.key("jobs"), prefix),
+                    Some("steps") => tree.walk_steps(value, &parameters_path.key("steps"), prefix),
+                    _ => {}
+                }
+            }
+        }
+
+        tree
+    }
+
+    /// Set the steps of the enclosing job that run before this document
+    ///
+    /// Used for step templates, whose steps can read variables set at runtime by the steps
+    /// before the `- template:` entry.
+    pub fn with_preceding_steps(mut self, steps: Vec<Scope>) -> Self {
+        self.preceding_steps = steps;
+        self
+    }
+
+    /// Scopes defined by this document, outermost first (inherited scopes excluded)
+    pub fn scopes(&self) -> &[Scope] {
+        &self.scopes
+    }
+
+    /// Scopes visible from a YAML path, outermost first (inherited, root, stage, job, step)
+    pub fn chain(&self, path: &YamlPath) -> Vec<&Scope> {
+        self.inherited
+            .iter()
+            .chain(self.scopes.iter().filter(|scope| path.starts_with(&scope.path)))
+            .collect()
+    }
+
+    /// Innermost scope that can define variables (root, stage or job) containing a YAML path
+    pub fn variable_scope(&self, path: &YamlPath) -> &Scope {
+        self.scopes
+            .iter()
+            .rev()
+            .find(|scope| scope.kind != ScopeKind::Step && path.starts_with(&scope.path))
+            .unwrap_or(&self.scopes[0])
+    }
+
+    /// Innermost step containing a YAML path (if the path is inside a step)
+    pub fn step_at(&self, path: &YamlPath) -> Option<&Scope> {
+        self.scopes
+            .iter()
+            .rev()
+            .find(|scope| scope.kind == ScopeKind::Step && path.starts_with(&scope.path))
+    }
+
+    /// Steps of the same job that run before a YAML path, in order
+    ///
+    /// Includes the steps before the document when it is a step template.
+    pub fn steps_before(&self, path: &YamlPath) -> Vec<&Scope> {
+        let job = &self.variable_scope(path).path;
+        let own = self.scopes.iter().filter(|scope| {
+            scope.kind == ScopeKind::Step
+                && scope.path.precedes(path)
+                && self.variable_scope(&scope.path).path == *job
+        });
+
+        let inherited = self.preceding_steps.iter().filter(|_| *job == self.scopes[0].path);
+        inherited.chain(own).collect()
+    }
+
+    /// Latest earlier step of the same job that sets a variable with `task.setvariable`
+    ///
+    /// Variables set at runtime are only visible to later steps, so references outside a
+    /// step (e.g. in job `variables`) never resolve to them. Output variables are read as
+    /// `$(step.name)` instead and are not returned.
+    ///
+    /// # Arguments
+    /// * `name` - Variable name
+    /// * `path` - YAML path of the reference
+    ///
+    /// # Returns
+    /// * `Option<&Scope>` - The step that sets the variable, if any
+    pub fn runtime_definition(&self, name: &str, path: &YamlPath) -> Option<&Scope> {
+        self.step_at(path)?;
+        self.steps_before(path).into_iter().rev().find(|step| {
+            step.set_variables
+                .iter()
+                .any(|variable| variable_names_match(&variable.name, name) && !variable.is_output)
+        })
+    }
+
+    /// Add definitions (e.g. from a variables template) to the scope enclosing a YAML path
+    ///
+    /// # Arguments
+    /// * `path` - YAML path inside the scope that receives the definitions
+    /// * `groups` - Variable groups to link in that scope
+    /// * `inline_variables` - Inline variables to define in that scope
+    pub fn add_definitions(&mut self, path: &YamlPath, groups: &[String], inline_variables: &[String]) {
+        let index = self
+            .scopes
+            .iter()
+            .rposition(|scope| scope.kind != ScopeKind::Step && path.starts_with(&scope.path))
+            .unwrap_or(0);
+        let scope = &mut self.scopes[index];
+
+        for group in groups {
+            if !scope.groups.contains(group) {
+                scope.groups.push(group.clone());
+            }
+            scope.definitions.push(Definition::Group(group.clone()));
+        }
+        for name in inline_variables {
+            if !scope.inline_variables.contains(name) {
+                scope.inline_variables.push(name.clone());
+            }
+            scope.definitions.push(Definition::Inline {
+                name: name.clone(),
+                value: None,
+            });
+        }
+    }
+
+    /// Walk a `stages` list and record a scope for each stage
+    fn walk_stages(&mut self, stages: &Value, path: &YamlPath, parent_label: &str) {
+        for (stage, stage_path) in list_items(stages, path) {
+            let name = string_field(stage, "stage");
+            let label = match &name {
+                Some(name) => child_label(parent_label, format!("stage '{name}'")),
+                None => child_label(parent_label, format!("stage at {stage_path}")),
+            };
+
+            let mut scope = new_scope(ScopeKind::Stage, name, stage_path.clone(), label.clone());
+            collect_definitions(stage, &mut scope);
+            scope.depends_on = depends_on(stage);
+            self.scopes.push(scope);
+
+            if let Some(jobs) = stage.get("jobs") {
+                self.walk_jobs(jobs, &stage_path.key("jobs"), &label);
+            }
+        }
+    }
+
+    /// Walk a `jobs` list and record a scope for each job or deployment job
+    fn walk_jobs(&mut self, jobs: &Value, path: &YamlPath, parent_label: &str) {
+        for (job, job_path) in list_items(jobs, path) {
+            let deployment = job.contains_key("deployment");
+            let kind = if deployment { "deployment" } else { "job" };
+            let name = string_field(job, kind);
+            let label = match &name {
+                Some(name) => child_label(parent_label, format!("{kind} '{name}'")),
+                None => child_label(parent_label, format!("{kind} at {job_path}")),
+            };
+
+            let mut scope = new_scope(ScopeKind::Job, name, job_path.clone(), label.clone());
+            collect_definitions(job, &mut scope);
+            scope.depends_on = depends_on(job);
+            scope.deployment = deployment;
+            scope.matrix = job
+                .get("strategy")
+                .and_then(|strategy| strategy.get("matrix"))
+                .map(Matrix::parse);
+            self.scopes.push(scope);
+
+            if let Some(steps) = job.get("steps") {
+                self.walk_steps(steps, &job_path.key("steps"), &label);
+            }
+            for (steps, steps_path) in lifecycle_steps(job, &job_path) {
+                self.walk_steps(&steps, &steps_path, &label);
+            }
+        }
+    }
+
+    /// Walk a `steps` list and record a scope for each step
+    fn walk_steps(&mut self, steps: &Value, path: &YamlPath, parent_label: &str) {
+        for (step, step_path) in list_items(steps, path) {
+            let name = string_field(step, "name");
+            let label = match name.clone().or_else(|| string_field(step, "displayName")) {
+                Some(name) => child_label(parent_label, format!("step '{name}'")),
+                None => child_label(parent_label, format!("step at {step_path}")),
+            };
+
+            let mut scope = new_scope(ScopeKind::Step, name, step_path, label);
+            collect_set_variables(step, &mut scope);
+            if let Some(env) = step.get("env").and_then(|env| env.as_mapping()) {
+                scope.env = env.keys().filter_map(|key| key.as_str().map(|k| k.to_string())).collect();
+            }
+            self.scopes.push(scope);
+        }
+    }
+}
+
+/// Join a parent scope label and a child description
+fn child_label(parent: &str, child: String) -> String {
+    if parent.is_empty() {
+        child
+    } else {
+        format!("{parent} / {child}")
+    }
+}
+
+/// Create a scope without any definitions
+fn new_scope(kind: ScopeKind, name: Option<String>, path: YamlPath, label: String) -> Scope {
+    Scope {
+        kind,
+        name,
+        path,
+        label,
+        groups: Vec::new(),
+        inline_variables: Vec::new(),
+        definitions: Vec::new(),
+        depends_on: None,
+        deployment: false,
+        matrix: None,
+        set_variables: Vec::new(),
+        dynamic_variables: false,
+        env: Vec::new(),
+    }
+}
+
+/// Collect the variable groups and inline variables defined in a node's `variables` section
+fn collect_definitions(node: &Mapping, scope: &mut Scope) {
+    let variables = node
+        .get("variables")
+        .and_then(|value| serde_yaml::from_value::<Variables>(value.clone()).ok());
+
+    Pipeline::collect_groups_from_variables(&variables, &mut scope.groups);
+    Pipeline::collect_inline_variables(&variables, &mut scope.inline_variables);
+    if let Some(value) = node.get("variables") {
+        collect_ordered_definitions(value, &mut scope.definitions);
+    }
+}
+
+/// Collect the entries of a `variables` section in declaration order, looking through
+/// `${{ }}` blocks
+fn collect_ordered_definitions(value: &Value, definitions: &mut Vec<Definition>) {
+    match value {
+        Value::Sequence(items) => {
+            for item in items.iter().filter_map(|item| item.as_mapping()) {
+                if let Some(group) = item.get("group").and_then(|g| g.as_str()) {
+                    definitions.push(Definition::Group(group.to_string()));
+                } else if let Some(name) = item.get("name").and_then(|n| n.as_str()) {
+                    definitions.push(Definition::Inline {
+                        name: name.to_string(),
+                        value: item.get("value").and_then(scalar_text),
+                    });
+                } else {
+                    for (key, nested) in item {
+                        if key.as_str().is_some_and(|k| k.starts_with("${{")) {
+                            collect_ordered_definitions(nested, definitions);
+                        }
+                    }
+                }
+            }
+        }
+        Value::Mapping(map) => {
+            for (key, nested) in map {
+                let Some(key) = key.as_str() else {
+                    continue;
+                };
+                if key.starts_with("${{") {
+                    collect_ordered_definitions(nested, definitions);
+                } else {
+                    definitions.push(Definition::Inline {
+                        name: key.to_string(),
+                        value: scalar_text(nested),
+                    });
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Text of a scalar YAML value
+fn scalar_text(value: &Value) -> Option<String> {
+    match value {
+        Value::String(text) => Some(text.clone()),
+        Value::Bool(flag) => Some(flag.to_string()),
+        Value::Number(number) => Some(number.to_string()),
+        _ => None,
+    }
+}
+
+/// Step keys whose variables are fully visible in the YAML (inline scripts)
+pub(crate) const SCRIPT_STEP_KEYS: &[&str] = &["script", "bash", "pwsh", "powershell"];
+
+/// Task inputs that hold inline scripts (e.g. `AzureCLI@2`, `Bash@3`, `PowerShell@2`)
+const INLINE_SCRIPT_INPUTS: &[&str] = &["inlineScript", "script"];
+
+/// Collect the variables a step sets with `##vso[task.setvariable]` logging commands
+fn collect_set_variables(step: &Mapping, scope: &mut Scope) {
+    let inputs = step.get("inputs").and_then(|inputs| inputs.as_mapping());
+    let texts = SCRIPT_STEP_KEYS
+        .iter()
+        .filter_map(|key| step.get(*key))
+        .chain(INLINE_SCRIPT_INPUTS.iter().filter_map(|key| inputs?.get(*key)))
+        .filter_map(|value| value.as_str());
+
+    for text in texts {
+        for variable in extract_set_variables(text) {
+            if variable.name.contains(['$', '%', '{', '(']) {
+                scope.dynamic_variables = true;
+            } else if !scope.set_variables.contains(&variable) {
+                scope.set_variables.push(variable);
+            }
+        }
+    }
+
+    // Tasks declare output variables the linter cannot see
+    if !SCRIPT_STEP_KEYS.iter().any(|key| step.contains_key(*key)) {
+        scope.dynamic_variables = true;
+    }
+}
+
+/// Steps of the lifecycle hooks of a deployment job's strategy with their YAML paths, in
+/// execution order
+fn lifecycle_steps(job: &Mapping, job_path: &YamlPath) -> Vec<(Value, YamlPath)> {
+    let Some(strategy) = job
+        .get("strategy")
+        .and_then(|strategy| serde_yaml::from_value::<DeploymentStrategy>(strategy.clone()).ok())
+    else {
+        return Vec::new();
+    };
+    let Some((name, lifecycle)) = strategy.named_lifecycle() else {
+        return Vec::new();
+    };
+
+    let lifecycle_path = job_path.key("strategy").key(name);
+    lifecycle
+        .hooks()
+        .into_iter()
+        .filter_map(|(hook, lifecycle_hook)| {
+            let steps = lifecycle_hook.steps.clone()?;
+            // `on.failure` lives at `on` / `failure`
+            let hook_path = hook.split('.').fold(lifecycle_path.clone(), |path, key| path.key(key));
+            Some((Value::Sequence(steps), hook_path.key("steps")))
+        })
+        .collect()
+}
+
+/// Read the `dependsOn` of a stage or job as a list of names
+fn depends_on(node: &Mapping) -> Option<Vec<String>> {
+    match node.get("dependsOn")? {
+        Value::String(name) => Some(vec![name.clone()]),
+        Value::Sequence(names) => Some(
+            names
+                .iter()
+                .filter_map(|name| name.as_str().map(|s| s.to_string()))
+                .collect(),
+        ),
+        _ => Some(Vec::new()),
+    }
+}
+
+/// Read a string field of a mapping
+fn string_field(node: &Mapping, key: &str) -> Option<String> {
+    node.get(key).and_then(|v| v.as_str()).map(|s| s.to_string())
+}
+
+/// Items of a stages/jobs/steps list with their YAML paths
+///
+/// Items wrapped in template expressions (`- ${{ if ... }}:` or `- ${{ each ... }}:`) are
+/// flattened so that conditionally inserted stages, jobs and steps get their own scopes.
+/// Template references (`- template: ...`) are not scopes and are skipped.
+fn list_items<'a>(value: &'a Value, path: &YamlPath) -> Vec<(&'a Mapping, YamlPath)> {
+    let mut items = Vec::new();
+    collect_list_items(value, path, &mut items);
+    items
+}
+
+fn collect_list_items<'a>(value: &'a Value, path: &YamlPath, items: &mut Vec<(&'a Mapping, YamlPath)>) {
+    let Some(seq) = value.as_sequence() else {
+        return;
+    };
+
+    for (i, item) in seq.iter().enumerate() {
+        let item_path = path.index(i);
+        let Some(mapping) = item.as_mapping() else {
+            continue;
+        };
+
+        let expression_keys: Vec<&str> = mapping
+            .keys()
+            .filter_map(|k| k.as_str())
+            .filter(|k| k.starts_with("${{"))
+            .collect();
+
+        if !expression_keys.is_empty() {
+            for key in expression_keys {
+                if let Some(nested) = mapping.get(key) {
+                    collect_list_items(nested, &item_path.key(key), items);
+                }
+            }
+        } else if !mapping.contains_key("template") {
+            items.push((mapping, item_path));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PIPELINE: &str = "
+variables:
+  - group: shared
+  - name: rootVar
+    value: a
+
+stages:
+  - stage: Build
+    variables:
+      - group: build-secrets
+    jobs:
+      - job: Compile
+        variables:
+          jobVar: b
+        steps:
+          - script: echo $(jobVar)
+            name: echoStep
+  - ${{ if eq(parameters.deploy, true) }}:
+    - stage: Deploy
+      variables:
+        - group: deploy-secrets
+";
+
+    fn tree() -> ScopeTree {
+        let yaml: Value = serde_yaml::from_str(PIPELINE).unwrap();
+        ScopeTree::from_value(&yaml)
+    }
+
+    fn step_path() -> YamlPath {
+        YamlPath::root()
+            .key("stages")
+            .index(0)
+            .key("jobs")
+            .index(0)
+            .key("steps")
+            .index(0)
+            .key("script")
+    }
+
+    #[test]
+    fn test_root_scope_definitions() {
+        let tree = tree();
+        let root = &tree.scopes()[0];
+        assert_eq!(root.kind, ScopeKind::Root);
+        assert_eq!(root.groups, vec!["shared"]);
+        assert_eq!(root.inline_variables, vec!["rootVar"]);
+    }
+
+    #[test]
+    fn test_chain_from_step() {
+        let tree = tree();
+        let chain = tree.chain(&step_path());
+        let kinds: Vec<ScopeKind> = chain.iter().map(|s| s.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![ScopeKind::Root, ScopeKind::Stage, ScopeKind::Job, ScopeKind::Step]
+        );
+        assert_eq!(chain[1].groups, vec!["build-secrets"]);
+        assert_eq!(chain[2].inline_variables, vec!["jobVar"]);
+    }
+
+    #[test]
+    fn test_variable_scope_skips_steps() {
+        let tree = tree();
+        let scope = tree.variable_scope(&step_path());
+        assert_eq!(scope.kind, ScopeKind::Job);
+        assert_eq!(scope.label, "stage 'Build' / job 'Compile'");
+
+        let step = tree.step_at(&step_path()).expect("step scope");
+        assert_eq!(step.name.as_deref(), Some("echoStep"));
+    }
+
+    #[test]
+    fn test_conditional_stage_gets_scope() {
+        let tree = tree();
+        let deploy = tree
+            .scopes()
+            .iter()
+            .find(|s| s.name.as_deref() == Some("Deploy"))
+            .expect("Deploy stage scope");
+        assert_eq!(deploy.groups, vec!["deploy-secrets"]);
+        assert_eq!(
+            deploy.path.to_string(),
+            "stages[1]['${{ if eq(parameters.deploy, true) }}'][0]"
+        );
+    }
+
+    #[test]
+    fn test_template_tree_inherits_insertion_scopes() {
+        let pipeline = tree();
+        let insertion = YamlPath::root().key("stages").index(0).key("jobs").index(0);
+        let inherited: Vec<Scope> = pipeline.chain(&insertion).into_iter().cloned().collect();
+
+        let template: Value = serde_yaml::from_str(
+            "steps:\n  - script: echo $(jobVar)\n    displayName: Echo\n",
+        )
+        .unwrap();
+        let tree = ScopeTree::for_template(
+            &template,
+            inherited,
+            "stage 'Build' / job 'Compile' / template 'steps.yml'".to_string(),
+        );
+
+        let path = YamlPath::root().key("steps").index(0).key("script");
+        let chain = tree.chain(&path);
+        let kinds: Vec<ScopeKind> = chain.iter().map(|s| s.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                ScopeKind::Root,
+                ScopeKind::Stage,
+                ScopeKind::Job,
+                ScopeKind::Root,
+                ScopeKind::Step
+            ]
+        );
+        assert_eq!(
+            chain[4].label,
+            "stage 'Build' / job 'Compile' / template 'steps.yml' / step 'Echo'"
+        );
+        assert_eq!(chain[2].inline_variables, vec!["jobVar"]);
+    }
+
+    #[test]
+    fn test_add_definitions_to_enclosing_scope() {
+        let mut tree = tree();
+        let path = YamlPath::root().key("stages").index(0).key("variables").index(1);
+        tree.add_definitions(&path, &["from-template".to_string()], &["templateVar".to_string()]);
+
+        let build = &tree.scopes()[1];
+        assert_eq!(build.groups, vec!["build-secrets", "from-template"]);
+        assert_eq!(build.inline_variables, vec!["templateVar"]);
+    }
+
+    #[test]
+    fn test_extends_parameter_stages_get_scopes() {
+        let yaml: Value = serde_yaml::from_str(
+            "
+variables:
+  - group: shared
+extends:
+  template: base.yml
+  parameters:
+    stages:
+      - stage: Build
+        variables:
+          - group: build-secrets
+        jobs:
+          - job: Compile
+            steps:
+              - script: echo hi
+",
+        )
+        .unwrap();
+        let tree = ScopeTree::from_value(&yaml);
+
+        let path = YamlPath::root()
+            .key("extends")
+            .key("parameters")
+            .key("stages")
+            .index(0)
+            .key("jobs")
+            .index(0);
+        let scope = tree.variable_scope(&path);
+        assert_eq!(scope.kind, ScopeKind::Job);
+        assert_eq!(scope.label, "stage 'Build' / job 'Compile'");
+
+        let groups: Vec<&String> = tree.chain(&path).iter().flat_map(|s| s.groups.iter()).collect();
+        assert_eq!(groups, vec!["shared", "build-secrets"]);
+    }
+}
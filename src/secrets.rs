@@ -0,0 +1,243 @@
+//! Checks for secret variables used where Azure DevOps cannot provide their value
+//!
+//! Secret variables from variable groups and the pipeline settings are masked in logs and
+//! are not available to conditions or template expressions. Unlike regular variables they are
+//! not exposed to scripts as environment variables; a step has to map them in its `env:`
+//! section.
+
+use crate::parser::{
+    environment_variable_name, variable_names_match, EnvironmentReference, VariableReference, VariableSyntax,
+};
+use crate::precedence::{definition_sites, DefinitionSite};
+use crate::scope::{ScopeTree, SCRIPT_STEP_KEYS};
+use crate::span::{PathSegment, SourceSpan, YamlPath};
+use crate::validator::{GroupVariable, PipelineVariable};
+
+/// How a secret variable is misused
+#[derive(Debug, Clone, PartialEq)]
+pub enum SecretMisuseKind {
+    /// Read in a `condition:`, where secrets are not available
+    Condition,
+    /// Read in a `${{ }}` template expression, which is expanded before secrets are available
+    TemplateExpression,
+    /// Read in a `displayName`, where its value is masked
+    DisplayName,
+    /// Read by a script as an environment variable the step does not map in `env:`
+    UnmappedEnvironment {
+        /// Name of the environment variable the script reads
+        environment_name: String,
+    },
+}
+
+/// A secret variable used where its value is not available
+#[derive(Debug, Clone, PartialEq)]
+pub struct SecretMisuse {
+    /// Name of the secret variable where it is defined
+    pub variable_name: String,
+    /// Where the secret is defined, e.g. `group 'app-secrets' in pipeline root`
+    pub defined_in: String,
+    /// Kind of misuse
+    pub kind: SecretMisuseKind,
+    /// Location of the reference
+    pub span: SourceSpan,
+}
+
+impl SecretMisuse {
+    /// Whether the misuse breaks the pipeline (a masked display name only hides the value)
+    pub fn is_error(&self) -> bool {
+        self.kind != SecretMisuseKind::DisplayName
+    }
+
+    /// Describe the misuse and how to fix it
+    pub fn message(&self) -> String {
+        let variable = format!("Secret variable '{}' ({})", self.variable_name, self.defined_in);
+        match &self.kind {
+            SecretMisuseKind::Condition => {
+                format!("{variable} is used in a condition; secrets are not available to conditions")
+            }
+            SecretMisuseKind::TemplateExpression => format!(
+                "{variable} is used in a template expression; secrets are not available when the pipeline is compiled"
+            ),
+            SecretMisuseKind::DisplayName => {
+                format!("{variable} is used in a displayName, where its value is shown as ***")
+            }
+            SecretMisuseKind::UnmappedEnvironment { environment_name } => format!(
+                "{variable} is read as ${environment_name}, but secrets are not exposed as environment variables; map it in the step's env: ({environment_name}: $({}))",
+                self.variable_name
+            ),
+        }
+    }
+}
+
+/// Find secret variables used in conditions, template expressions, display names, or read by
+/// scripts without an `env:` mapping
+///
+/// A reference is checked against the definition that wins where it is used, so an inline
+/// variable shadowing a secret is not reported.
+///
+/// # Arguments
+/// * `references` - Variable references found in the document
+/// * `environment_references` - Environment variables read by scripts of the document
+/// * `scopes` - Scope tree of the document
+/// * `group_variables` - Variables of the linked groups, with their secret flags
+/// * `pipeline_variables` - Variables of the pipeline definition, with their secret flags
+///
+/// # Returns
+/// * `Vec<SecretMisuse>` - Findings in file order
+pub fn find_secret_misuse(
+    references: &[VariableReference],
+    environment_references: &[EnvironmentReference],
+    scopes: &ScopeTree,
+    group_variables: &[GroupVariable],
+    pipeline_variables: &[PipelineVariable],
+) -> Vec<SecretMisuse> {
+    let mut findings = Vec::new();
+
+    let secret_at = |name: &str, span: &SourceSpan| -> Option<DefinitionSite> {
+        let chain = scopes.chain(&span.yaml_path);
+        definition_sites(name, &chain, group_variables, pipeline_variables)
+            .pop()
+            .filter(|site| site.is_secret)
+    };
+
+    for reference in references {
+        let path = &reference.span.yaml_path;
+        let kind = if reference.syntax == VariableSyntax::TemplateExpression {
+            SecretMisuseKind::TemplateExpression
+        } else if path.contains_key("condition") {
+            SecretMisuseKind::Condition
+        } else if path.contains_key("displayName") {
+            SecretMisuseKind::DisplayName
+        } else {
+            continue;
+        };
+
+        if let Some(site) = secret_at(&reference.name, &reference.span) {
+            findings.push(SecretMisuse {
+                variable_name: site.name.clone(),
+                defined_in: site.describe(),
+                kind,
+                span: reference.span.clone(),
+            });
+        }
+    }
+
+    let secret_names: Vec<&str> = group_variables
+        .iter()
+        .filter(|v| v.is_secret)
+        .map(|v| v.name.as_str())
+        .chain(pipeline_variables.iter().filter(|v| v.is_secret).map(|v| v.name.as_str()))
+        .collect();
+
+    // Only scripts read environment variables; `$NAME` elsewhere, e.g. in a displayName, is text
+    for reference in environment_references.iter().filter(|r| is_script(&r.span.yaml_path)) {
+        let Some(step) = scopes.step_at(&reference.span.yaml_path) else {
+            continue;
+        };
+        if step.env.iter().any(|key| variable_names_match(key, &reference.name)) {
+            continue;
+        }
+
+        let secret = secret_names
+            .iter()
+            .filter(|name| variable_names_match(&environment_variable_name(name), &reference.name))
+            .find_map(|name| secret_at(name, &reference.span));
+        if let Some(site) = secret {
+            findings.push(SecretMisuse {
+                variable_name: site.name.clone(),
+                defined_in: site.describe(),
+                kind: SecretMisuseKind::UnmappedEnvironment {
+                    environment_name: reference.name.clone(),
+                },
+                span: reference.span.clone(),
+            });
+        }
+    }
+
+    findings.sort_by_key(|finding| finding.span.byte_range.start);
+    findings
+}
+
+/// Task inputs that hold a script or the arguments passed to one
+const SCRIPT_INPUTS: &[&str] = &["script", "inlineScript", "arguments"];
+
+/// Whether a YAML path points into a value run by a shell: a script step or a script input
+fn is_script(path: &YamlPath) -> bool {
+    let key = |segment: &PathSegment, keys: &[&str]| {
+        matches!(segment, PathSegment::Key(key) if keys.contains(&key.as_str()))
+    };
+    match path.segments() {
+        [.., inputs, input] if key(inputs, &["inputs"]) => key(input, SCRIPT_INPUTS),
+        [.., last] => key(last, SCRIPT_STEP_KEYS),
+        [] => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{extract_environment_references, extract_variable_reference_spans_from_content};
+
+    const PIPELINE: &str = "\
+variables:
+  - group: app-secrets
+  - name: Shadowed
+    value: plain
+jobs:
+  - job: Deploy
+    condition: eq(variables['ApiKey'], 'x')
+    steps:
+      - script: echo $API_KEY $SHADOWED
+        displayName: Deploy with $(ApiKey)
+      - bash: ./deploy.sh \"$API_KEY\"
+        env:
+          API_KEY: $(Api.Key)
+      - ${{ if eq(variables.ApiKey, 'x') }}:
+        - script: echo %API_KEY%
+      - script: echo done
+        displayName: costs $API_KEY
+        condition: ne('$API_KEY', '')
+";
+
+    fn secret(name: &str) -> GroupVariable {
+        GroupVariable {
+            name: name.to_string(),
+            group: "app-secrets".to_string(),
+            value: None,
+            is_secret: true,
+        }
+    }
+
+    #[test]
+    fn test_find_secret_misuse() {
+        let yaml: serde_yaml::Value = serde_yaml::from_str(PIPELINE).unwrap();
+        let scopes = ScopeTree::from_value(&yaml);
+        let references = extract_variable_reference_spans_from_content(PIPELINE, "azure-pipelines.yml").unwrap();
+        let environment = extract_environment_references(PIPELINE, "azure-pipelines.yml");
+        let group_variables = vec![secret("ApiKey"), secret("Api.Key"), secret("Shadowed")];
+
+        let findings = find_secret_misuse(&references, &environment, &scopes, &group_variables, &[]);
+        let kinds: Vec<(&str, &SecretMisuseKind, usize)> = findings
+            .iter()
+            .map(|f| (f.variable_name.as_str(), &f.kind, f.span.line))
+            .collect();
+        let unmapped = SecretMisuseKind::UnmappedEnvironment {
+            environment_name: "API_KEY".to_string(),
+        };
+        assert_eq!(
+            kinds,
+            vec![
+                ("ApiKey", &SecretMisuseKind::Condition, 7),
+                ("Api.Key", &unmapped, 9),
+                ("ApiKey", &SecretMisuseKind::DisplayName, 10),
+                ("ApiKey", &SecretMisuseKind::TemplateExpression, 14),
+                ("Api.Key", &unmapped, 15),
+            ]
+        );
+        assert!(!findings[2].is_error());
+        assert_eq!(
+            findings[0].message(),
+            "Secret variable 'ApiKey' (group 'app-secrets' in pipeline root) is used in a condition; secrets are not available to conditions"
+        );
+    }
+}
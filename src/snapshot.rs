@@ -0,0 +1,376 @@
+//! Snapshots of the variable groups and pipeline definitions of a project
+//!
+//! A snapshot is a versioned JSON file with everything validation reads from Azure DevOps. It is
+//! taken once with `azdolint snapshot` and lets later runs validate with `--offline`, without a
+//! PAT or network access. Values are only stored when asked for; secret values are never
+//! returned by Azure DevOps and are never stored.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::azure::{PipelineVariableValue, VariableGroupData, VariableValue};
+use crate::backend::{InMemoryBackend, VariableBackend};
+use crate::concurrent::map_concurrent;
+
+/// Version of the snapshot file format written by this build
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// Variable groups and pipeline definitions of a project at one point in time
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Snapshot {
+    /// Version of the file format
+    pub version: u32,
+    /// Azure DevOps organization the snapshot was taken from
+    pub organization: String,
+    /// Azure DevOps project the snapshot was taken from
+    pub project: String,
+    /// When the snapshot was taken, in seconds since the Unix epoch
+    pub created_at: u64,
+    /// Whether the values of non-secret variables are stored
+    pub includes_values: bool,
+    /// Variable groups of the project, sorted by name
+    pub variable_groups: Vec<SnapshotGroup>,
+    /// Pipelines of the project, sorted by name
+    pub pipelines: Vec<SnapshotPipeline>,
+}
+
+/// A variable group in a snapshot
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SnapshotGroup {
+    /// Variable group ID
+    pub id: i32,
+    /// Variable group name
+    pub name: String,
+    /// Variables of the group, by name
+    #[serde(default)]
+    pub variables: BTreeMap<String, SnapshotVariable>,
+}
+
+/// A variable of a group in a snapshot
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SnapshotVariable {
+    /// Value of the variable (None for secrets, or when values are not stored)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+    /// Whether the variable is secret
+    #[serde(default)]
+    pub is_secret: bool,
+}
+
+/// A pipeline definition in a snapshot
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SnapshotPipeline {
+    /// Pipeline ID
+    pub id: i32,
+    /// Pipeline name
+    pub name: String,
+    /// Variables of the pipeline settings, by name
+    #[serde(default)]
+    pub variables: BTreeMap<String, SnapshotPipelineVariable>,
+}
+
+/// A variable of a pipeline definition in a snapshot
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SnapshotPipelineVariable {
+    /// Value of the variable (None for secrets, or when values are not stored)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+    /// Whether the variable is secret
+    #[serde(default)]
+    pub is_secret: bool,
+    /// Whether the variable can be overridden at queue time
+    #[serde(default)]
+    pub allow_override: bool,
+}
+
+impl Snapshot {
+    /// Take a snapshot of every variable group and pipeline definition of a project
+    ///
+    /// Pipeline definitions are fetched concurrently, up to the backend's `concurrency`. A
+    /// pipeline whose definition cannot be fetched, e.g. for lack of permissions, is left out of
+    /// the snapshot with a warning rather than failing it.
+    ///
+    /// # Arguments
+    /// * `backend` - Source of the groups and pipelines, usually the REST client
+    /// * `organization` - Organization recorded in the snapshot
+    /// * `project` - Project recorded in the snapshot
+    /// * `include_values` - Whether to store the values of non-secret variables
+    ///
+    /// # Returns
+    /// * `Result<(Snapshot, Vec<String>)>` - The snapshot and a warning per pipeline left out, or
+    ///   the listing that failed
+    pub fn capture<B: VariableBackend + ?Sized>(
+        backend: &B,
+        organization: &str,
+        project: &str,
+        include_values: bool,
+    ) -> Result<(Self, Vec<String>)> {
+        let value = |value: Option<String>, is_secret: bool| value.filter(|_| include_values && !is_secret);
+
+        let mut variable_groups: Vec<SnapshotGroup> = backend
+            .list_variable_groups()?
+            .into_iter()
+            .map(|group| SnapshotGroup {
+                id: group.id,
+                name: group.name,
+                variables: group
+                    .variables
+                    .into_iter()
+                    .map(|(name, variable)| {
+                        let is_secret = variable.is_secret.unwrap_or(false);
+                        let variable = SnapshotVariable {
+                            value: value(variable.value, is_secret),
+                            is_secret,
+                        };
+                        (name, variable)
+                    })
+                    .collect(),
+            })
+            .collect();
+        variable_groups.sort_by(|a, b| a.name.cmp(&b.name).then(a.id.cmp(&b.id)));
+
+        let pipeline_infos = backend.list_pipelines()?;
+        let definitions = map_concurrent(&pipeline_infos, backend.concurrency(), |pipeline| {
+            backend.get_pipeline_variables_by_id(pipeline.id)
+        });
+
+        let mut pipelines = Vec::new();
+        let mut warnings = Vec::new();
+        for (pipeline, definition) in pipeline_infos.into_iter().zip(definitions) {
+            let definition = match definition {
+                Ok(definition) => definition,
+                Err(e) => {
+                    warnings.push(format!(
+                        "Pipeline '{}' (ID {}) is not in the snapshot: {e}",
+                        pipeline.name, pipeline.id
+                    ));
+                    continue;
+                }
+            };
+            let variables = definition
+                .into_iter()
+                .map(|(name, variable)| {
+                    let is_secret = variable.is_secret.unwrap_or(false);
+                    let variable = SnapshotPipelineVariable {
+                        value: value(variable.value, is_secret),
+                        is_secret,
+                        allow_override: variable.allow_override,
+                    };
+                    (name, variable)
+                })
+                .collect();
+            pipelines.push(SnapshotPipeline {
+                id: pipeline.id,
+                name: pipeline.name,
+                variables,
+            });
+        }
+        pipelines.sort_by(|a, b| a.name.cmp(&b.name).then(a.id.cmp(&b.id)));
+
+        let created_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+
+        let snapshot = Self {
+            version: SNAPSHOT_VERSION,
+            organization: organization.to_string(),
+            project: project.to_string(),
+            created_at,
+            includes_values: include_values,
+            variable_groups,
+            pipelines,
+        };
+        Ok((snapshot, warnings))
+    }
+
+    /// Write the snapshot as pretty-printed JSON
+    ///
+    /// # Arguments
+    /// * `path` - Path of the file to write
+    pub fn save(&self, path: &str) -> Result<()> {
+        let json = serde_json::to_string_pretty(self).context("Failed to serialize snapshot")?;
+        fs::write(path, json + "\n").with_context(|| format!("Failed to write snapshot: {path}"))
+    }
+
+    /// Read a snapshot written by `save`
+    ///
+    /// # Arguments
+    /// * `path` - Path of the snapshot file
+    ///
+    /// # Returns
+    /// * `Result<Snapshot>` - The snapshot, or an error if it cannot be read or has another version
+    pub fn load(path: &str) -> Result<Self> {
+        let content = fs::read_to_string(path).with_context(|| format!("Failed to read snapshot: {path}"))?;
+        Self::from_json(&content).with_context(|| format!("Invalid snapshot: {path}"))
+    }
+
+    /// Parse a snapshot from JSON
+    ///
+    /// # Arguments
+    /// * `json` - Content of a snapshot file
+    ///
+    /// # Returns
+    /// * `Result<Snapshot>` - The snapshot, or an error if it is malformed or has another version
+    pub fn from_json(json: &str) -> Result<Self> {
+        let value: serde_json::Value = serde_json::from_str(json).context("Failed to parse snapshot JSON")?;
+        match value.get("version").and_then(|v| v.as_u64()) {
+            Some(version) if version == u64::from(SNAPSHOT_VERSION) => {}
+            Some(version) => anyhow::bail!(
+                "Unsupported snapshot version {version} (this build reads version {SNAPSHOT_VERSION}); take a new snapshot"
+            ),
+            None => anyhow::bail!("Snapshot has no version"),
+        }
+        serde_json::from_value(value).context("Failed to parse snapshot")
+    }
+
+    /// Serve the groups and pipelines of the snapshot to the validator
+    pub fn into_backend(self) -> InMemoryBackend {
+        let mut backend = InMemoryBackend::new();
+        for group in self.variable_groups {
+            let variables = group
+                .variables
+                .into_iter()
+                .map(|(name, variable)| {
+                    let variable = VariableValue {
+                        value: variable.value,
+                        is_secret: Some(variable.is_secret),
+                    };
+                    (name, variable)
+                })
+                .collect();
+            backend = backend.with_group(VariableGroupData {
+                id: group.id,
+                name: group.name,
+                variables,
+            });
+        }
+        for pipeline in self.pipelines {
+            let variables: HashMap<String, PipelineVariableValue> = pipeline
+                .variables
+                .into_iter()
+                .map(|(name, variable)| {
+                    let variable = PipelineVariableValue {
+                        value: variable.value,
+                        is_secret: Some(variable.is_secret),
+                        allow_override: variable.allow_override,
+                    };
+                    (name, variable)
+                })
+                .collect();
+            backend = backend.with_pipeline(pipeline.id, &pipeline.name, variables);
+        }
+        backend
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::azure::PipelineInfo;
+    use crate::backend::test_backend;
+
+    /// The shared backend fixture, with a non-secret `ApiUrl` added to `app-secrets`
+    fn backend() -> InMemoryBackend {
+        let mut backend = test_backend();
+        backend.groups[0].variables.insert(
+            "ApiUrl".to_string(),
+            VariableValue {
+                value: Some("https://api.example.com".to_string()),
+                is_secret: None,
+            },
+        );
+        backend
+    }
+
+    #[test]
+    fn test_capture_and_restore() {
+        let (snapshot, warnings) = Snapshot::capture(&backend(), "myorg", "myproject", true).unwrap();
+        assert!(warnings.is_empty());
+        assert_eq!(snapshot.version, SNAPSHOT_VERSION);
+        assert_eq!(
+            snapshot.variable_groups[0].variables["ApiUrl"].value.as_deref(),
+            Some("https://api.example.com")
+        );
+        assert!(snapshot.variable_groups[0].variables["ApiKey"].is_secret);
+
+        let json = serde_json::to_string(&snapshot).unwrap();
+        let restored = Snapshot::from_json(&json).unwrap();
+        assert_eq!(restored, snapshot);
+
+        let backend = restored.into_backend();
+        assert_eq!(backend.get_variable_group("app-secrets").unwrap().id, 7);
+        assert!(backend.get_pipeline_variables("deploy").unwrap()["Region"].allow_override);
+    }
+
+    #[test]
+    fn test_capture_without_values() {
+        let (snapshot, _) = Snapshot::capture(&backend(), "myorg", "myproject", false).unwrap();
+        assert!(!snapshot.includes_values);
+        assert_eq!(snapshot.variable_groups[0].variables["ApiUrl"].value, None);
+        assert_eq!(snapshot.pipelines[0].variables["Region"].value, None);
+    }
+
+    /// Lists a pipeline whose definition cannot be fetched
+    struct UnreadablePipeline(InMemoryBackend);
+
+    impl VariableBackend for UnreadablePipeline {
+        fn get_variable_group(&self, group_name: &str) -> Result<VariableGroupData> {
+            self.0.get_variable_group(group_name)
+        }
+
+        fn get_variable_group_by_id(&self, group_id: i32) -> Result<VariableGroupData> {
+            self.0.get_variable_group_by_id(group_id)
+        }
+
+        fn list_variable_groups(&self) -> Result<Vec<VariableGroupData>> {
+            self.0.list_variable_groups()
+        }
+
+        fn list_pipelines(&self) -> Result<Vec<PipelineInfo>> {
+            let mut pipelines = self.0.list_pipelines()?;
+            pipelines.push(PipelineInfo {
+                id: 43,
+                name: "classic".to_string(),
+            });
+            Ok(pipelines)
+        }
+
+        fn get_pipeline_variables_by_id(&self, pipeline_id: i32) -> Result<HashMap<String, PipelineVariableValue>> {
+            if pipeline_id == 43 {
+                anyhow::bail!("Access denied for pipeline ID 43.");
+            }
+            self.0.get_pipeline_variables_by_id(pipeline_id)
+        }
+    }
+
+    #[test]
+    fn test_capture_skips_unreadable_pipelines() {
+        let (snapshot, warnings) =
+            Snapshot::capture(&UnreadablePipeline(backend()), "myorg", "myproject", false).unwrap();
+        assert_eq!(snapshot.pipelines.len(), 1);
+        assert_eq!(snapshot.pipelines[0].name, "deploy");
+        assert_eq!(
+            warnings,
+            vec!["Pipeline 'classic' (ID 43) is not in the snapshot: Access denied for pipeline ID 43."]
+        );
+    }
+
+    #[test]
+    fn test_rejects_other_versions() {
+        let error = Snapshot::from_json(r#"{"version": 2}"#).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Unsupported snapshot version 2 (this build reads version 1); take a new snapshot"
+        );
+        assert!(Snapshot::from_json("{}").is_err());
+    }
+}
@@ -0,0 +1,596 @@
+//! Source locations and YAML paths for references found in pipeline files
+
+use std::fmt;
+use std::ops::Range;
+
+/// A single segment of a YAML path
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum PathSegment {
+    /// Key inside a mapping
+    Key(String),
+    /// Index inside a sequence
+    Index(usize),
+}
+
+/// Path to a node inside a YAML document, e.g. `stages[1].jobs[0].steps[3].script`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct YamlPath(Vec<PathSegment>);
+
+impl YamlPath {
+    /// Path of the document root
+    pub fn root() -> Self {
+        Self::default()
+    }
+
+    /// Segments of the path from the root
+    pub fn segments(&self) -> &[PathSegment] {
+        &self.0
+    }
+
+    /// Returns true if this is the document root
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Path of a mapping key below this node
+    pub fn key(&self, key: &str) -> Self {
+        let mut segments = self.0.clone();
+        segments.push(PathSegment::Key(key.to_string()));
+        Self(segments)
+    }
+
+    /// Path of a sequence item below this node
+    pub fn index(&self, index: usize) -> Self {
+        let mut segments = self.0.clone();
+        segments.push(PathSegment::Index(index));
+        Self(segments)
+    }
+
+    /// Returns true if `prefix` is an ancestor of (or equal to) this path
+    pub fn starts_with(&self, prefix: &YamlPath) -> bool {
+        self.0.starts_with(&prefix.0)
+    }
+
+    /// Returns true if the path comes before `other` in document order, e.g. `steps[1]` before
+    /// `steps[3].script`; a path does not precede its own children, and sibling mapping keys
+    /// are not ordered
+    pub fn precedes(&self, other: &YamlPath) -> bool {
+        match self.0.iter().zip(other.0.iter()).find(|(a, b)| a != b) {
+            Some((PathSegment::Index(a), PathSegment::Index(b))) => a < b,
+            _ => false,
+        }
+    }
+
+    /// Returns true if any segment of the path is the given key
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.0
+            .iter()
+            .any(|segment| matches!(segment, PathSegment::Key(k) if k == key))
+    }
+
+    /// Last key segment of the path, if the path ends with a key
+    pub fn last_key(&self) -> Option<&str> {
+        match self.0.last() {
+            Some(PathSegment::Key(key)) => Some(key),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for YamlPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, segment) in self.0.iter().enumerate() {
+            match segment {
+                PathSegment::Key(key) if is_plain_key(key) => {
+                    if i > 0 {
+                        write!(f, ".")?;
+                    }
+                    write!(f, "{key}")?;
+                }
+                PathSegment::Key(key) => write!(f, "['{key}']")?,
+                PathSegment::Index(index) => write!(f, "[{index}]")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Keys that can be printed without brackets in a YAML path
+fn is_plain_key(key: &str) -> bool {
+    !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+/// Location of a reference in a pipeline or template file
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SourceSpan {
+    /// Path to the file containing the reference
+    pub file: String,
+    /// Line number (1-based)
+    pub line: usize,
+    /// Column number in characters (1-based)
+    pub column: usize,
+    /// Byte range of the reference within the file
+    pub byte_range: Range<usize>,
+    /// YAML path of the node containing the reference
+    pub yaml_path: YamlPath,
+}
+
+impl fmt::Display for SourceSpan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.column)
+    }
+}
+
+/// Per-line information computed by the line index
+#[derive(Debug, Clone, Default)]
+struct LineInfo {
+    /// YAML path of the node the line belongs to
+    path: YamlPath,
+    /// Byte range of an inline scalar value on this line (quotes excluded)
+    value: Option<Range<usize>>,
+    /// Byte range of the mapping key on this line (quotes excluded)
+    key: Option<Range<usize>>,
+}
+
+/// Kind of block container tracked while indexing
+#[derive(Debug)]
+enum ContainerKind {
+    Mapping { last_key: Option<YamlPath> },
+    Sequence { next_index: usize, last_item: Option<YamlPath> },
+}
+
+/// A block mapping or sequence open at a given indentation
+#[derive(Debug)]
+struct Container {
+    indent: usize,
+    path: YamlPath,
+    kind: ContainerKind,
+}
+
+/// Maps byte offsets in a YAML document to line/column positions and YAML paths
+///
+/// The index works on the raw text so that positions survive any later processing
+/// of the parsed YAML. It understands block mappings, block sequences, block scalars
+/// (`|`, `>`) and multi-line plain scalars; flow collections are treated as scalars.
+#[derive(Debug, Clone)]
+pub struct LineIndex {
+    content: String,
+    line_starts: Vec<usize>,
+    lines: Vec<LineInfo>,
+}
+
+impl LineIndex {
+    /// Build a line index for the given YAML content
+    pub fn new(content: &str) -> Self {
+        let mut line_starts = Vec::new();
+        let mut lines = Vec::new();
+        let mut stack: Vec<Container> = Vec::new();
+        // Owner indentation and path of a block scalar or multi-line scalar in progress
+        let mut continuation: Option<(usize, YamlPath)> = None;
+
+        let mut offset = 0;
+        for raw_line in content.split_inclusive('\n') {
+            line_starts.push(offset);
+            let text = raw_line.trim_end_matches('\n').trim_end_matches('\r');
+            let indent = text.len() - text.trim_start_matches(' ').len();
+            let rest = &text[indent..];
+
+            let continued = continuation
+                .as_ref()
+                .filter(|(owner, _)| rest.trim().is_empty() || indent > *owner)
+                .map(|(_, path)| path.clone());
+
+            let info = if let Some(path) = continued {
+                LineInfo { path, value: None, key: None }
+            } else {
+                continuation = None;
+                if rest.trim().is_empty() || rest.starts_with('#') {
+                    LineInfo {
+                        path: stack.last().map(|c| c.path.clone()).unwrap_or_default(),
+                        value: None,
+                        key: None,
+                    }
+                } else if indent == 0 && (rest.starts_with("---") || rest.starts_with("...")) {
+                    stack.clear();
+                    LineInfo::default()
+                } else {
+                    index_line(&mut stack, &mut continuation, indent, rest, offset)
+                }
+            };
+
+            lines.push(info);
+            offset += raw_line.len();
+        }
+
+        Self {
+            content: content.to_string(),
+            line_starts,
+            lines,
+        }
+    }
+
+    /// Zero-based line number containing the given byte offset
+    fn line_of(&self, offset: usize) -> usize {
+        match self.line_starts.binary_search(&offset) {
+            Ok(line) => line,
+            Err(next) => next.saturating_sub(1),
+        }
+    }
+
+    /// YAML path of the node containing the given byte offset
+    pub fn path_at(&self, offset: usize) -> YamlPath {
+        self.lines
+            .get(self.line_of(offset))
+            .map(|info| info.path.clone())
+            .unwrap_or_default()
+    }
+
+    /// Build a source span for a byte range of the indexed content
+    pub fn span(&self, file: &str, byte_range: Range<usize>) -> SourceSpan {
+        let line = self.line_of(byte_range.start);
+        let line_start = self.line_starts.get(line).copied().unwrap_or(0);
+        let column = self
+            .content
+            .get(line_start..byte_range.start)
+            .map(|prefix| prefix.chars().count())
+            .unwrap_or(0)
+            + 1;
+
+        SourceSpan {
+            file: file.to_string(),
+            line: line + 1,
+            column,
+            yaml_path: self.path_at(byte_range.start),
+            byte_range,
+        }
+    }
+
+    /// Iterate over all inline scalar values as `(yaml path, byte range)` pairs
+    ///
+    /// Only values written on the same line as their key or sequence indicator are
+    /// reported; block scalars and flow collections are skipped.
+    pub fn scalar_values(&self) -> impl Iterator<Item = (&YamlPath, Range<usize>)> {
+        self.lines
+            .iter()
+            .filter_map(|info| info.value.clone().map(|value| (&info.path, value)))
+    }
+
+    /// Iterate over all block mapping keys as `(yaml path of the key's value, byte range)` pairs
+    pub fn keys(&self) -> impl Iterator<Item = (&YamlPath, Range<usize>)> {
+        self.lines
+            .iter()
+            .filter_map(|info| info.key.clone().map(|key| (&info.path, key)))
+    }
+
+    /// The text of the indexed content for a byte range
+    pub fn text(&self, byte_range: Range<usize>) -> &str {
+        self.content.get(byte_range).unwrap_or("")
+    }
+}
+
+/// Compute the path of a single non-empty line and update the container stack
+fn index_line(
+    stack: &mut Vec<Container>,
+    continuation: &mut Option<(usize, YamlPath)>,
+    indent: usize,
+    rest: &str,
+    line_offset: usize,
+) -> LineInfo {
+    while stack.last().is_some_and(|c| c.indent > indent) {
+        stack.pop();
+    }
+
+    let mut col = indent;
+    let mut rest = rest;
+    let mut item_path: Option<YamlPath> = None;
+
+    loop {
+        if rest == "-" || rest.starts_with("- ") || rest.starts_with("-\t") {
+            let path = match stack.last_mut() {
+                Some(Container {
+                    indent: seq_indent,
+                    path,
+                    kind: ContainerKind::Sequence { next_index, last_item },
+                }) if *seq_indent == col => {
+                    let item = path.index(*next_index);
+                    *next_index += 1;
+                    *last_item = Some(item.clone());
+                    item
+                }
+                _ => {
+                    let parent = child_container_path(stack);
+                    let item = parent.index(0);
+                    stack.push(Container {
+                        indent: col,
+                        path: parent,
+                        kind: ContainerKind::Sequence {
+                            next_index: 1,
+                            last_item: Some(item.clone()),
+                        },
+                    });
+                    item
+                }
+            };
+
+            let after = &rest[1..];
+            let trimmed = after.trim_start();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                return LineInfo { path, value: None, key: None };
+            }
+            col += 1 + (after.len() - trimmed.len());
+            rest = trimmed;
+            item_path = Some(path);
+            continue;
+        }
+
+        if let Some((key, value_start)) = parse_key(rest) {
+            if matches!(
+                stack.last(),
+                Some(Container { indent: i, kind: ContainerKind::Sequence { .. }, .. }) if *i == col
+            ) {
+                stack.pop();
+            }
+            let is_mapping_here = matches!(
+                stack.last(),
+                Some(Container { indent: i, kind: ContainerKind::Mapping { .. }, .. }) if *i == col
+            );
+            if !is_mapping_here {
+                let parent = item_path.clone().unwrap_or_else(|| child_container_path(stack));
+                stack.push(Container {
+                    indent: col,
+                    path: parent,
+                    kind: ContainerKind::Mapping { last_key: None },
+                });
+            }
+
+            let container = stack.last_mut().expect("mapping container was just ensured");
+            let key_path = container.path.key(&key);
+            if let ContainerKind::Mapping { last_key } = &mut container.kind {
+                *last_key = Some(key_path.clone());
+            }
+
+            let value_text = &rest[value_start..];
+            let value_offset = line_offset + col + value_start;
+            let inline = value_text.trim_start();
+            if !inline.is_empty() && !inline.starts_with('#') {
+                // Deeper lines can only continue this value (block, flow or multi-line scalar)
+                *continuation = Some((col, key_path.clone()));
+            }
+            let value = if inline.starts_with('|') || inline.starts_with('>') {
+                None
+            } else {
+                scalar_value_range(value_text, value_offset)
+            };
+            let key_start = line_offset + col + usize::from(rest.starts_with(['"', '\'']));
+            return LineInfo {
+                path: key_path,
+                value,
+                key: Some(key_start..key_start + key.len()),
+            };
+        }
+
+        // Plain scalar: either a sequence item or a stray continuation line
+        let path = item_path
+            .clone()
+            .or_else(|| stack.last().map(|c| c.path.clone()))
+            .unwrap_or_default();
+        if item_path.is_some() {
+            *continuation = Some((indent, path.clone()));
+        }
+        let value = scalar_value_range(rest, line_offset + col);
+        return LineInfo { path, value, key: None };
+    }
+}
+
+/// Path that a newly opened container below the top of the stack belongs to
+fn child_container_path(stack: &[Container]) -> YamlPath {
+    match stack.last() {
+        Some(Container { kind: ContainerKind::Mapping { last_key: Some(key) }, .. }) => key.clone(),
+        Some(Container { kind: ContainerKind::Sequence { last_item: Some(item), .. }, .. }) => {
+            item.clone()
+        }
+        Some(container) => container.path.clone(),
+        None => YamlPath::root(),
+    }
+}
+
+/// Parse a `key:` prefix, returning the unquoted key and the byte offset after the colon
+fn parse_key(rest: &str) -> Option<(String, usize)> {
+    let first = rest.chars().next()?;
+
+    let (key, after_key) = if first == '"' || first == '\'' {
+        let close = rest[1..].find(first)? + 1;
+        (rest[1..close].to_string(), close + 1)
+    } else if rest.starts_with("${{") {
+        let close = rest.find("}}")? + 2;
+        (rest[..close].to_string(), close)
+    } else if matches!(first, '{' | '[' | '|' | '>' | '*' | '!' | '&' | '?') {
+        return None;
+    } else {
+        let bytes = rest.as_bytes();
+        let mut colon = None;
+        for (i, &b) in bytes.iter().enumerate() {
+            if b == b'#' && i > 0 && bytes[i - 1] == b' ' {
+                return None;
+            }
+            if b == b':' && (i + 1 == bytes.len() || bytes[i + 1] == b' ' || bytes[i + 1] == b'\t') {
+                colon = Some(i);
+                break;
+            }
+        }
+        let colon = colon?;
+        (rest[..colon].trim_end().to_string(), colon)
+    };
+
+    let remainder = &rest[after_key..];
+    let spaces = remainder.len() - remainder.trim_start().len();
+    let remainder = remainder.trim_start();
+    if !remainder.starts_with(':') {
+        return None;
+    }
+    let after_colon = &remainder[1..];
+    if !(after_colon.is_empty() || after_colon.starts_with(' ') || after_colon.starts_with('\t')) {
+        return None;
+    }
+    Some((key, after_key + spaces + 1))
+}
+
+/// Byte range of an inline scalar value, excluding surrounding quotes and comments
+fn scalar_value_range(text: &str, offset: usize) -> Option<Range<usize>> {
+    let leading = text.len() - text.trim_start().len();
+    let value = text.trim_start();
+    if value.is_empty() || value.starts_with('#') || value.starts_with('{') || value.starts_with('[') {
+        return None;
+    }
+
+    let start = offset + leading;
+    let first = value.chars().next()?;
+    if first == '"' || first == '\'' {
+        let close = value[1..].find(first).map(|i| i + 1).unwrap_or(value.len());
+        return Some(start + 1..start + close);
+    }
+
+    let end = value.find(" #").unwrap_or(value.len());
+    Some(start..start + value[..end].trim_end().len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PIPELINE: &str = "\
+trigger: none
+
+variables:
+  - group: 'shared'
+  - name: buildConfig
+    value: Release
+
+stages:
+  - stage: Build
+    jobs:
+      - job: BuildJob
+        steps:
+          - script: echo $(buildConfig)
+          - bash: |
+              echo $(ApiKey)
+              echo done
+  - stage: Deploy
+    jobs:
+      - job: DeployJob
+        steps:
+          - script: echo $(DeployToken)
+";
+
+    fn offset_of(needle: &str) -> usize {
+        PIPELINE.find(needle).expect("needle not in fixture")
+    }
+
+    #[test]
+    fn test_path_display() {
+        let path = YamlPath::root()
+            .key("stages")
+            .index(1)
+            .key("jobs")
+            .index(0)
+            .key("steps")
+            .index(3)
+            .key("script");
+        assert_eq!(path.to_string(), "stages[1].jobs[0].steps[3].script");
+    }
+
+    #[test]
+    fn test_path_precedes() {
+        let steps = YamlPath::root().key("jobs").index(0).key("steps");
+        assert!(steps.index(1).precedes(&steps.index(3).key("script")));
+        assert!(!steps.index(3).precedes(&steps.index(1)));
+        assert!(!steps.index(1).precedes(&steps.index(1).key("script")));
+        assert!(steps.index(1).precedes(&YamlPath::root().key("jobs").index(1).key("steps").index(0)));
+        assert!(!steps.index(1).key("bash").precedes(&steps.index(1).key("name")));
+    }
+
+    #[test]
+    fn test_path_display_quotes_template_expressions() {
+        let path = YamlPath::root()
+            .key("variables")
+            .index(0)
+            .key("${{ if eq(parameters.env, 'dev') }}");
+        assert_eq!(
+            path.to_string(),
+            "variables[0]['${{ if eq(parameters.env, 'dev') }}']"
+        );
+    }
+
+    #[test]
+    fn test_path_of_inline_step_value() {
+        let index = LineIndex::new(PIPELINE);
+        let path = index.path_at(offset_of("$(buildConfig)"));
+        assert_eq!(path.to_string(), "stages[0].jobs[0].steps[0].script");
+    }
+
+    #[test]
+    fn test_path_of_block_scalar_line() {
+        let index = LineIndex::new(PIPELINE);
+        let path = index.path_at(offset_of("$(ApiKey)"));
+        assert_eq!(path.to_string(), "stages[0].jobs[0].steps[1].bash");
+    }
+
+    #[test]
+    fn test_path_after_block_scalar_returns_to_outer_sequence() {
+        let index = LineIndex::new(PIPELINE);
+        let path = index.path_at(offset_of("$(DeployToken)"));
+        assert_eq!(path.to_string(), "stages[1].jobs[0].steps[0].script");
+    }
+
+    #[test]
+    fn test_sequence_at_same_indent_as_key() {
+        let content = "variables:\n- group: a\n- group: b\nstages:\n- stage: X\n";
+        let index = LineIndex::new(content);
+        let path = index.path_at(content.find("group: b").unwrap());
+        assert_eq!(path.to_string(), "variables[1].group");
+        let path = index.path_at(content.find("stage: X").unwrap());
+        assert_eq!(path.to_string(), "stages[0].stage");
+    }
+
+    #[test]
+    fn test_span_line_and_column() {
+        let index = LineIndex::new(PIPELINE);
+        let start = offset_of("$(DeployToken)");
+        let span = index.span("azure-pipelines.yml", start..start + "$(DeployToken)".len());
+        assert_eq!(span.line, 21);
+        assert_eq!(span.column, 26);
+        assert_eq!(span.to_string(), "azure-pipelines.yml:21:26");
+    }
+
+    #[test]
+    fn test_scalar_values_strip_quotes() {
+        let index = LineIndex::new(PIPELINE);
+        let groups: Vec<&str> = index
+            .scalar_values()
+            .filter(|(path, _)| path.last_key() == Some("group"))
+            .map(|(_, range)| index.text(range))
+            .collect();
+        assert_eq!(groups, vec!["shared"]);
+    }
+
+    #[test]
+    fn test_keys_strip_quotes() {
+        let content = "variables:\n  buildConfig: Release\n  'quoted.name': x\n";
+        let index = LineIndex::new(content);
+        let keys: Vec<(String, &str)> = index
+            .keys()
+            .map(|(path, range)| (path.to_string(), index.text(range)))
+            .collect();
+        assert_eq!(
+            keys,
+            vec![
+                ("variables".to_string(), "variables"),
+                ("variables.buildConfig".to_string(), "buildConfig"),
+                ("variables['quoted.name']".to_string(), "quoted.name"),
+            ]
+        );
+    }
+}
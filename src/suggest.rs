@@ -0,0 +1,140 @@
+//! "Did you mean" suggestions for misspelled variable and group names
+
+/// Find the candidate closest to a name that could not be resolved
+///
+/// Names are compared case-insensitively. Candidates made of the same words are preferred
+/// (`db_password` for `DbPassword`, `StringConnection` for `ConnectionString`), then the
+/// candidate with the smallest edit distance. Candidates more than a third of the name's
+/// length away are never suggested; on ties the earliest candidate wins.
+///
+/// # Arguments
+/// * `name` - Name that could not be resolved
+/// * `candidates` - Known names, each with data describing where it is defined
+///
+/// # Returns
+/// * `Option<(&str, T)>` - The closest candidate, if any is close enough
+pub fn closest<'a, T>(name: &str, candidates: impl IntoIterator<Item = (&'a str, T)>) -> Option<(&'a str, T)> {
+    let lowered = name.to_lowercase();
+    let tokens = sorted_tokens(name);
+    let max_distance = (lowered.chars().count() / 3).max(1);
+
+    let mut best: Option<(usize, &'a str, T)> = None;
+    for (candidate, data) in candidates {
+        let candidate_lowered = candidate.to_lowercase();
+        if candidate_lowered == lowered {
+            continue;
+        }
+
+        let distance = if sorted_tokens(candidate) == tokens {
+            0
+        } else {
+            edit_distance(&lowered, &candidate_lowered)
+        };
+        if distance > max_distance {
+            continue;
+        }
+        if best.as_ref().is_none_or(|(best_distance, _, _)| distance < *best_distance) {
+            best = Some((distance, candidate, data));
+        }
+    }
+
+    best.map(|(_, candidate, data)| (candidate, data))
+}
+
+/// Split a name into lowercase words at separators and camelCase boundaries, sorted
+fn sorted_tokens(name: &str) -> Vec<String> {
+    let chars: Vec<char> = name.chars().collect();
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+
+    for (i, &c) in chars.iter().enumerate() {
+        if !c.is_alphanumeric() {
+            if !current.is_empty() {
+                tokens.push(std::mem::take(&mut current));
+            }
+            continue;
+        }
+
+        // `apiKey` -> api|Key, `APIKey` -> API|Key
+        let boundary = i > 0
+            && c.is_uppercase()
+            && (chars[i - 1].is_lowercase()
+                || (chars[i - 1].is_uppercase() && chars.get(i + 1).is_some_and(|next| next.is_lowercase())));
+        if boundary && !current.is_empty() {
+            tokens.push(std::mem::take(&mut current));
+        }
+        current.extend(c.to_lowercase());
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+
+    tokens.sort();
+    tokens
+}
+
+/// Edit distance counting insertions, deletions, substitutions and swaps of adjacent characters
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut rows = vec![vec![0; b.len() + 1]; a.len() + 1];
+
+    for (i, row) in rows.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in rows[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut distance = (rows[i - 1][j] + 1)
+                .min(rows[i][j - 1] + 1)
+                .min(rows[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(rows[i - 2][j - 2] + 1);
+            }
+            rows[i][j] = distance;
+        }
+    }
+
+    rows[a.len()][b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("connectionstring", "connectionstring"), 0);
+        assert_eq!(edit_distance("conectionstring", "connectionstring"), 1);
+        assert_eq!(edit_distance("apikye", "apikey"), 1);
+        assert_eq!(edit_distance("", "abc"), 3);
+    }
+
+    #[test]
+    fn test_sorted_tokens() {
+        assert_eq!(sorted_tokens("ConnectionString"), vec!["connection", "string"]);
+        assert_eq!(sorted_tokens("db_password"), vec!["db", "password"]);
+        assert_eq!(sorted_tokens("APIKey"), vec!["api", "key"]);
+        assert_eq!(sorted_tokens("Build.Version"), vec!["build", "version"]);
+    }
+
+    #[test]
+    fn test_closest_prefers_nearest_candidate() {
+        let candidates = [("ConnectionString", 1), ("ConnectionStrings", 2), ("ApiKey", 3)];
+        assert_eq!(closest("ConectionString", candidates), Some(("ConnectionString", 1)));
+        assert_eq!(closest("StringConnection", candidates), Some(("ConnectionString", 1)));
+        assert_eq!(closest("Unrelated", candidates), None);
+    }
+
+    #[test]
+    fn test_closest_keeps_short_names_strict() {
+        let candidates = [("env", ()), ("Region", ())];
+        assert_eq!(closest("ENV", candidates), None);
+        assert_eq!(closest("ab", candidates), None);
+        assert_eq!(closest("Regoin", candidates), Some(("Region", ())));
+    }
+}
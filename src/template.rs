@@ -0,0 +1,673 @@
+//! Template expansion for pipelines
+//!
+//! Follows `- template:` entries in `stages`, `jobs`, `steps` and `variables` lists and
+//! the `extends:` entry point, including templates included by other templates. Every included file is parsed with
+//! the scopes of the place it is inserted into, so its variable references can be
+//! validated in the context they run in. Variables templates are merged into the scope
+//! that includes them.
+
+use anyhow::{Context, Result};
+use serde_yaml::{Mapping, Value};
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+use crate::expression::{prune_inactive, resolve_value, Evaluation, ExpressionContext};
+use crate::outputs::OutputIndex;
+use crate::parameters::{check_parameters, parameter_declarations, ParameterFinding};
+use crate::parser::{
+    extract_environment_references, extract_group_references_from_content,
+    extract_output_reference_spans_from_content, extract_variable_definitions_from_content,
+    extract_variable_reference_spans_from_content, resolve_template_path, template_references_in,
+    EnvironmentReference, GroupReference, OutputReference, TemplateKind, TemplateReference, VariableDefinition, VariableReference,
+};
+use crate::scope::{ScopeTree, ROOT_LABEL};
+use crate::span::{LineIndex, YamlPath};
+
+/// Maximum number of distinct template files a pipeline may include (Azure DevOps limit)
+pub const MAX_TEMPLATE_FILES: usize = 100;
+
+/// Maximum template nesting depth (Azure DevOps limit)
+pub const MAX_TEMPLATE_DEPTH: usize = 20;
+
+/// Reason a template could not be expanded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TemplateProblemKind {
+    /// The template file does not exist
+    NotFound,
+    /// The template lives in another repository (`path@repo`) and cannot be read
+    ExternalRepository,
+    /// The template includes itself, directly or indirectly
+    Cycle,
+    /// The template is nested deeper than `MAX_TEMPLATE_DEPTH`
+    DepthLimit,
+    /// The pipeline includes more than `MAX_TEMPLATE_FILES` template files
+    FileLimit,
+    /// The template file is not valid YAML
+    InvalidYaml,
+}
+
+/// A template reference that could not be expanded
+#[derive(Debug, Clone)]
+pub struct TemplateProblem {
+    /// Kind of problem
+    pub kind: TemplateProblemKind,
+    /// The offending template reference
+    pub reference: TemplateReference,
+    /// Path the template reference resolved to
+    pub resolved_path: String,
+    /// Description of the problem
+    pub message: String,
+}
+
+impl TemplateProblem {
+    /// Whether the problem fails validation
+    ///
+    /// Missing and external templates are reported as warnings because they may live in a
+    /// location the linter cannot see; cycles, limits and invalid YAML break the pipeline.
+    pub fn is_error(&self) -> bool {
+        !matches!(
+            self.kind,
+            TemplateProblemKind::NotFound | TemplateProblemKind::ExternalRepository
+        )
+    }
+}
+
+/// A template file inserted into the pipeline
+#[derive(Debug, Clone)]
+pub struct TemplateInstance {
+    /// The reference that inserts the template
+    pub reference: TemplateReference,
+    /// Path the template reference resolved to
+    pub resolved_path: String,
+    /// Nesting depth (1 for templates included directly by the pipeline)
+    pub depth: usize,
+    /// Files that include this template, outermost (the pipeline) first
+    pub included_from: Vec<String>,
+    /// Label of the scope the template is inserted into
+    pub inserted_into: String,
+    /// Declared parameters bound to the supplied values (or their defaults)
+    pub parameters: Mapping,
+    /// Problems with the parameters supplied by the reference
+    pub parameter_findings: Vec<ParameterFinding>,
+    /// `${{ }}` branches of the template that would not be compiled with these parameters
+    pub inactive: Vec<YamlPath>,
+    /// Scopes of the template, including those inherited from the insertion point
+    pub scopes: ScopeTree,
+    /// Variable references found in the template, with locations
+    pub variable_references: Vec<VariableReference>,
+    /// Output variable references found in the template, with locations
+    pub output_references: Vec<OutputReference>,
+    /// Variable group links found in the template, with locations
+    pub group_references: Vec<GroupReference>,
+    /// Inline variables defined in the template, with locations
+    pub variable_definitions: Vec<VariableDefinition>,
+    /// Environment variables read by scripts of the template, with locations
+    pub environment_references: Vec<EnvironmentReference>,
+}
+
+/// Result of expanding all templates of a pipeline
+#[derive(Debug, Clone)]
+pub struct TemplateExpansion {
+    /// Runtime parameters of the pipeline bound to the supplied values (or their defaults)
+    pub parameters: Mapping,
+    /// `${{ }}` branches of the pipeline that would not be compiled
+    pub inactive: Vec<YamlPath>,
+    /// Scopes of the pipeline, including definitions merged from variables templates
+    pub scopes: ScopeTree,
+    /// Variable references found in the pipeline file, with locations
+    pub variable_references: Vec<VariableReference>,
+    /// Output variable references found in the pipeline file, with locations
+    pub output_references: Vec<OutputReference>,
+    /// Variable group links found in the pipeline file, with locations
+    pub group_references: Vec<GroupReference>,
+    /// Inline variables defined in the pipeline file, with locations
+    pub variable_definitions: Vec<VariableDefinition>,
+    /// Environment variables read by scripts of the pipeline file, with locations
+    pub environment_references: Vec<EnvironmentReference>,
+    /// Expanded templates, each parent before the templates it includes
+    pub instances: Vec<TemplateInstance>,
+    /// Template references that could not be expanded
+    pub problems: Vec<TemplateProblem>,
+}
+
+impl TemplateExpansion {
+    /// Unique variable groups linked by the pipeline and all of its templates
+    pub fn variable_groups(&self) -> Vec<String> {
+        let mut groups = Vec::new();
+        let trees = std::iter::once(&self.scopes).chain(self.instances.iter().map(|i| &i.scopes));
+        for tree in trees {
+            for group in tree.scopes().iter().flat_map(|scope| scope.groups.iter()) {
+                if !groups.contains(group) {
+                    groups.push(group.clone());
+                }
+            }
+        }
+        groups
+    }
+
+    /// Stages, jobs and steps of the pipeline and all of its templates, used to resolve
+    /// output variable references
+    pub fn output_index(&self) -> OutputIndex<'_> {
+        let templates: Vec<&ScopeTree> = self.instances.iter().map(|i| &i.scopes).collect();
+        OutputIndex::new(&self.scopes, &templates)
+    }
+
+    /// Variable group links of the pipeline and all of its templates
+    pub fn all_group_references(&self) -> Vec<GroupReference> {
+        self.group_references
+            .iter()
+            .chain(self.instances.iter().flat_map(|i| i.group_references.iter()))
+            .cloned()
+            .collect()
+    }
+}
+
+/// Expand all templates included by a pipeline
+///
+/// Follows `- template:` entries and `extends:` recursively, detecting cycles and enforcing
+/// the Azure DevOps limits of `MAX_TEMPLATE_FILES` files and `MAX_TEMPLATE_DEPTH` nesting levels.
+/// Templates that cannot be expanded are reported as problems rather than errors.
+/// Runtime parameters take their defaults; see `expand_templates_with_parameters`.
+///
+/// # Arguments
+/// * `pipeline_path` - Path to the pipeline YAML file
+///
+/// # Returns
+/// * `Result<TemplateExpansion>` - The pipeline scopes, expanded templates and problems
+pub fn expand_templates(pipeline_path: &str) -> Result<TemplateExpansion> {
+    expand_templates_with_parameters(pipeline_path, &Mapping::new())
+}
+
+/// Expand all templates included by a pipeline, given values for its runtime parameters
+///
+/// `${{ }}` conditionals and loops are evaluated with the parameter values each document
+/// receives, and branches that would not be compiled are excluded from the expansion.
+///
+/// # Arguments
+/// * `pipeline_path` - Path to the pipeline YAML file
+/// * `parameters` - Values for the pipeline's runtime parameters (others take their defaults)
+///
+/// # Returns
+/// * `Result<TemplateExpansion>` - The pipeline scopes, expanded templates and problems
+pub fn expand_templates_with_parameters(pipeline_path: &str, parameters: &Mapping) -> Result<TemplateExpansion> {
+    let content = fs::read_to_string(pipeline_path)
+        .with_context(|| format!("Failed to read pipeline file: {pipeline_path}"))?;
+
+    let yaml: Value = serde_yaml::from_str(&content)
+        .with_context(|| format!("Failed to parse YAML in pipeline file: {pipeline_path}"))?;
+
+    let mut expander = Expander {
+        instances: Vec::new(),
+        problems: Vec::new(),
+        files: HashSet::new(),
+        stack: vec![StackEntry::new(pipeline_path)],
+    };
+
+    let parameters = bind_parameters(&yaml, parameters);
+    let context = ExpressionContext::new(parameters.clone());
+    let evaluation = prune_inactive(&yaml, &context);
+
+    let mut scopes = ScopeTree::from_value(&evaluation.value);
+    expander.expand(pipeline_path, &content, &evaluation, &context, &mut scopes)?;
+
+    Ok(TemplateExpansion {
+        parameters,
+        scopes,
+        variable_references: extract_variable_reference_spans_from_content(&content, pipeline_path)?
+            .into_iter()
+            .filter(|r| !evaluation.is_inactive(&r.span.yaml_path))
+            .collect(),
+        output_references: extract_output_reference_spans_from_content(&content, pipeline_path)?
+            .into_iter()
+            .filter(|r| !evaluation.is_inactive(&r.span.yaml_path))
+            .collect(),
+        group_references: extract_group_references_from_content(&content, pipeline_path)
+            .into_iter()
+            .filter(|r| !evaluation.is_inactive(&r.span.yaml_path))
+            .collect(),
+        variable_definitions: extract_variable_definitions_from_content(&content, pipeline_path)
+            .into_iter()
+            .filter(|d| !evaluation.is_inactive(&d.span.yaml_path))
+            .collect(),
+        environment_references: extract_environment_references(&content, pipeline_path)
+            .into_iter()
+            .filter(|r| !evaluation.is_inactive(&r.span.yaml_path))
+            .collect(),
+        inactive: evaluation.inactive,
+        instances: expander.instances,
+        problems: expander.problems,
+    })
+}
+
+/// A file currently being expanded
+struct StackEntry {
+    /// Canonical path used to detect cycles
+    key: String,
+    /// Path as resolved from the including file
+    display: String,
+}
+
+impl StackEntry {
+    fn new(path: &str) -> Self {
+        Self {
+            key: canonical_key(path),
+            display: path.to_string(),
+        }
+    }
+}
+
+/// A loaded template file
+struct LoadedTemplate {
+    resolved_path: String,
+    content: String,
+    yaml: Value,
+}
+
+/// Expansion state shared across the whole pipeline
+struct Expander {
+    instances: Vec<TemplateInstance>,
+    problems: Vec<TemplateProblem>,
+    /// Distinct template files loaded so far
+    files: HashSet<String>,
+    /// Files being expanded, outermost (the pipeline) first
+    stack: Vec<StackEntry>,
+}
+
+impl Expander {
+    /// Expand the templates referenced by a document whose scopes are `scopes`
+    ///
+    /// Variables templates are merged into `scopes` first, so that stage, job and step
+    /// templates see the variables they define. Templates in inactive branches are skipped.
+    fn expand(
+        &mut self,
+        file: &str,
+        content: &str,
+        evaluation: &Evaluation,
+        context: &ExpressionContext,
+        scopes: &mut ScopeTree,
+    ) -> Result<()> {
+        let index = LineIndex::new(content);
+        let yaml = &evaluation.value;
+
+        let variables_templates: Vec<TemplateReference> = template_references_in(yaml, &index, file, scopes)
+            .into_iter()
+            .filter(|r| r.kind == TemplateKind::Variables)
+            .collect();
+        for reference in variables_templates {
+            if let Some(instance) = self.expand_reference(file, reference, context, scopes)? {
+                let definitions = &instance.scopes.scopes()[0];
+                let groups = definitions.groups.clone();
+                let inline_variables = definitions.inline_variables.clone();
+                scopes.add_definitions(&instance.reference.span.yaml_path, &groups, &inline_variables);
+            }
+        }
+
+        // Recompute after merging so the references report the groups they can now see
+        let other_templates: Vec<TemplateReference> = template_references_in(yaml, &index, file, scopes)
+            .into_iter()
+            .filter(|r| r.kind != TemplateKind::Variables)
+            .collect();
+        for reference in other_templates {
+            self.expand_reference(file, reference, context, scopes)?;
+        }
+
+        Ok(())
+    }
+
+    /// Load and expand one template reference, recording the instance or a problem
+    ///
+    /// Parameter values that are `${{ }}` expressions are evaluated in the including
+    /// document's `context` before they are bound to the template.
+    fn expand_reference(
+        &mut self,
+        file: &str,
+        mut reference: TemplateReference,
+        context: &ExpressionContext,
+        scopes: &ScopeTree,
+    ) -> Result<Option<TemplateInstance>> {
+        let Some(loaded) = self.load(file, &reference)? else {
+            return Ok(None);
+        };
+
+        reference.parameters = reference
+            .parameters
+            .iter()
+            .map(|(name, value)| (name.clone(), resolve_value(value, context)))
+            .collect();
+        let parameters = bind_parameters(&loaded.yaml, &reference.parameters);
+        let parameter_findings = check_parameters(&parameter_declarations(&loaded.yaml), &reference.parameters);
+
+        let template_context = ExpressionContext::new(parameters.clone());
+        let evaluation = prune_inactive(&loaded.yaml, &template_context);
+
+        let chain: Vec<_> = scopes
+            .chain(&reference.span.yaml_path)
+            .into_iter()
+            .cloned()
+            .collect();
+        let inserted_into = chain
+            .last()
+            .map(|scope| scope.label.clone())
+            .unwrap_or_else(|| ROOT_LABEL.to_string());
+        let label = if inserted_into == ROOT_LABEL {
+            format!("template '{}'", reference.template_path)
+        } else {
+            format!("{inserted_into} / template '{}'", reference.template_path)
+        };
+        let mut template_scopes = ScopeTree::for_template(&evaluation.value, chain, label);
+        if reference.kind == TemplateKind::Steps {
+            let preceding = scopes.steps_before(&reference.span.yaml_path).into_iter().cloned().collect();
+            template_scopes = template_scopes.with_preceding_steps(preceding);
+        }
+
+        // Reserve the slot so a parent is listed before the templates it includes
+        let slot = self.instances.len();
+        let depth = self.stack.len();
+        let included_from = self.stack.iter().map(|entry| entry.display.clone()).collect();
+
+        self.stack.push(StackEntry::new(&loaded.resolved_path));
+        let expanded = self.expand(
+            &loaded.resolved_path,
+            &loaded.content,
+            &evaluation,
+            &template_context,
+            &mut template_scopes,
+        );
+        self.stack.pop();
+        expanded?;
+
+        let instance = TemplateInstance {
+            variable_references: extract_variable_reference_spans_from_content(
+                &loaded.content,
+                &loaded.resolved_path,
+            )?
+            .into_iter()
+            .filter(|r| !evaluation.is_inactive(&r.span.yaml_path))
+            .collect(),
+            output_references: extract_output_reference_spans_from_content(&loaded.content, &loaded.resolved_path)?
+                .into_iter()
+                .filter(|r| !evaluation.is_inactive(&r.span.yaml_path))
+                .collect(),
+            group_references: extract_group_references_from_content(&loaded.content, &loaded.resolved_path)
+                .into_iter()
+                .filter(|r| !evaluation.is_inactive(&r.span.yaml_path))
+                .collect(),
+            variable_definitions: extract_variable_definitions_from_content(&loaded.content, &loaded.resolved_path)
+                .into_iter()
+                .filter(|d| !evaluation.is_inactive(&d.span.yaml_path))
+                .collect(),
+            environment_references: extract_environment_references(&loaded.content, &loaded.resolved_path)
+                .into_iter()
+                .filter(|r| !evaluation.is_inactive(&r.span.yaml_path))
+                .collect(),
+            reference,
+            resolved_path: loaded.resolved_path,
+            depth,
+            included_from,
+            inserted_into,
+            parameters,
+            parameter_findings,
+            inactive: evaluation.inactive,
+            scopes: template_scopes,
+        };
+        self.instances.insert(slot, instance.clone());
+
+        Ok(Some(instance))
+    }
+
+    /// Resolve and parse a template, recording a problem if it cannot be expanded
+    fn load(&mut self, file: &str, reference: &TemplateReference) -> Result<Option<LoadedTemplate>> {
+        // `path@self` names the repository of the pipeline; any other alias is another repository
+        let (template_path, external) = match reference.template_path.rsplit_once('@') {
+            Some((path, alias)) if alias.eq_ignore_ascii_case("self") => (path, false),
+            Some(_) => (reference.template_path.as_str(), true),
+            None => (reference.template_path.as_str(), false),
+        };
+        let resolved_path = resolve_template_path(file, template_path);
+
+        let problem = if external {
+            Some((
+                TemplateProblemKind::ExternalRepository,
+                format!(
+                    "Template '{}' is in another repository and cannot be validated",
+                    reference.template_path
+                ),
+            ))
+        } else if let Some(start) = self
+            .stack
+            .iter()
+            .position(|entry| entry.key == canonical_key(&resolved_path))
+        {
+            let cycle: Vec<&str> = self.stack[start..]
+                .iter()
+                .map(|entry| entry.display.as_str())
+                .chain(std::iter::once(resolved_path.as_str()))
+                .collect();
+            Some((
+                TemplateProblemKind::Cycle,
+                format!("Template cycle detected: {}", cycle.join(" -> ")),
+            ))
+        } else if self.stack.len() > MAX_TEMPLATE_DEPTH {
+            Some((
+                TemplateProblemKind::DepthLimit,
+                format!(
+                    "Template '{}' exceeds the maximum nesting depth of {MAX_TEMPLATE_DEPTH}",
+                    reference.template_path
+                ),
+            ))
+        } else if !Path::new(&resolved_path).exists() {
+            Some((
+                TemplateProblemKind::NotFound,
+                format!(
+                    "Template file not found: {} (resolved to: {})",
+                    reference.template_path, resolved_path
+                ),
+            ))
+        } else if self.files.len() >= MAX_TEMPLATE_FILES && !self.files.contains(&canonical_key(&resolved_path)) {
+            Some((
+                TemplateProblemKind::FileLimit,
+                format!(
+                    "Template '{}' exceeds the maximum of {MAX_TEMPLATE_FILES} template files per pipeline",
+                    reference.template_path
+                ),
+            ))
+        } else {
+            None
+        };
+
+        if let Some((kind, message)) = problem {
+            self.problems.push(TemplateProblem {
+                kind,
+                reference: reference.clone(),
+                resolved_path,
+                message,
+            });
+            return Ok(None);
+        }
+
+        self.files.insert(canonical_key(&resolved_path));
+
+        let content = fs::read_to_string(&resolved_path)
+            .with_context(|| format!("Failed to read template file: {resolved_path}"))?;
+
+        match serde_yaml::from_str::<Value>(&content) {
+            Ok(yaml) => Ok(Some(LoadedTemplate {
+                resolved_path,
+                content,
+                yaml,
+            })),
+            Err(e) => {
+                self.problems.push(TemplateProblem {
+                    kind: TemplateProblemKind::InvalidYaml,
+                    reference: reference.clone(),
+                    message: format!("Failed to parse YAML in template file {resolved_path}: {e}"),
+                    resolved_path,
+                });
+                Ok(None)
+            }
+        }
+    }
+}
+
+/// Bind the parameters declared by a template to the values supplied by its caller
+///
+/// Parameters that are not supplied take their `default` (or null if there is none).
+/// Both the list syntax (`- name: x`) and the legacy map syntax (`x: default`) are supported.
+/// Supplied parameters the template does not declare are not bound.
+///
+/// # Arguments
+/// * `template` - Parsed template document
+/// * `supplied` - Parameters passed by the `template:` reference
+///
+/// # Returns
+/// * `Mapping` - Declared parameter names mapped to their values, in declaration order
+pub fn bind_parameters(template: &Value, supplied: &Mapping) -> Mapping {
+    parameter_declarations(template)
+        .into_iter()
+        .map(|declaration| {
+            let value = supplied
+                .get(declaration.name.as_str())
+                .cloned()
+                .or(declaration.default)
+                .unwrap_or(Value::Null);
+            (Value::String(declaration.name), value)
+        })
+        .collect()
+}
+
+/// Key identifying a file regardless of how its path was spelled
+fn canonical_key(path: &str) -> String {
+    fs::canonicalize(path)
+        .map(|p| p.to_string_lossy().to_string())
+        .unwrap_or_else(|_| path.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// Write a set of files into a fresh temporary directory and return its path
+    fn write_files(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("azdolint-template-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        for (path, content) in files {
+            let full = dir.join(path);
+            fs::create_dir_all(full.parent().unwrap()).unwrap();
+            fs::write(full, content).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn test_depth_limit() {
+        // t0 -> t1 -> ... each template includes the next one
+        let mut files: Vec<(String, String)> = vec![(
+            "pipeline.yml".to_string(),
+            "steps:\n  - template: t0.yml\n".to_string(),
+        )];
+        for i in 0..=MAX_TEMPLATE_DEPTH + 1 {
+            files.push((format!("t{i}.yml"), format!("steps:\n  - template: t{}.yml\n", i + 1)));
+        }
+        let refs: Vec<(&str, &str)> = files.iter().map(|(p, c)| (p.as_str(), c.as_str())).collect();
+        let dir = write_files("depth", &refs);
+
+        let expansion = expand_templates(dir.join("pipeline.yml").to_str().unwrap()).unwrap();
+
+        assert_eq!(expansion.instances.len(), MAX_TEMPLATE_DEPTH);
+        assert_eq!(expansion.instances.last().unwrap().depth, MAX_TEMPLATE_DEPTH);
+        assert_eq!(expansion.problems.len(), 1);
+        assert_eq!(expansion.problems[0].kind, TemplateProblemKind::DepthLimit);
+        assert!(expansion.problems[0].is_error());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_file_limit() {
+        let mut pipeline = String::from("steps:\n");
+        let mut files: Vec<(String, String)> = Vec::new();
+        for i in 0..=MAX_TEMPLATE_FILES {
+            pipeline.push_str(&format!("  - template: t{i}.yml\n"));
+            files.push((format!("t{i}.yml"), "steps:\n  - script: echo hi\n".to_string()));
+        }
+        // Including the same file again does not count towards the limit
+        pipeline.push_str("  - template: t0.yml\n");
+        files.push(("pipeline.yml".to_string(), pipeline));
+        let refs: Vec<(&str, &str)> = files.iter().map(|(p, c)| (p.as_str(), c.as_str())).collect();
+        let dir = write_files("files", &refs);
+
+        let expansion = expand_templates(dir.join("pipeline.yml").to_str().unwrap()).unwrap();
+
+        assert_eq!(expansion.instances.len(), MAX_TEMPLATE_FILES + 1);
+        assert_eq!(expansion.problems.len(), 1);
+        assert_eq!(expansion.problems[0].kind, TemplateProblemKind::FileLimit);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_repository_root_relative_path() {
+        let dir = write_files(
+            "root-relative",
+            &[
+                (".git/HEAD", "ref: refs/heads/main\n"),
+                ("pipelines/pipeline.yml", "steps:\n  - template: /templates/steps.yml\n"),
+                ("templates/steps.yml", "steps:\n  - script: echo $(Greeting)\n"),
+            ],
+        );
+
+        let expansion = expand_templates(dir.join("pipelines/pipeline.yml").to_str().unwrap()).unwrap();
+
+        assert!(expansion.problems.is_empty());
+        assert_eq!(
+            expansion.instances[0].resolved_path,
+            dir.join("templates/steps.yml").to_string_lossy()
+        );
+        assert_eq!(expansion.instances[0].variable_references[0].name, "Greeting");
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_external_repository_is_warning() {
+        let dir = write_files(
+            "external",
+            &[("pipeline.yml", "steps:\n  - template: steps/build.yml@templates\n")],
+        );
+
+        let expansion = expand_templates(dir.join("pipeline.yml").to_str().unwrap()).unwrap();
+
+        assert!(expansion.instances.is_empty());
+        assert_eq!(expansion.problems[0].kind, TemplateProblemKind::ExternalRepository);
+        assert!(!expansion.problems[0].is_error());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_self_repository_is_expanded() {
+        let dir = write_files(
+            "self-repository",
+            &[
+                ("pipeline.yml", "steps:
+  - template: t/steps.yml@self
+  - template: t/steps.yml@Self
+"),
+                ("t/steps.yml", "steps:
+  - script: echo $(Missing)
+"),
+            ],
+        );
+
+        let expansion = expand_templates(dir.join("pipeline.yml").to_str().unwrap()).unwrap();
+
+        assert!(expansion.problems.is_empty());
+        assert_eq!(expansion.instances.len(), 2);
+        assert_eq!(expansion.instances[0].resolved_path, dir.join("t/steps.yml").to_string_lossy());
+        assert_eq!(expansion.instances[0].variable_references[0].name, "Missing");
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,204 @@
+//! Detection of variable groups and inline variables that are never used
+//!
+//! Variables can be read as `$(name)`, in `$[ ]` / `${{ }}` expressions, or by scripts through
+//! the environment variable Azure DevOps creates for each of them (`$NAME`, `$env:NAME`,
+//! `%NAME%`). A definition counts as used if any of these appears anywhere in the pipeline or
+//! its templates; scopes are not taken into account.
+
+use crate::parser::{environment_variable_name, is_system_variable, variable_names_match, OutputForm};
+use crate::span::SourceSpan;
+use crate::template::TemplateExpansion;
+use crate::validator::GroupValidationResult;
+
+/// A linked variable group none of whose variables is used
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnusedGroup {
+    /// Name of the variable group
+    pub name: String,
+    /// Locations where the group is linked
+    pub locations: Vec<SourceSpan>,
+}
+
+/// An inline variable that is defined but never used
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnusedVariable {
+    /// Name of the variable
+    pub name: String,
+    /// Locations where the variable is defined
+    pub locations: Vec<SourceSpan>,
+}
+
+/// Variable names read anywhere in a pipeline and its templates
+#[derive(Debug, Clone, Default)]
+pub struct VariableUsage {
+    /// Names read with `$(name)`, in expressions, or as dotted `$(step.var)` macros
+    names: Vec<String>,
+    /// Environment variables read by scripts
+    environment: Vec<String>,
+}
+
+impl VariableUsage {
+    /// Collect the variable names read by a pipeline and all of its templates
+    ///
+    /// # Arguments
+    /// * `expansion` - The expanded pipeline
+    pub fn from_expansion(expansion: &TemplateExpansion) -> Self {
+        let mut usage = Self::default();
+
+        let references = expansion
+            .variable_references
+            .iter()
+            .chain(expansion.instances.iter().flat_map(|i| i.variable_references.iter()));
+        for reference in references {
+            usage.names.push(reference.name.clone());
+        }
+
+        // Dotted macros may read a regular variable whose name contains dots
+        let outputs = expansion
+            .output_references
+            .iter()
+            .chain(expansion.instances.iter().flat_map(|i| i.output_references.iter()));
+        for reference in outputs {
+            if let OutputForm::Step { .. } = reference.form {
+                usage.names.push(reference.text.clone());
+            }
+        }
+
+        let environment = expansion
+            .environment_references
+            .iter()
+            .chain(expansion.instances.iter().flat_map(|i| i.environment_references.iter()));
+        usage.environment.extend(environment.map(|reference| reference.name.clone()));
+
+        usage
+    }
+
+    /// Whether a variable is read anywhere
+    ///
+    /// # Arguments
+    /// * `name` - Name of the variable
+    ///
+    /// # Returns
+    /// * `bool` - True if the variable, or the environment variable created for it, is read
+    pub fn is_used(&self, name: &str) -> bool {
+        let environment_name = environment_variable_name(name);
+
+        self.names.iter().any(|n| variable_names_match(n, name))
+            || self
+                .environment
+                .iter()
+                .any(|n| variable_names_match(n, &environment_name))
+    }
+}
+
+/// Find linked variable groups from which no variable is used
+///
+/// Groups that do not exist are skipped; they are already reported by group validation.
+///
+/// # Arguments
+/// * `groups` - Group validation results, with the locations of each link
+/// * `available_variables` - `(variable_name, group_name)` pairs of the linked groups
+/// * `usage` - Variable names read by the pipeline
+///
+/// # Returns
+/// * `Vec<UnusedGroup>` - Unused groups, in the order they were validated
+pub fn find_unused_groups(
+    groups: &[GroupValidationResult],
+    available_variables: &[(String, String)],
+    usage: &VariableUsage,
+) -> Vec<UnusedGroup> {
+    groups
+        .iter()
+        .filter(|group| group.exists)
+        .filter(|group| {
+            !available_variables
+                .iter()
+                .any(|(name, g)| *g == group.group_name && usage.is_used(name))
+        })
+        .map(|group| UnusedGroup {
+            name: group.group_name.clone(),
+            locations: group.locations.clone(),
+        })
+        .collect()
+}
+
+/// Find inline variables of a pipeline and its templates that are never used
+///
+/// Predefined variables such as `system.debug` configure the agent and are never reported.
+///
+/// # Arguments
+/// * `expansion` - The expanded pipeline
+/// * `usage` - Variable names read by the pipeline
+///
+/// # Returns
+/// * `Vec<UnusedVariable>` - One entry per unused name, with every definition site
+pub fn find_unused_variables(expansion: &TemplateExpansion, usage: &VariableUsage) -> Vec<UnusedVariable> {
+    let definitions = expansion
+        .variable_definitions
+        .iter()
+        .chain(expansion.instances.iter().flat_map(|i| i.variable_definitions.iter()));
+
+    let mut unused: Vec<UnusedVariable> = Vec::new();
+    for definition in definitions {
+        if is_system_variable(&definition.name) || usage.is_used(&definition.name) {
+            continue;
+        }
+        match unused.iter_mut().find(|u| variable_names_match(&u.name, &definition.name)) {
+            Some(entry) => {
+                if !entry.locations.contains(&definition.span) {
+                    entry.locations.push(definition.span.clone());
+                }
+            }
+            None => unused.push(UnusedVariable {
+                name: definition.name.clone(),
+                locations: vec![definition.span.clone()],
+            }),
+        }
+    }
+    unused
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usage(names: &[&str], environment: &[&str]) -> VariableUsage {
+        VariableUsage {
+            names: names.iter().map(|n| n.to_string()).collect(),
+            environment: environment.iter().map(|n| n.to_string()).collect(),
+        }
+    }
+
+    fn group(name: &str) -> GroupValidationResult {
+        GroupValidationResult {
+            group_name: name.to_string(),
+            exists: true,
+            error: None,
+            group_id: Some(1),
+            locations: Vec::new(),
+            suggestion: None,
+        }
+    }
+
+    #[test]
+    fn test_is_used_by_reference_or_environment() {
+        let usage = usage(&["apikey"], &["BUILD_CONFIG", "DB_HOST"]);
+        assert!(usage.is_used("ApiKey"));
+        assert!(usage.is_used("build.config"));
+        assert!(usage.is_used("db_host"));
+        assert!(!usage.is_used("Unused"));
+    }
+
+    #[test]
+    fn test_find_unused_groups() {
+        let groups = vec![group("app-secrets"), group("stale-secrets"), group("empty")];
+        let available = vec![
+            ("ApiKey".to_string(), "app-secrets".to_string()),
+            ("OldToken".to_string(), "stale-secrets".to_string()),
+        ];
+        let unused = find_unused_groups(&groups, &available, &usage(&["ApiKey"], &[]));
+
+        let names: Vec<&str> = unused.iter().map(|g| g.name.as_str()).collect();
+        assert_eq!(names, vec!["stale-secrets", "empty"]);
+    }
+}
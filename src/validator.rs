@@ -1,7 +1,16 @@
 //! Validation logic for pipeline variable groups and variables
 
 use anyhow::Result;
-use crate::azure::AzureDevOpsClient;
+use regex::Regex;
+use std::collections::HashMap;
+use crate::azure::{PipelineVariableValue, VariableGroupData};
+use crate::backend::VariableBackend;
+use crate::concurrent::map_concurrent;
+use crate::parser::{variable_names_match, GroupReference, VariableReference, VariableSyntax};
+use crate::precedence::definition_sites;
+use crate::scope::{Scope, ScopeKind, ScopeTree};
+use crate::span::SourceSpan;
+use crate::suggest::closest;
 
 /// Result of validating a single variable group
 #[derive(Debug, Clone)]
@@ -14,6 +23,10 @@ pub struct GroupValidationResult {
     pub error: Option<String>,
     /// Variable group ID if found
     pub group_id: Option<i32>,
+    /// Locations where the group is linked in the pipeline
+    pub locations: Vec<SourceSpan>,
+    /// Project group with a similar name, when the group does not exist
+    pub suggestion: Option<String>,
 }
 
 /// Source of a validated variable
@@ -25,10 +38,24 @@ pub enum VariableSource {
     Inline,
     /// Variable defined on the pipeline definition (not in YAML)
     PipelineDefinition,
+    /// Variable defined by the legs of a job's `strategy: matrix`, or assumed to be when the
+    /// matrix is computed by an expression
+    Matrix {
+        /// Label of the job that owns the matrix
+        job: String,
+    },
+    /// Variable set at runtime by an earlier step of the same job (`task.setvariable`)
+    RuntimeSet {
+        /// Label of the step that sets the variable
+        step: String,
+    },
     /// Variable not found
     NotFound,
 }
 
+/// Source of a resolved variable and its spelling where it is defined
+pub type Resolution = (VariableSource, String);
+
 /// Result of validating a single variable reference
 #[derive(Debug)]
 pub struct VariableValidationResult {
@@ -42,35 +69,63 @@ pub struct VariableValidationResult {
     pub error: Option<String>,
     /// Source of the variable (group, inline, or not found)
     pub source: VariableSource,
+    /// Locations where the variable is referenced
+    pub locations: Vec<SourceSpan>,
+    /// Scope in which the variable is used (None when validated without scope information)
+    pub scope: Option<String>,
+    /// Scopes that define the variable but are not visible where it is used
+    pub defined_in: Vec<String>,
+    /// Problems that do not fail validation, e.g. a group variable used in a template expression
+    pub warnings: Vec<String>,
+    /// Spelling of the variable where it is defined, when it differs from the reference only in
+    /// case (Azure DevOps variable names are case-insensitive)
+    pub canonical_name: Option<String>,
+    /// Closest known variable and where it is defined, when the variable was not found
+    pub suggestion: Option<Resolution>,
+    /// Whether the suggested variable is in a project group the pipeline does not link
+    pub suggestion_unlinked: bool,
+    /// Project groups that define the variable but are not linked where it is used, groups the
+    /// pipeline links elsewhere first
+    pub unlinked_groups: Vec<String>,
+    /// Why the value of the group variable the reference resolves to is not usable, e.g.
+    /// `an empty value` (None when the value is set or secret)
+    pub value_problem: Option<String>,
 }
 
 /// Validate that variable groups exist in Azure DevOps
 ///
+/// Groups are looked up concurrently, up to the backend's `concurrency`.
+///
 /// # Arguments
 /// * `group_names` - List of variable group names to validate
-/// * `client` - Azure DevOps client for API calls
+/// * `backend` - Source of variable groups, e.g. the Azure DevOps client
 ///
 /// # Returns
 /// * `Result<Vec<GroupValidationResult>>` - Validation results for each group
-pub fn validate_variable_groups(
+pub fn validate_variable_groups<B: VariableBackend + ?Sized>(
     group_names: Vec<String>,
-    client: &AzureDevOpsClient,
+    backend: &B,
 ) -> Result<Vec<GroupValidationResult>> {
-    let mut results = Vec::new();
+    let lookups = map_concurrent(&group_names, backend.concurrency(), |name| backend.get_variable_group(name));
 
-    for group_name in group_names {
-        let result = match client.get_variable_group(&group_name) {
+    let mut results = Vec::new();
+    for (group_name, lookup) in group_names.into_iter().zip(lookups) {
+        let result = match lookup {
             Ok(group_data) => GroupValidationResult {
                 group_name,
                 exists: true,
                 error: None,
                 group_id: Some(group_data.id),
+                locations: Vec::new(),
+                suggestion: None,
             },
             Err(e) => GroupValidationResult {
                 group_name,
                 exists: false,
                 error: Some(e.to_string()),
                 group_id: None,
+                locations: Vec::new(),
+                suggestion: None,
             },
         };
         results.push(result);
@@ -79,6 +134,133 @@ pub fn validate_variable_groups(
     Ok(results)
 }
 
+/// Suggest a similarly named project group for each group that does not exist
+///
+/// # Arguments
+/// * `results` - Group validation results to annotate
+/// * `project_groups` - Names of all variable groups in the project
+pub fn attach_group_suggestions(results: &mut [GroupValidationResult], project_groups: &[String]) {
+    for result in results.iter_mut().filter(|r| !r.exists) {
+        result.suggestion = closest(&result.group_name, project_groups.iter().map(|g| (g.as_str(), ())))
+            .map(|(name, _)| name.to_string());
+    }
+}
+
+/// Flatten variable groups into `(variable_name, group_name)` pairs, sorted by group and variable
+///
+/// # Arguments
+/// * `groups` - Variable groups with their variables
+pub fn group_variable_pairs(groups: &[VariableGroupData]) -> Vec<(String, String)> {
+    let mut pairs: Vec<(String, String)> = groups
+        .iter()
+        .flat_map(|group| group.variables.keys().map(|name| (name.clone(), group.name.clone())))
+        .collect();
+    pairs.sort_by(|a, b| a.1.cmp(&b.1).then_with(|| a.0.cmp(&b.0)));
+    pairs
+}
+
+/// Record, for each variable that was not found, the project groups that define it
+///
+/// The variable is not visible where it is used, so none of these groups is linked in that
+/// scope. Groups the pipeline already links elsewhere are listed first, as linking them in
+/// one more place is usually the intended fix.
+///
+/// # Arguments
+/// * `results` - Variable validation results to annotate
+/// * `project_variables` - `(variable_name, group_name)` pairs of every group in the project
+/// * `pipeline_groups` - Groups linked anywhere in the pipeline or its templates
+pub fn attach_unlinked_groups(
+    results: &mut [VariableValidationResult],
+    project_variables: &[(String, String)],
+    pipeline_groups: &[String],
+) {
+    for result in results.iter_mut().filter(|r| !r.exists) {
+        let mut groups: Vec<String> = Vec::new();
+        for (_, group) in project_variables
+            .iter()
+            .filter(|(name, _)| variable_names_match(name, &result.variable_name))
+        {
+            if !groups.contains(group) {
+                groups.push(group.clone());
+            }
+        }
+        groups.sort_by_key(|group| !pipeline_groups.contains(group));
+        result.unlinked_groups = groups;
+    }
+}
+
+/// Suggest, for each variable that was not found and has no closer match, a similarly named
+/// variable from a project group the pipeline does not link
+///
+/// Variables of linked groups are already suggested during validation and are preferred.
+///
+/// # Arguments
+/// * `results` - Variable validation results to annotate
+/// * `project_variables` - `(variable_name, group_name)` pairs of every group in the project
+/// * `pipeline_groups` - Groups linked anywhere in the pipeline or its templates
+pub fn attach_project_suggestions(
+    results: &mut [VariableValidationResult],
+    project_variables: &[(String, String)],
+    pipeline_groups: &[String],
+) {
+    for result in results.iter_mut().filter(|r| !r.exists) {
+        if result.suggestion.is_some() || !result.unlinked_groups.is_empty() || !result.defined_in.is_empty() {
+            continue;
+        }
+        let candidates = project_variables
+            .iter()
+            .filter(|(_, group)| !pipeline_groups.contains(group))
+            .map(|(name, group)| (name.as_str(), group));
+        if let Some((name, group)) = closest(&result.variable_name, candidates) {
+            result.suggestion = Some((VariableSource::Group(group.clone()), name.to_string()));
+            result.suggestion_unlinked = true;
+        }
+    }
+}
+
+/// Default pattern for values that were never filled in, matched against the trimmed value
+pub const DEFAULT_PLACEHOLDER_PATTERN: &str =
+    r"(?i)^(todo|tbd|fixme|changeme|change[-_ ]me|replace[-_ ]?me|placeholder|dummy|xxx+|<[^<>]*>)$";
+
+/// Record, for each variable resolved to a group, whether its value is empty or a placeholder
+///
+/// Secret values cannot be read and are never flagged, nor are values that are unknown, e.g.
+/// when validating against a snapshot taken without values.
+///
+/// # Arguments
+/// * `results` - Variable validation results to annotate
+/// * `group_variables` - Variables of the linked groups, with values
+/// * `placeholder` - Pattern matched against the trimmed value of each variable
+pub fn attach_value_problems(
+    results: &mut [VariableValidationResult],
+    group_variables: &[GroupVariable],
+    placeholder: &Regex,
+) {
+    for result in results.iter_mut().filter(|r| r.exists) {
+        let VariableSource::Group(group) = &result.source else {
+            continue;
+        };
+        let name = result.canonical_name.as_deref().unwrap_or(&result.variable_name);
+        let Some(variable) = group_variables
+            .iter()
+            .find(|v| v.group == *group && !v.is_secret && variable_names_match(&v.name, name))
+        else {
+            continue;
+        };
+
+        let Some(value) = variable.value.as_deref().map(str::trim) else {
+            continue;
+        };
+        result.value_problem = if value.is_empty() {
+            Some("an empty value".to_string())
+        } else if placeholder.is_match(value) {
+            Some(format!("the placeholder value '{value}'"))
+        } else {
+            None
+        };
+    }
+}
+
 /// Validate that variables referenced in the pipeline exist in the variable groups,
 /// are defined inline, or exist on the pipeline definition
 ///
@@ -87,94 +269,477 @@ pub fn validate_variable_groups(
 /// * `group_validation_results` - Results from validating variable groups (contains group IDs)
 /// * `inline_variables` - List of variable names defined inline in the pipeline
 /// * `pipeline_definition_variables` - List of variable names defined on the pipeline definition
-/// * `client` - Azure DevOps client for API calls
+/// * `backend` - Source of variable groups, e.g. the Azure DevOps client
 ///
 /// # Returns
 /// * `Result<Vec<VariableValidationResult>>` - Validation results for each variable
-pub fn validate_variables(
+pub fn validate_variables<B: VariableBackend + ?Sized>(
     variable_references: Vec<String>,
     group_validation_results: &[GroupValidationResult],
     inline_variables: &[String],
     pipeline_definition_variables: &[String],
-    client: &AzureDevOpsClient,
+    backend: &B,
 ) -> Result<Vec<VariableValidationResult>> {
     // Collect all available variables from all existing groups
-    let mut available_variables: Vec<(String, String)> = Vec::new(); // (variable_name, group_name)
-
-    for group_result in group_validation_results {
-        if group_result.exists {
-            if let Some(group_id) = group_result.group_id {
-                match client.get_variables_in_group(group_id) {
-                    Ok(vars) => {
-                        for var in vars {
-                            available_variables.push((var, group_result.group_name.clone()));
-                        }
-                    }
-                    Err(_) => {
-                        // Skip groups that fail to fetch variables - already reported in group validation
-                    }
-                }
-            }
-        }
-    }
+    let available_variables = fetch_group_variables(group_validation_results, backend);
+
+    Ok(validate_variables_against_available_with_inline(
+        variable_references,
+        &available_variables,
+        inline_variables,
+        pipeline_definition_variables,
+    ))
+}
 
-    // Validate each variable reference
-    let mut results = Vec::new();
+/// Fetch the variables of every existing group as `(variable_name, group_name)` pairs
+///
+/// Groups that do not exist or fail to load are skipped; they are already reported by
+/// group validation.
+pub fn fetch_group_variables<B: VariableBackend + ?Sized>(
+    group_validation_results: &[GroupValidationResult],
+    backend: &B,
+) -> Vec<(String, String)> {
+    fetch_group_variable_details(group_validation_results, backend)
+        .into_iter()
+        .map(|variable| (variable.name, variable.group))
+        .collect()
+}
 
-    for var_name in variable_references {
-        // First check if it's an inline variable (highest priority)
-        if inline_variables.contains(&var_name) {
-            results.push(VariableValidationResult {
-                variable_name: var_name,
-                group_name: None,
-                exists: true,
-                error: None,
-                source: VariableSource::Inline,
-            });
+/// A variable of a linked variable group, with its value
+#[derive(Debug, Clone, PartialEq)]
+pub struct GroupVariable {
+    /// Name of the variable
+    pub name: String,
+    /// Name of the group that defines it
+    pub group: String,
+    /// Value of the variable (None for secrets, or when values are not known)
+    pub value: Option<String>,
+    /// Whether the variable is secret
+    pub is_secret: bool,
+}
+
+/// A variable set on the pipeline definition (pipeline settings UI)
+#[derive(Debug, Clone, PartialEq)]
+pub struct PipelineVariable {
+    /// Name of the variable
+    pub name: String,
+    /// Value of the variable (None for secrets)
+    pub value: Option<String>,
+    /// Whether the variable is secret
+    pub is_secret: bool,
+    /// Whether users may override the value when queueing a run
+    pub allow_override: bool,
+}
+
+/// Fetch the variables of every existing group, with values, in link order
+///
+/// Groups are fetched concurrently, up to the backend's `concurrency`. Groups that do not exist
+/// or fail to load are skipped; they are already reported by group validation.
+pub fn fetch_group_variable_details<B: VariableBackend + ?Sized>(
+    group_validation_results: &[GroupValidationResult],
+    backend: &B,
+) -> Vec<GroupVariable> {
+    let existing: Vec<(&str, i32)> = group_validation_results
+        .iter()
+        .filter(|r| r.exists)
+        .filter_map(|r| Some((r.group_name.as_str(), r.group_id?)))
+        .collect();
+    let groups = map_concurrent(&existing, backend.concurrency(), |(_, group_id)| {
+        backend.get_variable_group_by_id(*group_id)
+    });
+
+    let mut variables = Vec::new();
+    for ((group_name, _), group) in existing.into_iter().zip(groups) {
+        // Skip groups that fail to fetch variables - already reported in group validation
+        let Ok(group) = group else {
             continue;
-        }
+        };
 
-        // Check if it's a pipeline definition variable
-        if pipeline_definition_variables.contains(&var_name) {
-            results.push(VariableValidationResult {
-                variable_name: var_name,
-                group_name: None,
-                exists: true,
-                error: None,
-                source: VariableSource::PipelineDefinition,
+        let mut entries: Vec<_> = group.variables.into_iter().collect();
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        for (name, value) in entries {
+            variables.push(GroupVariable {
+                name,
+                group: group_name.to_string(),
+                value: value.value,
+                is_secret: value.is_secret.unwrap_or(false),
             });
-            continue;
         }
+    }
 
-        // Search for the variable in all available groups
-        let found = available_variables
-            .iter()
-            .find(|(name, _)| name == &var_name);
+    variables
+}
 
-        let result = match found {
-            Some((_, group_name)) => VariableValidationResult {
-                variable_name: var_name,
-                group_name: Some(group_name.clone()),
-                exists: true,
-                error: None,
-                source: VariableSource::Group(group_name.clone()),
-            },
-            None => VariableValidationResult {
-                variable_name: var_name,
-                group_name: None,
-                exists: false,
-                error: Some("Variable not found in any referenced variable group".to_string()),
-                source: VariableSource::NotFound,
-            },
+/// Convert the variables of a pipeline definition, sorted by name
+///
+/// # Arguments
+/// * `variables` - Variables as returned by `VariableBackend::get_pipeline_variables_by_id`
+pub fn pipeline_variables_from(variables: HashMap<String, PipelineVariableValue>) -> Vec<PipelineVariable> {
+    let mut variables: Vec<PipelineVariable> = variables
+        .into_iter()
+        .map(|(name, value)| PipelineVariable {
+            name,
+            value: value.value,
+            is_secret: value.is_secret.unwrap_or(false),
+            allow_override: value.allow_override,
+        })
+        .collect();
+    variables.sort_by(|a, b| a.name.cmp(&b.name));
+    variables
+}
+
+/// Validate variable references against the variables visible in the scope where each one is used
+///
+/// A reference inside a job only sees root, stage and job variables of its own stage and
+/// job, matching how Azure DevOps resolves `$(variable)` at runtime. Variables that are not
+/// defined anywhere may still be set by an earlier step of the same job with
+/// `task.setvariable`. References are grouped per variable, scope and source, so the same
+/// name used in two jobs yields two results.
+///
+/// # Arguments
+/// * `variable_references` - Variable references found in the pipeline, with locations
+/// * `scopes` - Scope tree of the pipeline
+/// * `group_validation_results` - Results from validating variable groups (contains group IDs)
+/// * `pipeline_definition_variables` - List of variable names defined on the pipeline definition
+/// * `backend` - Source of variable groups, e.g. the Azure DevOps client
+///
+/// # Returns
+/// * `Result<Vec<VariableValidationResult>>` - Validation results for each variable and scope
+pub fn validate_scoped_variables<B: VariableBackend + ?Sized>(
+    variable_references: &[VariableReference],
+    scopes: &ScopeTree,
+    group_validation_results: &[GroupValidationResult],
+    pipeline_definition_variables: &[String],
+    backend: &B,
+) -> Result<Vec<VariableValidationResult>> {
+    let available_variables = fetch_group_variables(group_validation_results, backend);
+
+    Ok(validate_scoped_variables_against_available(
+        variable_references,
+        scopes,
+        &available_variables,
+        pipeline_definition_variables,
+    ))
+}
+
+/// Validate scoped variable references against pre-fetched group variables
+pub fn validate_scoped_variables_against_available(
+    variable_references: &[VariableReference],
+    scopes: &ScopeTree,
+    available_variables: &[(String, String)], // (variable_name, group_name)
+    pipeline_definition_variables: &[String],
+) -> Vec<VariableValidationResult> {
+    // Group occurrences per (variable, scope, resolution), keeping first-occurrence order
+    let mut usages: Vec<(&str, &Scope, Option<Resolution>, Vec<&VariableReference>)> = Vec::new();
+    for reference in variable_references {
+        let scope = scopes.variable_scope(&reference.span.yaml_path);
+        let resolution = resolve_in_chain(
+            &reference.name,
+            &scopes.chain(&scope.path),
+            available_variables,
+            pipeline_definition_variables,
+        )
+        .or_else(|| {
+            scopes
+                .runtime_definition(&reference.name, &reference.span.yaml_path)
+                .map(|step| {
+                    let canonical = step
+                        .set_variables
+                        .iter()
+                        .find(|v| variable_names_match(&v.name, &reference.name))
+                        .map_or_else(|| reference.name.clone(), |v| v.name.clone());
+                    let source = VariableSource::RuntimeSet {
+                        step: step.label.clone(),
+                    };
+                    (source, canonical)
+                })
+        })
+        .or_else(|| {
+            // Legs of a computed matrix are only known at runtime, so any name may be one of them
+            scopes
+                .chain(&scope.path)
+                .into_iter()
+                .rev()
+                .find(|s| s.matrix.as_ref().is_some_and(|m| m.expression.is_some()))
+                .map(|job| {
+                    let source = VariableSource::Matrix {
+                        job: job.label.clone(),
+                    };
+                    (source, reference.name.clone())
+                })
+        });
+
+        match usages
+            .iter_mut()
+            .find(|(name, s, res, _)| *name == reference.name && s.path == scope.path && *res == resolution)
+        {
+            Some((_, _, _, references)) => references.push(reference),
+            None => usages.push((&reference.name, scope, resolution, vec![reference])),
+        }
+    }
+
+    let mut results = Vec::new();
+
+    for (var_name, scope, resolution, references) in usages {
+        let locations: Vec<SourceSpan> = references.iter().map(|r| r.span.clone()).collect();
+        let compile_time = references
+            .iter()
+            .any(|r| r.syntax == VariableSyntax::TemplateExpression);
+        let chain = scopes.chain(&scope.path);
+
+        let result = match resolution {
+            Some((source, canonical)) => {
+                // Variable groups are linked at queue time and runtime variables are set while
+                // the job runs, both after templates are compiled
+                let warnings = match &source {
+                    VariableSource::Group(group) if compile_time => vec![format!(
+                        "${{{{ variables.{var_name} }}}} is evaluated at compile time, but '{var_name}' only exists in variable group '{group}', which is not available until runtime; use $({var_name}) or $[ variables.{var_name} ] instead"
+                    )],
+                    VariableSource::RuntimeSet { step } if compile_time => vec![format!(
+                        "${{{{ variables.{var_name} }}}} is evaluated at compile time, but '{var_name}' is only set at runtime by {step}; use $({var_name}) or $[ variables.{var_name} ] instead"
+                    )],
+                    VariableSource::Matrix { job } => matrix_warnings(var_name, job, &chain),
+                    _ => Vec::new(),
+                };
+
+                VariableValidationResult {
+                    variable_name: var_name.to_string(),
+                    group_name: match &source {
+                        VariableSource::Group(group) => Some(group.clone()),
+                        _ => None,
+                    },
+                    exists: true,
+                    error: None,
+                    source,
+                    locations,
+                    scope: Some(scope.label.clone()),
+                    defined_in: Vec::new(),
+                    warnings,
+                    canonical_name: (canonical != var_name).then_some(canonical),
+                    unlinked_groups: Vec::new(),
+                    value_problem: None,
+                    suggestion_unlinked: false,
+                    suggestion: None,
+                }
+            }
+            None => {
+                let defined_in: Vec<String> = scopes
+                    .scopes()
+                    .iter()
+                    .filter(|s| s.kind != ScopeKind::Step)
+                    .filter(|s| !chain.iter().any(|c| c.path == s.path))
+                    .filter_map(|s| describe_definition(var_name, s, available_variables))
+                    .collect();
+
+                let error = if defined_in.is_empty() {
+                    "Variable not found in any referenced variable group".to_string()
+                } else {
+                    format!(
+                        "Variable is defined in {} but used in {}",
+                        defined_in.join(", "),
+                        scope.label
+                    )
+                };
+
+                VariableValidationResult {
+                    variable_name: var_name.to_string(),
+                    group_name: None,
+                    exists: false,
+                    error: Some(error),
+                    source: VariableSource::NotFound,
+                    locations,
+                    scope: Some(scope.label.clone()),
+                    suggestion: if defined_in.is_empty() {
+                        suggest_variable(
+                            var_name,
+                            chain_variables(&chain),
+                            available_variables,
+                            pipeline_definition_variables,
+                        )
+                    } else {
+                        None
+                    },
+                    defined_in,
+                    warnings: Vec::new(),
+                    canonical_name: None,
+                    unlinked_groups: Vec::new(),
+                    value_problem: None,
+                    suggestion_unlinked: false,
+                }
+            }
         };
         results.push(result);
     }
 
-    Ok(results)
+    results
+}
+
+/// Resolve a variable against a chain of visible scopes
+///
+/// The winner is the last of `precedence::definition_sites`, so validation and the precedence
+/// diagnostics agree: the most specific scope wins, a later entry of a `variables` section
+/// overrides an earlier one, and any YAML definition overrides the pipeline settings. Names are
+/// matched case-insensitively, as Azure DevOps does.
+///
+/// # Returns
+/// * `Option<Resolution>` - Source of the variable and its spelling where it is defined
+pub fn resolve_in_chain(
+    var_name: &str,
+    chain: &[&Scope],
+    available_variables: &[(String, String)],
+    pipeline_definition_variables: &[String],
+) -> Option<Resolution> {
+    let group_variables: Vec<GroupVariable> = available_variables
+        .iter()
+        .filter(|(name, _)| variable_names_match(name, var_name))
+        .map(|(name, group)| GroupVariable {
+            name: name.clone(),
+            group: group.clone(),
+            value: None,
+            is_secret: false,
+        })
+        .collect();
+    let pipeline_variables: Vec<PipelineVariable> = pipeline_definition_variables
+        .iter()
+        .filter(|name| variable_names_match(name, var_name))
+        .map(|name| PipelineVariable {
+            name: name.clone(),
+            value: None,
+            is_secret: false,
+            allow_override: false,
+        })
+        .collect();
+
+    definition_sites(var_name, chain, &group_variables, &pipeline_variables)
+        .pop()
+        .map(|site| (site.source, site.name))
+}
+
+/// Find the known variable closest to one that could not be resolved
+///
+/// Candidates are the variables defined in YAML where the variable is used, the pipeline
+/// definition variables and the variables of every fetched group, linked there or not.
+fn suggest_variable<'a>(
+    var_name: &str,
+    mut candidates: Vec<(&'a str, VariableSource)>,
+    available_variables: &'a [(String, String)],
+    pipeline_definition_variables: &'a [String],
+) -> Option<Resolution> {
+    candidates.extend(
+        pipeline_definition_variables
+            .iter()
+            .map(|v| (v.as_str(), VariableSource::PipelineDefinition)),
+    );
+    candidates.extend(
+        available_variables
+            .iter()
+            .map(|(name, group)| (name.as_str(), VariableSource::Group(group.clone()))),
+    );
+
+    closest(var_name, candidates).map(|(name, source)| (source, name.to_string()))
+}
+
+/// Inline and matrix variables visible in a scope chain, innermost first
+fn chain_variables<'a>(chain: &[&'a Scope]) -> Vec<(&'a str, VariableSource)> {
+    let mut candidates = Vec::new();
+    for scope in chain.iter().rev() {
+        candidates.extend(scope.inline_variables.iter().map(|v| (v.as_str(), VariableSource::Inline)));
+        if let Some(matrix) = &scope.matrix {
+            let source = VariableSource::Matrix {
+                job: scope.label.clone(),
+            };
+            candidates.extend(matrix.variables().into_iter().map(|v| (v, source.clone())));
+        }
+    }
+    candidates
 }
 
-/// Helper function to validate variables against pre-fetched available variables
-/// This is used for testing without needing to call Azure CLI
+/// Warnings for a variable provided by a job matrix: legs that leave it undefined, or a matrix
+/// that is computed by an expression and cannot be checked
+fn matrix_warnings(var_name: &str, job: &str, chain: &[&Scope]) -> Vec<String> {
+    let Some(matrix) = chain
+        .iter()
+        .rev()
+        .find(|scope| scope.label == job)
+        .and_then(|scope| scope.matrix.as_ref())
+    else {
+        return Vec::new();
+    };
+
+    if let Some(expression) = &matrix.expression {
+        return vec![format!(
+            "The matrix of {job} is computed by '{expression}' and cannot be validated statically; '{var_name}' is assumed to be one of its variables"
+        )];
+    }
+
+    let missing = matrix.legs_missing(var_name);
+    if missing.is_empty() {
+        return Vec::new();
+    }
+    let legs: Vec<String> = missing.iter().map(|leg| format!("'{leg}'")).collect();
+    vec![format!(
+        "Matrix leg{} {} of {job} do{} not define '{var_name}', which other legs define",
+        if missing.len() == 1 { "" } else { "s" },
+        legs.join(", "),
+        if missing.len() == 1 { "es" } else { "" },
+    )]
+}
+
+/// Describe where a scope defines a variable, e.g. `stage 'Deploy' (group 'deploy-secrets')`
+fn describe_definition(
+    var_name: &str,
+    scope: &Scope,
+    available_variables: &[(String, String)],
+) -> Option<String> {
+    if scope.inline_variables.iter().any(|v| variable_names_match(v, var_name)) {
+        return Some(format!("{} (inline)", scope.label));
+    }
+
+    scope
+        .groups
+        .iter()
+        .find(|group| {
+            available_variables
+                .iter()
+                .any(|(name, g)| variable_names_match(name, var_name) && g == *group)
+        })
+        .map(|group| format!("{} (group '{}')", scope.label, group))
+}
+
+/// Attach the locations of each group link to the matching group validation result
+///
+/// # Arguments
+/// * `results` - Group validation results to annotate
+/// * `references` - Group links found in the pipeline, with locations
+pub fn attach_group_locations(results: &mut [GroupValidationResult], references: &[GroupReference]) {
+    for result in results {
+        result.locations = references
+            .iter()
+            .filter(|r| r.name == result.group_name)
+            .map(|r| r.span.clone())
+            .collect();
+    }
+}
+
+/// Attach the locations of each variable reference to the matching variable validation result
+///
+/// # Arguments
+/// * `results` - Variable validation results to annotate
+/// * `references` - Variable references found in the pipeline, with locations
+pub fn attach_variable_locations(
+    results: &mut [VariableValidationResult],
+    references: &[VariableReference],
+) {
+    for result in results {
+        result.locations = references
+            .iter()
+            .filter(|r| r.name == result.variable_name)
+            .map(|r| r.span.clone())
+            .collect();
+    }
+}
+
+/// Validate variables against pre-fetched group variables, e.g. from `fetch_group_variables`
 pub fn validate_variables_against_available(
     variable_references: Vec<String>,
     available_variables: &[(String, String)], // (variable_name, group_name)
@@ -182,8 +747,8 @@ pub fn validate_variables_against_available(
     validate_variables_against_available_with_inline(variable_references, available_variables, &[], &[])
 }
 
-/// Helper function to validate variables against pre-fetched available variables, inline variables,
-/// and pipeline definition variables. This is used for testing without needing to call Azure CLI.
+/// Validate variables against pre-fetched group variables, inline variables and pipeline
+/// definition variables; `validate_variables` fetches the group variables from a backend first
 pub fn validate_variables_against_available_with_inline(
     variable_references: Vec<String>,
     available_variables: &[(String, String)], // (variable_name, group_name)
@@ -193,48 +758,65 @@ pub fn validate_variables_against_available_with_inline(
     let mut results = Vec::new();
 
     for var_name in variable_references {
-        // First check if it's an inline variable (highest priority)
-        if inline_variables.contains(&var_name) {
-            results.push(VariableValidationResult {
-                variable_name: var_name,
-                group_name: None,
-                exists: true,
-                error: None,
-                source: VariableSource::Inline,
-            });
-            continue;
-        }
-
-        // Check if it's a pipeline definition variable
-        if pipeline_definition_variables.contains(&var_name) {
-            results.push(VariableValidationResult {
-                variable_name: var_name,
-                group_name: None,
-                exists: true,
-                error: None,
-                source: VariableSource::PipelineDefinition,
-            });
-            continue;
-        }
-
-        let found = available_variables
+        // Inline variables take precedence over variable groups, and any YAML definition takes
+        // precedence over the pipeline definition
+        let resolution = inline_variables
             .iter()
-            .find(|(name, _)| name == &var_name);
+            .find(|v| variable_names_match(v, &var_name))
+            .map(|v| (VariableSource::Inline, v.clone()))
+            .or_else(|| {
+                available_variables
+                    .iter()
+                    .find(|(name, _)| variable_names_match(name, &var_name))
+                    .map(|(name, group)| (VariableSource::Group(group.clone()), name.clone()))
+            })
+            .or_else(|| {
+                pipeline_definition_variables
+                    .iter()
+                    .find(|v| variable_names_match(v, &var_name))
+                    .map(|v| (VariableSource::PipelineDefinition, v.clone()))
+            });
 
-        let result = match found {
-            Some((_, group_name)) => VariableValidationResult {
+        let result = match resolution {
+            Some((source, canonical)) => VariableValidationResult {
+                group_name: match &source {
+                    VariableSource::Group(group) => Some(group.clone()),
+                    _ => None,
+                },
+                canonical_name: (canonical != var_name).then_some(canonical),
+                unlinked_groups: Vec::new(),
+                value_problem: None,
+                suggestion_unlinked: false,
+                suggestion: None,
                 variable_name: var_name,
-                group_name: Some(group_name.clone()),
                 exists: true,
                 error: None,
-                source: VariableSource::Group(group_name.clone()),
+                source,
+                locations: Vec::new(),
+                scope: None,
+                defined_in: Vec::new(),
+                warnings: Vec::new(),
             },
             None => VariableValidationResult {
+                suggestion: suggest_variable(
+                    &var_name,
+                    inline_variables.iter().map(|v| (v.as_str(), VariableSource::Inline)).collect(),
+                    available_variables,
+                    pipeline_definition_variables,
+                ),
                 variable_name: var_name,
                 group_name: None,
                 exists: false,
                 error: Some("Variable not found in any referenced variable group".to_string()),
                 source: VariableSource::NotFound,
+                locations: Vec::new(),
+                scope: None,
+                defined_in: Vec::new(),
+                warnings: Vec::new(),
+                canonical_name: None,
+                unlinked_groups: Vec::new(),
+                value_problem: None,
+                suggestion_unlinked: false,
             },
         };
         results.push(result);
@@ -247,6 +829,20 @@ pub fn validate_variables_against_available_with_inline(
 mod tests {
     use super::*;
 
+    use crate::azure::VariableValue;
+    use crate::backend::InMemoryBackend;
+    use crate::span::YamlPath;
+
+    fn span_at(line: usize) -> SourceSpan {
+        SourceSpan {
+            file: "azure-pipelines.yml".to_string(),
+            line,
+            column: 1,
+            byte_range: 0..1,
+            yaml_path: YamlPath::root(),
+        }
+    }
+
     // Tests for GroupValidationResult struct
     #[test]
     fn test_group_validation_result_exists() {
@@ -255,6 +851,8 @@ mod tests {
             exists: true,
             error: None,
             group_id: Some(123),
+            locations: Vec::new(),
+            suggestion: None,
         };
 
         assert_eq!(result.group_name, "MyGroup");
@@ -270,6 +868,8 @@ mod tests {
             exists: false,
             error: Some("Group not found".to_string()),
             group_id: None,
+            locations: Vec::new(),
+            suggestion: None,
         };
 
         assert_eq!(result.group_name, "MissingGroup");
@@ -287,6 +887,15 @@ mod tests {
             exists: true,
             error: None,
             source: VariableSource::Group("Secrets".to_string()),
+            locations: Vec::new(),
+            scope: None,
+            defined_in: Vec::new(),
+            warnings: Vec::new(),
+            canonical_name: None,
+            unlinked_groups: Vec::new(),
+            value_problem: None,
+            suggestion_unlinked: false,
+            suggestion: None,
         };
 
         assert_eq!(result.variable_name, "ApiKey");
@@ -304,6 +913,15 @@ mod tests {
             exists: false,
             error: Some("Variable not found".to_string()),
             source: VariableSource::NotFound,
+            locations: Vec::new(),
+            scope: None,
+            defined_in: Vec::new(),
+            warnings: Vec::new(),
+            canonical_name: None,
+            unlinked_groups: Vec::new(),
+            value_problem: None,
+            suggestion_unlinked: false,
+            suggestion: None,
         };
 
         assert_eq!(result.variable_name, "MissingVar");
@@ -321,6 +939,15 @@ mod tests {
             exists: true,
             error: None,
             source: VariableSource::Inline,
+            locations: Vec::new(),
+            scope: None,
+            defined_in: Vec::new(),
+            warnings: Vec::new(),
+            canonical_name: None,
+            unlinked_groups: Vec::new(),
+            value_problem: None,
+            suggestion_unlinked: false,
+            suggestion: None,
         };
 
         assert_eq!(result.variable_name, "BuildConfig");
@@ -453,7 +1080,7 @@ mod tests {
     }
 
     #[test]
-    fn test_validate_case_sensitive_matching() {
+    fn test_validate_case_insensitive_matching() {
         let available = vec![
             ("ConnectionString".to_string(), "Group1".to_string()),
         ];
@@ -467,7 +1094,9 @@ mod tests {
 
         assert_eq!(results.len(), 2);
         assert!(results[0].exists); // Exact match
-        assert!(!results[1].exists); // Case mismatch - not found
+        assert!(results[0].canonical_name.is_none());
+        assert!(results[1].exists); // Azure DevOps ignores case
+        assert_eq!(results[1].canonical_name, Some("ConnectionString".to_string()));
     }
 
     #[test]
@@ -575,8 +1204,8 @@ mod tests {
     }
 
     #[test]
-    fn test_pipeline_definition_takes_precedence_over_group() {
-        // If a variable is both in pipeline definition and a group, pipeline definition should take precedence
+    fn test_group_takes_precedence_over_pipeline_definition() {
+        // If a variable is both in pipeline definition and a group, the group should take precedence
         let available = vec![("SharedVar".to_string(), "Group1".to_string())];
         let inline: Vec<String> = vec![];
         let pipeline_def = vec!["SharedVar".to_string()];
@@ -592,7 +1221,324 @@ mod tests {
 
         assert_eq!(results.len(), 1);
         assert!(results[0].exists);
-        // Should be marked as pipeline definition, not group
-        assert_eq!(results[0].source, VariableSource::PipelineDefinition);
+        // Should be marked as group, not pipeline definition
+        assert_eq!(results[0].source, VariableSource::Group("Group1".to_string()));
+    }
+
+    // Tests for attaching locations to results
+
+    #[test]
+    fn test_attach_variable_locations() {
+        let available = vec![("Var1".to_string(), "Group1".to_string())];
+        let references = vec!["Var1".to_string(), "Missing".to_string()];
+        let mut results = validate_variables_against_available(references, &available);
+
+        let spans = vec![
+            VariableReference { name: "Missing".to_string(), span: span_at(3), syntax: VariableSyntax::Macro },
+            VariableReference { name: "Var1".to_string(), span: span_at(5), syntax: VariableSyntax::Macro },
+            VariableReference { name: "Missing".to_string(), span: span_at(9), syntax: VariableSyntax::Macro },
+        ];
+        attach_variable_locations(&mut results, &spans);
+
+        assert_eq!(results[0].locations, vec![span_at(5)]);
+        assert_eq!(results[1].locations, vec![span_at(3), span_at(9)]);
+    }
+
+    #[test]
+    fn test_attach_group_locations() {
+        let mut results = vec![GroupValidationResult {
+            group_name: "Secrets".to_string(),
+            exists: false,
+            error: None,
+            group_id: None,
+            locations: Vec::new(),
+            suggestion: None,
+        }];
+
+        let spans = vec![
+            GroupReference { name: "Other".to_string(), span: span_at(2) },
+            GroupReference { name: "Secrets".to_string(), span: span_at(4) },
+        ];
+        attach_group_locations(&mut results, &spans);
+
+        assert_eq!(results[0].locations, vec![span_at(4)]);
+    }
+
+    // Tests for scope-aware validation
+
+    const SCOPED_PIPELINE: &str = "
+variables:
+  - name: rootVar
+    value: a
+stages:
+  - stage: Build
+    jobs:
+      - job: BuildJob
+        steps:
+          - script: echo $(rootVar) $(DbPassword)
+  - stage: Deploy
+    variables:
+      - group: db-secrets
+    jobs:
+      - job: DeployJob
+        steps:
+          - script: echo $(DbPassword)
+";
+
+    fn scoped_references() -> (ScopeTree, Vec<VariableReference>) {
+        let yaml: serde_yaml::Value = serde_yaml::from_str(SCOPED_PIPELINE).unwrap();
+        let references = crate::parser::extract_variable_reference_spans_from_content(
+            SCOPED_PIPELINE,
+            "azure-pipelines.yml",
+        )
+        .unwrap();
+        (ScopeTree::from_value(&yaml), references)
+    }
+
+    #[test]
+    fn test_scoped_variable_visible_from_root() {
+        let (scopes, references) = scoped_references();
+        let results = validate_scoped_variables_against_available(&references, &scopes, &[], &[]);
+
+        let root_var = results.iter().find(|r| r.variable_name == "rootVar").unwrap();
+        assert!(root_var.exists);
+        assert_eq!(root_var.source, VariableSource::Inline);
+        assert_eq!(root_var.scope.as_deref(), Some("stage 'Build' / job 'BuildJob'"));
+    }
+
+    #[test]
+    fn test_scoped_group_variable_not_visible_in_other_stage() {
+        let (scopes, references) = scoped_references();
+        let available = vec![("DbPassword".to_string(), "db-secrets".to_string())];
+        let results = validate_scoped_variables_against_available(&references, &scopes, &available, &[]);
+
+        let usages: Vec<_> = results.iter().filter(|r| r.variable_name == "DbPassword").collect();
+        assert_eq!(usages.len(), 2);
+
+        // Used in Build, where db-secrets is not linked
+        assert!(!usages[0].exists);
+        assert_eq!(usages[0].defined_in, vec!["stage 'Deploy' (group 'db-secrets')"]);
+        assert_eq!(
+            usages[0].error.as_deref(),
+            Some("Variable is defined in stage 'Deploy' (group 'db-secrets') but used in stage 'Build' / job 'BuildJob'")
+        );
+
+        // Used in Deploy, where db-secrets is linked at stage level
+        assert!(usages[1].exists);
+        assert_eq!(usages[1].source, VariableSource::Group("db-secrets".to_string()));
+    }
+
+    #[test]
+    fn test_missing_variable_suggests_closest_name() {
+        let available = vec![
+            ("ConnectionString".to_string(), "app-secrets".to_string()),
+            ("ApiKey".to_string(), "app-secrets".to_string()),
+        ];
+        let references = vec!["ConectionString".to_string(), "Unrelated".to_string()];
+        let results = validate_variables_against_available_with_inline(references, &available, &[], &[]);
+
+        assert_eq!(
+            results[0].suggestion,
+            Some((VariableSource::Group("app-secrets".to_string()), "ConnectionString".to_string()))
+        );
+        assert!(results[1].suggestion.is_none());
+    }
+
+    #[test]
+    fn test_attach_group_suggestions() {
+        let mut results = vec![GroupValidationResult {
+            group_name: "app-secret".to_string(),
+            exists: false,
+            error: None,
+            group_id: None,
+            locations: Vec::new(),
+            suggestion: None,
+        }];
+        let project_groups = vec!["shared-config".to_string(), "app-secrets".to_string()];
+        attach_group_suggestions(&mut results, &project_groups);
+
+        assert_eq!(results[0].suggestion, Some("app-secrets".to_string()));
+    }
+
+    #[test]
+    fn test_attach_unlinked_groups() {
+        let mut results =
+            validate_variables_against_available_with_inline(vec!["SqlAdminPassword".to_string()], &[], &[], &[]);
+        let project_variables = vec![
+            ("SqlAdminPassword".to_string(), "db-prod".to_string()),
+            ("SqlAdminPassword".to_string(), "db-test".to_string()),
+            ("ApiKey".to_string(), "app-secrets".to_string()),
+        ];
+        attach_unlinked_groups(&mut results, &project_variables, &["db-test".to_string()]);
+
+        // db-test is already linked elsewhere in the pipeline, so it ranks first
+        assert_eq!(results[0].unlinked_groups, vec!["db-test", "db-prod"]);
+    }
+
+    #[test]
+    fn test_attach_project_suggestions() {
+        let mut results = validate_variables_against_available_with_inline(
+            vec!["ApiEndpont".to_string(), "Region".to_string()],
+            &[("Regions".to_string(), "app".to_string())],
+            &[],
+            &[],
+        );
+        let project_variables = vec![
+            ("ApiEndpoint".to_string(), "shared".to_string()),
+            ("Region".to_string(), "app".to_string()),
+        ];
+        attach_project_suggestions(&mut results, &project_variables, &["app".to_string()]);
+
+        assert_eq!(
+            results[0].suggestion,
+            Some((VariableSource::Group("shared".to_string()), "ApiEndpoint".to_string()))
+        );
+        assert!(results[0].suggestion_unlinked);
+        // A suggestion from a linked group is kept
+        assert_eq!(
+            results[1].suggestion,
+            Some((VariableSource::Group("app".to_string()), "Regions".to_string()))
+        );
+        assert!(!results[1].suggestion_unlinked);
+    }
+
+    fn in_memory_backend() -> InMemoryBackend {
+        let variables = HashMap::from([(
+            "DbPassword".to_string(),
+            VariableValue {
+                value: None,
+                is_secret: Some(true),
+            },
+        )]);
+        InMemoryBackend::new().with_group(VariableGroupData {
+            id: 3,
+            name: "db-secrets".to_string(),
+            variables,
+        })
+    }
+
+    #[test]
+    fn test_validate_against_backend() {
+        let backend = in_memory_backend();
+        let groups = validate_variable_groups(vec!["db-secrets".to_string(), "missing".to_string()], &backend).unwrap();
+        assert!(groups[0].exists);
+        assert_eq!(groups[0].group_id, Some(3));
+        assert!(!groups[1].exists);
+        assert_eq!(groups[1].error.as_deref(), Some("Variable group 'missing' not found"));
+
+        let results = validate_variables(
+            vec!["DbPassword".to_string(), "Region".to_string()],
+            &groups,
+            &[],
+            &[],
+            &backend,
+        )
+        .unwrap();
+        assert_eq!(results[0].source, VariableSource::Group("db-secrets".to_string()));
+        assert!(!results[1].exists);
+
+        let details = fetch_group_variable_details(&groups, &backend);
+        assert!(details[0].is_secret);
+    }
+
+    #[test]
+    fn test_attach_value_problems() {
+        let names = ["Region", "ApiUrl", "Owner", "Token", "Note", "Unknown"].map(|n| n.to_string()).to_vec();
+        let available: Vec<(String, String)> = names.iter().map(|n| (n.clone(), "app".to_string())).collect();
+        let mut results = validate_variables_against_available_with_inline(names, &available, &[], &[]);
+
+        let variable = |name: &str, value: Option<&str>, is_secret: bool| GroupVariable {
+            name: name.to_string(),
+            group: "app".to_string(),
+            value: value.map(|v| v.to_string()),
+            is_secret,
+        };
+        let group_variables = vec![
+            variable("Region", Some("westeurope"), false),
+            variable("ApiUrl", Some("  "), false),
+            variable("Owner", Some("TODO"), false),
+            variable("Token", None, true),
+            variable("Note", Some("<your note here>"), false),
+            variable("Unknown", None, false),
+        ];
+        let placeholder = Regex::new(DEFAULT_PLACEHOLDER_PATTERN).unwrap();
+        attach_value_problems(&mut results, &group_variables, &placeholder);
+
+        let problems: Vec<Option<&str>> = results.iter().map(|r| r.value_problem.as_deref()).collect();
+        assert_eq!(
+            problems,
+            vec![
+                None,
+                Some("an empty value"),
+                Some("the placeholder value 'TODO'"),
+                None,
+                Some("the placeholder value '<your note here>'"),
+                None,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_scoped_resolution_ignores_case() {
+        let (scopes, references) = scoped_references();
+        let available = vec![("dbpassword".to_string(), "db-secrets".to_string())];
+        let results = validate_scoped_variables_against_available(&references, &scopes, &available, &[]);
+
+        let deploy = results
+            .iter()
+            .find(|r| r.variable_name == "DbPassword" && r.exists)
+            .unwrap();
+        assert_eq!(deploy.source, VariableSource::Group("db-secrets".to_string()));
+        assert_eq!(deploy.canonical_name, Some("dbpassword".to_string()));
+    }
+
+    const RUNTIME_PIPELINE: &str = "
+jobs:
+  - job: Build
+    steps:
+      - script: echo $(imageTag)
+      - bash: echo \"##vso[task.setvariable variable=imageTag]1.0\"
+        name: setTag
+      - script: docker push app:$(imageTag)
+  - job: Deploy
+    steps:
+      - script: echo $(imageTag)
+";
+
+    #[test]
+    fn test_runtime_set_variables_visible_to_later_steps() {
+        let yaml: serde_yaml::Value = serde_yaml::from_str(RUNTIME_PIPELINE).unwrap();
+        let scopes = ScopeTree::from_value(&yaml);
+        let references =
+            crate::parser::extract_variable_reference_spans_from_content(RUNTIME_PIPELINE, "azure-pipelines.yml")
+                .unwrap();
+        let results = validate_scoped_variables_against_available(&references, &scopes, &[], &[]);
+
+        let found: Vec<(usize, &VariableSource)> = results.iter().map(|r| (r.locations[0].line, &r.source)).collect();
+        assert_eq!(
+            found,
+            vec![
+                // Used before the step that sets it
+                (5, &VariableSource::NotFound),
+                (
+                    8,
+                    &VariableSource::RuntimeSet {
+                        step: "job 'Build' / step 'setTag'".to_string()
+                    }
+                ),
+                // Runtime variables do not cross jobs
+                (11, &VariableSource::NotFound),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_scoped_results_carry_locations() {
+        let (scopes, references) = scoped_references();
+        let results = validate_scoped_variables_against_available(&references, &scopes, &[], &[]);
+
+        let missing = results.iter().find(|r| r.variable_name == "DbPassword").unwrap();
+        assert_eq!(missing.locations.len(), 1);
+        assert_eq!(missing.locations[0].line, 10);
     }
 }
@@ -1,6 +1,25 @@
 //! Integration tests for Azure DevOps pipeline YAML parsing
 
-use azdolint::parser::{extract_variable_references, parse_pipeline_file, VariableEntry};
+use azdolint::parser::{
+    detect_template, extract_group_references, extract_template_references,
+    extract_variable_reference_spans, extract_variable_references, parse_pipeline_file, JobEntry,
+    TemplateKind, VariableEntry, VariableSyntax,
+};
+use azdolint::outputs::{validate_output_references, OutputFindingKind};
+use azdolint::overrides::{check_overrides, parse_overrides, OverrideStatus};
+use azdolint::parameters::ParameterFindingKind;
+use azdolint::precedence::diagnose_precedence;
+use azdolint::scope::ScopeTree;
+use azdolint::backend::VariableBackend;
+use azdolint::secrets::{find_secret_misuse, SecretMisuseKind};
+use azdolint::snapshot::Snapshot;
+use azdolint::template::{expand_templates, expand_templates_with_parameters, TemplateProblemKind};
+use azdolint::usage::{find_unused_groups, find_unused_variables, VariableUsage};
+use azdolint::validator::{
+    fetch_group_variable_details, validate_scoped_variables_against_available, validate_variable_groups,
+    validate_variables_against_available, GroupValidationResult, GroupVariable, PipelineVariable,
+    VariableSource,
+};
 
 /// Test parsing a pipeline file with variable groups only
 #[test]
@@ -207,8 +226,11 @@ fn test_regular_variables_extracted_with_filtering() {
     assert!(var_refs.contains(&"ApiKey".to_string()));
     assert!(var_refs.contains(&"ConnectionString".to_string()));
 
-    // Should have exactly 3 variables (all the regular ones)
-    assert_eq!(var_refs.len(), 3, "Should only have 3 regular variables after filtering");
+    // Runtime expression $[variables.someVar] is validated too
+    assert!(var_refs.contains(&"someVar".to_string()));
+
+    // Should have exactly 4 variables (all the regular ones)
+    assert_eq!(var_refs.len(), 4, "Should only have 4 regular variables after filtering");
 }
 
 /// Test parsing a pipeline with template conditional variables
@@ -300,3 +322,807 @@ fn test_regular_variables_with_shell_commands() {
     assert!(var_refs.contains(&"SIMPLE_VAR".to_string()),
         "Should find SIMPLE_VAR variable reference");
 }
+
+/// Test that variable references carry file, line, column and YAML path
+#[test]
+fn test_variable_reference_spans() {
+    let path = "tests/fixtures/pipeline_with_stages.yml";
+    let refs = extract_variable_reference_spans(path).expect("Failed to extract variable references");
+
+    let conn = refs
+        .iter()
+        .find(|r| r.name == "ConnectionString")
+        .expect("ConnectionString reference not found");
+    assert_eq!(conn.span.file, path);
+    assert_eq!(conn.span.line, 21);
+    assert_eq!(conn.span.column, 33);
+    assert_eq!(conn.span.yaml_path.to_string(), "stages[0].jobs[0].steps[0].script");
+
+    let deploy = refs
+        .iter()
+        .find(|r| r.name == "DeployToken")
+        .expect("DeployToken reference not found");
+    assert_eq!(deploy.span.yaml_path.to_string(), "stages[1].jobs[0].steps[0].script");
+}
+
+/// Test that every occurrence is reported, not just unique names
+#[test]
+fn test_variable_reference_spans_include_duplicates() {
+    let path = "tests/fixtures/pipeline_with_conditionals.yml";
+    let refs = extract_variable_reference_spans(path).expect("Failed to extract variable references");
+
+    let occurrences: Vec<_> = refs.iter().filter(|r| r.name == "serviceConnection").collect();
+    assert_eq!(occurrences.len(), 2);
+    assert_eq!(occurrences[0].span.yaml_path.to_string(), "stages[0].jobs[0].steps[0].inputs.azureSubscription");
+    assert_eq!(occurrences[1].span.yaml_path.to_string(), "stages[0].jobs[0].steps[0].inputs.inlineScript");
+}
+
+/// Test that variable group links carry their locations
+#[test]
+fn test_group_reference_spans() {
+    let path = "tests/fixtures/pipeline_with_stages.yml";
+    let groups = extract_group_references(path).expect("Failed to extract group references");
+
+    let names: Vec<&str> = groups.iter().map(|g| g.name.as_str()).collect();
+    assert_eq!(names, vec!["build-secrets", "job-level-group", "deploy-secrets"]);
+
+    assert_eq!(groups[0].span.line, 10);
+    assert_eq!(groups[0].span.column, 16);
+    assert_eq!(groups[0].span.yaml_path.to_string(), "stages[0].variables[0].group");
+}
+
+/// Test that variables are resolved only against groups visible in the scope where they are used
+#[test]
+fn test_scoped_resolution_across_stages() {
+    let path = "tests/fixtures/pipeline_with_stages.yml";
+    let refs = extract_variable_reference_spans(path).expect("Failed to extract variable references");
+    let scopes = ScopeTree::from_file(path).expect("Failed to build scope tree");
+
+    // ConnectionString only exists in the Deploy stage's group, DeployToken in the Build job's group
+    let available = vec![
+        ("ConnectionString".to_string(), "deploy-secrets".to_string()),
+        ("DeployToken".to_string(), "job-level-group".to_string()),
+        ("Environment".to_string(), "deploy-secrets".to_string()),
+    ];
+    let results = validate_scoped_variables_against_available(&refs, &scopes, &available, &[]);
+
+    let find = |name: &str| results.iter().find(|r| r.variable_name == name).unwrap();
+
+    // Stage- and root-level inline variables are visible in the Build job
+    assert_eq!(find("buildConfig").source, VariableSource::Inline);
+    assert_eq!(find("platformBuildNumber").source, VariableSource::Inline);
+
+    // Group linked in Deploy does not leak into Build
+    let conn = find("ConnectionString");
+    assert!(!conn.exists);
+    assert_eq!(conn.scope.as_deref(), Some("stage 'Build' / job 'BuildJob'"));
+    assert_eq!(conn.defined_in, vec!["stage 'Deploy' (group 'deploy-secrets')"]);
+
+    // Job-level group in Build does not leak into Deploy
+    let token = find("DeployToken");
+    assert!(!token.exists);
+    assert_eq!(token.defined_in, vec!["stage 'Build' / job 'BuildJob' (group 'job-level-group')"]);
+
+    // Stage-level group in Deploy is visible to its job
+    assert_eq!(find("Environment").source, VariableSource::Group("deploy-secrets".to_string()));
+}
+
+/// Test that template references are found in stages, jobs, steps and variables lists
+#[test]
+fn test_extract_template_references_at_any_depth() {
+    let path = "tests/fixtures/pipeline_with_templates.yml";
+    let refs = extract_template_references(path).expect("Failed to extract template references");
+
+    let found: Vec<(&str, TemplateKind)> = refs.iter().map(|r| (r.template_path.as_str(), r.kind)).collect();
+    assert_eq!(
+        found,
+        vec![
+            ("templates/vars/common.yml", TemplateKind::Variables),
+            ("templates/jobs/build.yml", TemplateKind::Jobs),
+            ("templates/steps/deploy.yml", TemplateKind::Steps),
+        ]
+    );
+
+    // Job template sees root and stage groups
+    assert_eq!(refs[1].stage_name.as_deref(), Some("Build"));
+    assert_eq!(refs[1].available_groups, vec!["shared-config", "build-secrets"]);
+    assert_eq!(refs[1].span.line, 14);
+    assert_eq!(refs[1].span.yaml_path.to_string(), "stages[0].jobs[0].template");
+
+    // Step template inside a conditional
+    assert_eq!(refs[2].stage_name.as_deref(), Some("Deploy"));
+    assert_eq!(refs[2].available_groups, vec!["shared-config"]);
+}
+
+/// Test that nested templates are expanded in the scope they are inserted into
+#[test]
+fn test_expand_nested_templates() {
+    let path = "tests/fixtures/pipeline_with_templates.yml";
+    let expansion = expand_templates(path).expect("Failed to expand templates");
+
+    assert!(expansion.problems.is_empty());
+
+    let expanded: Vec<(&str, usize)> = expansion
+        .instances
+        .iter()
+        .map(|i| (i.reference.template_path.as_str(), i.depth))
+        .collect();
+    assert_eq!(
+        expanded,
+        vec![
+            ("templates/vars/common.yml", 1),
+            ("extra.yml", 2),
+            ("templates/jobs/build.yml", 1),
+            ("../steps/publish.yml", 2),
+            ("templates/steps/deploy.yml", 1),
+        ]
+    );
+
+    // Variables templates are merged into the scope that includes them
+    let root = &expansion.scopes.scopes()[0];
+    assert!(root.inline_variables.contains(&"CommonVar".to_string()));
+    assert!(root.groups.contains(&"extra-config".to_string()));
+    assert_eq!(
+        expansion.variable_groups(),
+        vec!["shared-config", "extra-config", "build-secrets"]
+    );
+
+    let publish = &expansion.instances[3];
+    assert_eq!(publish.inserted_into, "stage 'Build' / template 'templates/jobs/build.yml' / job 'Compile'");
+    assert_eq!(
+        publish.included_from,
+        vec![
+            "tests/fixtures/pipeline_with_templates.yml".to_string(),
+            "tests/fixtures/templates/jobs/build.yml".to_string(),
+        ]
+    );
+
+    let available = vec![
+        ("BuildSecret".to_string(), "build-secrets".to_string()),
+        ("ArtifactFeed".to_string(), "extra-config".to_string()),
+    ];
+
+    // Job template resolves against the Build stage group and the root variables template
+    let build = &expansion.instances[2];
+    let results = validate_scoped_variables_against_available(&build.variable_references, &build.scopes, &available, &[]);
+    assert!(results.iter().all(|r| r.exists));
+    assert_eq!(results[0].scope.as_deref(), Some("stage 'Build' / template 'templates/jobs/build.yml' / job 'Compile'"));
+    assert_eq!(results[0].locations[0].file, "tests/fixtures/templates/jobs/build.yml");
+
+    // Nested step template sees the group merged from the nested variables template
+    let results = validate_scoped_variables_against_available(&publish.variable_references, &publish.scopes, &available, &[]);
+    assert_eq!(results[0].source, VariableSource::Group("extra-config".to_string()));
+
+    // The Deploy stage does not link build-secrets
+    let deploy = &expansion.instances[4];
+    let results = validate_scoped_variables_against_available(&deploy.variable_references, &deploy.scopes, &available, &[]);
+    assert!(!results[0].exists);
+}
+
+/// Test that template cycles are reported instead of expanded forever
+#[test]
+fn test_expand_templates_detects_cycle() {
+    let path = "tests/fixtures/pipeline_with_template_cycle.yml";
+    let expansion = expand_templates(path).expect("Failed to expand templates");
+
+    assert_eq!(expansion.instances.len(), 2);
+    assert_eq!(expansion.problems.len(), 1);
+
+    let problem = &expansion.problems[0];
+    assert_eq!(problem.kind, TemplateProblemKind::Cycle);
+    assert!(problem.is_error());
+    assert_eq!(
+        problem.message,
+        "Template cycle detected: tests/fixtures/templates/cycle/a.yml -> tests/fixtures/templates/cycle/b.yml -> tests/fixtures/templates/cycle/a.yml"
+    );
+}
+
+/// Test that a pipeline extending a template is validated through the base template
+#[test]
+fn test_expand_extends_template() {
+    let path = "tests/fixtures/pipeline_with_extends.yml";
+
+    // Runtime parameters of an extending pipeline do not make it a template
+    assert!(!detect_template(path).expect("Failed to detect template").is_template);
+
+    let pipeline = parse_pipeline_file(path).expect("Failed to parse pipeline file");
+    let extends = pipeline.extends.expect("Pipeline should extend a template");
+    assert_eq!(extends.template, "templates/extends/pipeline-base.yml");
+
+    let expansion = expand_templates(path).expect("Failed to expand templates");
+    assert!(expansion.problems.is_empty());
+    assert_eq!(expansion.instances.len(), 1);
+    assert_eq!(expansion.variable_groups(), vec!["shared-config", "base-secrets"]);
+
+    let base = &expansion.instances[0];
+    assert_eq!(base.reference.kind, TemplateKind::Extends);
+    assert_eq!(base.reference.span.yaml_path.to_string(), "extends.template");
+
+    // Supplied parameters override defaults, the rest keep their defaults
+    assert_eq!(base.parameters.get("environment").and_then(|v| v.as_str()), Some("prod"));
+    assert_eq!(base.parameters.get("poolName").and_then(|v| v.as_str()), Some("ubuntu-latest"));
+    assert!(base.parameters.get("buildSteps").unwrap().is_sequence());
+
+    // Base template sees the root variables of the extending file and its own stage group
+    let available = vec![("BaseSecret".to_string(), "base-secrets".to_string())];
+    let results = validate_scoped_variables_against_available(&base.variable_references, &base.scopes, &available, &[]);
+    let find = |name: &str| results.iter().find(|r| r.variable_name == name).unwrap();
+    assert_eq!(find("AppName").source, VariableSource::Inline);
+    assert_eq!(find("BaseSecret").source, VariableSource::Group("base-secrets".to_string()));
+
+    // Steps passed as parameters are validated in the extending file
+    let results = validate_scoped_variables_against_available(&expansion.variable_references, &expansion.scopes, &[], &[]);
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].source, VariableSource::Inline);
+    assert_eq!(results[0].locations[0].yaml_path.to_string(), "extends.parameters.buildSteps[0].script");
+}
+
+/// Test that parameters passed to each template are checked against its declarations
+#[test]
+fn test_template_parameter_checks() {
+    let path = "tests/fixtures/pipeline_with_template_parameters.yml";
+    let expansion = expand_templates(path).expect("Failed to expand templates");
+
+    assert_eq!(expansion.instances.len(), 2);
+    assert!(expansion.instances[0].parameter_findings.is_empty());
+
+    let findings: Vec<(&str, ParameterFindingKind)> = expansion.instances[1]
+        .parameter_findings
+        .iter()
+        .map(|f| (f.parameter.as_str(), f.kind))
+        .collect();
+    assert_eq!(
+        findings,
+        vec![
+            ("environment", ParameterFindingKind::NotAllowed),
+            ("replicas", ParameterFindingKind::TypeMismatch),
+            ("serviceConnection", ParameterFindingKind::Missing),
+            ("region", ParameterFindingKind::Unknown),
+        ]
+    );
+    assert_eq!(
+        expansion.instances[1].parameter_findings[0].message,
+        "parameter 'environment' value 'prd' not in allowed values [dev, test, prod]"
+    );
+    assert_eq!(expansion.instances[1].reference.span.line, 11);
+}
+
+/// Test that only the `${{ }}` branches compiled for the given parameters are validated
+#[test]
+fn test_expand_templates_evaluates_conditionals() {
+    let path = "tests/fixtures/pipeline_with_template_expressions.yml";
+
+    // Default runtime parameter: dev
+    let expansion = expand_templates(path).expect("Failed to expand templates");
+    assert_eq!(expansion.variable_groups(), vec!["dev-secrets"]);
+
+    let template = &expansion.instances[0];
+    assert!(template.parameter_findings.is_empty());
+    assert_eq!(template.parameters.get("environment").and_then(|v| v.as_str()), Some("dev"));
+    let names: Vec<&str> = template.variable_references.iter().map(|r| r.name.as_str()).collect();
+    assert_eq!(names, vec!["DevOnlySecret", "RegionSecret"]);
+
+    // Runtime parameter supplied on the command line: prod
+    let mut parameters = serde_yaml::Mapping::new();
+    parameters.insert("environment".into(), "prod".into());
+    let expansion = expand_templates_with_parameters(path, &parameters).expect("Failed to expand templates");
+    assert_eq!(expansion.variable_groups(), vec!["prod-secrets"]);
+
+    let names: Vec<&str> = expansion.instances[0].variable_references.iter().map(|r| r.name.as_str()).collect();
+    assert_eq!(names, vec!["ProdOnlySecret", "RegionSecret"]);
+    assert_eq!(expansion.inactive.len(), 1);
+    assert_eq!(expansion.inactive[0].to_string(), "variables[1]['${{ else }}']");
+}
+
+/// Test that runtime and template expression references are extracted with their syntax
+#[test]
+fn test_expression_variable_references() {
+    let path = "tests/fixtures/pipeline_with_expression_variables.yml";
+    let refs = extract_variable_reference_spans(path).expect("Failed to extract variable references");
+
+    let found: Vec<(&str, VariableSyntax)> = refs.iter().map(|r| (r.name.as_str(), r.syntax)).collect();
+    assert_eq!(
+        found,
+        vec![
+            ("buildConfiguration", VariableSyntax::RuntimeExpression),
+            ("buildConfiguration", VariableSyntax::TemplateExpression),
+            ("ApiKey", VariableSyntax::TemplateExpression),
+            ("ApiKey", VariableSyntax::Macro),
+            ("configCopy", VariableSyntax::Macro),
+        ]
+    );
+
+    // The span covers the variables.name accessor inside the expression
+    assert_eq!(refs[0].span.line, 12);
+    assert_eq!(refs[0].span.column, 15);
+    assert_eq!(refs[0].span.byte_range.len(), "variables.buildConfiguration".len());
+}
+
+/// Test that template expressions using group variables get a compile-time warning
+#[test]
+fn test_template_expression_group_variable_warning() {
+    let path = "tests/fixtures/pipeline_with_expression_variables.yml";
+    let refs = extract_variable_reference_spans(path).expect("Failed to extract variable references");
+    let scopes = ScopeTree::from_file(path).expect("Failed to build scope tree");
+
+    let available = vec![("ApiKey".to_string(), "app-secrets".to_string())];
+    let results = validate_scoped_variables_against_available(&refs, &scopes, &available, &[]);
+    let find = |name: &str| results.iter().find(|r| r.variable_name == name).unwrap();
+
+    let config = find("buildConfiguration");
+    assert_eq!(config.source, VariableSource::Inline);
+    assert!(config.warnings.is_empty());
+
+    let api_key = find("ApiKey");
+    assert!(api_key.exists);
+    assert_eq!(api_key.locations.len(), 2);
+    assert_eq!(api_key.warnings.len(), 1);
+    assert!(api_key.warnings[0].contains("only exists in variable group 'app-secrets'"));
+}
+
+/// Test that output variable references are checked against the steps, jobs and stages that set them
+#[test]
+fn test_output_variable_references() {
+    let path = "tests/fixtures/pipeline_with_output_variables.yml";
+    let expansion = expand_templates(path).expect("Failed to expand templates");
+    let index = expansion.output_index();
+
+    let results = validate_output_references(&expansion.output_references, &expansion.scopes, &index, &|_, _| false);
+    let found: Vec<(&str, Option<OutputFindingKind>)> = results
+        .iter()
+        .map(|r| (r.reference.as_str(), r.finding.as_ref().map(|f| f.kind)))
+        .collect();
+    assert_eq!(
+        found,
+        vec![
+            ("setVersion.version", None),
+            ("dependencies.Compile.outputs['setVersion.version']", None),
+            (
+                "dependencies.Compile.outputs['setVersion.verison']",
+                Some(OutputFindingKind::UnknownVariable)
+            ),
+            (
+                "stageDependencies.Build.Compile.outputs['setVersion.version']",
+                Some(OutputFindingKind::MissingDependency)
+            ),
+        ]
+    );
+
+    // The producing step comes from a steps template inserted into the job
+    assert_eq!(
+        results[0].producer.as_deref(),
+        Some("stage 'Build' / job 'Compile' / template 'templates/steps/set-version.yml' / step 'setVersion'")
+    );
+    assert_eq!(results[2].locations[0].line, 15);
+}
+
+/// Test that variables set with task.setvariable resolve for later steps, including step templates
+#[test]
+fn test_runtime_set_variables() {
+    let path = "tests/fixtures/pipeline_with_runtime_variables.yml";
+    let expansion = expand_templates(path).expect("Failed to expand templates");
+
+    let results =
+        validate_scoped_variables_against_available(&expansion.variable_references, &expansion.scopes, &[], &[]);
+    assert_eq!(results.len(), 1);
+    assert_eq!(
+        results[0].source,
+        VariableSource::RuntimeSet {
+            step: "job 'Build' / step 'Look up registry'".to_string()
+        }
+    );
+
+    let instance = &expansion.instances[0];
+    let results =
+        validate_scoped_variables_against_available(&instance.variable_references, &instance.scopes, &[], &[]);
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].variable_name, "imageTag");
+    assert_eq!(
+        results[0].source,
+        VariableSource::RuntimeSet {
+            step: "job 'Build' / step 'computeTag'".to_string()
+        }
+    );
+}
+
+/// Test that deployment jobs, their strategies and lifecycle hooks are parsed
+#[test]
+fn test_parse_deployment_jobs() {
+    let path = "tests/fixtures/pipeline_with_deployments.yml";
+    let pipeline = parse_pipeline_file(path).expect("Failed to parse pipeline file");
+
+    let groups = pipeline.get_variable_groups();
+    assert_eq!(groups, vec!["shared-config", "web-secrets"]);
+
+    let jobs = pipeline.stages.as_ref().unwrap()[0].jobs.as_ref().unwrap();
+    assert_eq!(jobs.len(), 3);
+
+    let JobEntry::Deployment(web) = &jobs[0] else {
+        panic!("DeployWeb should be a deployment job");
+    };
+    assert_eq!(web.deployment, "DeployWeb");
+    let hooks: Vec<&str> = web.hooks().iter().map(|(name, _)| *name).collect();
+    assert_eq!(hooks, vec!["preDeploy", "deploy", "on.failure"]);
+    assert_eq!(web.hooks()[1].1.steps.as_ref().unwrap().len(), 2);
+
+    let JobEntry::Deployment(api) = &jobs[1] else {
+        panic!("DeployApi should be a deployment job");
+    };
+    assert!(api.strategy.as_ref().unwrap().canary.is_some());
+    let hooks: Vec<&str> = api.hooks().iter().map(|(name, _)| *name).collect();
+    assert_eq!(hooks, vec!["deploy", "routeTraffic", "postRouteTraffic"]);
+
+    assert!(matches!(jobs[2], JobEntry::Job(_)));
+}
+
+/// Test that variables in lifecycle hooks resolve against the deployment job's scope
+#[test]
+fn test_deployment_job_scoping() {
+    let path = "tests/fixtures/pipeline_with_deployments.yml";
+    let refs = extract_variable_reference_spans(path).expect("Failed to extract variable references");
+    let scopes = ScopeTree::from_file(path).expect("Failed to build scope tree");
+
+    let available = vec![
+        ("WebAppName".to_string(), "shared-config".to_string()),
+        ("DeployToken".to_string(), "web-secrets".to_string()),
+        ("ApiToken".to_string(), "web-secrets".to_string()),
+    ];
+    let results = validate_scoped_variables_against_available(&refs, &scopes, &available, &[]);
+    let find = |name: &str| results.iter().find(|r| r.variable_name == name).unwrap();
+
+    let app_name = find("WebAppName");
+    assert!(app_name.exists);
+    assert_eq!(app_name.scope.as_deref(), Some("stage 'Production' / deployment 'DeployWeb'"));
+    assert_eq!(app_name.locations.len(), 2);
+
+    let token = find("DeployToken");
+    assert_eq!(token.source, VariableSource::Group("web-secrets".to_string()));
+    assert_eq!(
+        token.locations[0].yaml_path.to_string(),
+        "stages[0].jobs[0].strategy.runOnce.deploy.steps[1].script"
+    );
+
+    // web-secrets is linked on DeployWeb only
+    let api_token = find("ApiToken");
+    assert!(!api_token.exists);
+    assert_eq!(api_token.defined_in, vec!["stage 'Production' / deployment 'DeployWeb' (group 'web-secrets')"]);
+}
+
+/// Test that output variables of deployment jobs need the job name prefix
+#[test]
+fn test_deployment_output_variables() {
+    let path = "tests/fixtures/pipeline_with_deployments.yml";
+    let expansion = expand_templates(path).expect("Failed to expand templates");
+    let index = expansion.output_index();
+
+    let results = validate_output_references(&expansion.output_references, &expansion.scopes, &index, &|_, _| false);
+    let found: Vec<(&str, Option<OutputFindingKind>)> = results
+        .iter()
+        .map(|r| (r.reference.as_str(), r.finding.as_ref().map(|f| f.kind)))
+        .collect();
+    assert_eq!(
+        found,
+        vec![
+            ("dependencies.DeployWeb.outputs['DeployWeb.setSlot.slotName']", None),
+            (
+                "dependencies.DeployWeb.outputs['setSlot.slotName']",
+                Some(OutputFindingKind::UnknownStep)
+            ),
+        ]
+    );
+    assert_eq!(
+        results[0].producer.as_deref(),
+        Some("stage 'Production' / deployment 'DeployWeb' / step 'setSlot'")
+    );
+}
+
+/// Test that root jobs of a single-stage pipeline are parsed like jobs in a stage
+#[test]
+fn test_single_stage_pipeline() {
+    let path = "tests/fixtures/pipeline_single_stage.yml";
+    let pipeline = parse_pipeline_file(path).expect("Failed to parse pipeline file");
+
+    assert!(pipeline.stages.is_none());
+    assert_eq!(pipeline.jobs().len(), 2);
+    assert_eq!(pipeline.get_variable_groups(), vec!["shared-config", "package-secrets"]);
+    assert_eq!(pipeline.get_inline_variable_names(), vec!["configuration"]);
+
+    let refs = extract_template_references(path).expect("Failed to extract template references");
+    let found: Vec<(&str, TemplateKind)> = refs.iter().map(|r| (r.template_path.as_str(), r.kind)).collect();
+    assert_eq!(
+        found,
+        vec![
+            ("templates/vars/common.yml", TemplateKind::Variables),
+            ("templates/jobs/build.yml", TemplateKind::Jobs),
+        ]
+    );
+    assert_eq!(refs[1].available_groups, vec!["shared-config"]);
+
+    // The job template sees the root variables and the variables template merged into them
+    let expansion = expand_templates(path).expect("Failed to expand templates");
+    let jobs = expansion
+        .instances
+        .iter()
+        .find(|i| i.reference.kind == TemplateKind::Jobs)
+        .unwrap();
+    let results = validate_scoped_variables_against_available(&jobs.variable_references, &jobs.scopes, &[], &[]);
+    let common = results.iter().find(|r| r.variable_name == "CommonVar").unwrap();
+    assert_eq!(common.source, VariableSource::Inline);
+    assert_eq!(common.scope.as_deref(), Some("template 'templates/jobs/build.yml' / job 'Compile'"));
+}
+
+/// Test that root steps of a single-job pipeline are parsed like steps in a job
+#[test]
+fn test_single_job_pipeline() {
+    let path = "tests/fixtures/pipeline_single_job.yml";
+    let pipeline = parse_pipeline_file(path).expect("Failed to parse pipeline file");
+
+    assert_eq!(pipeline.steps.as_ref().unwrap().len(), 2);
+    assert!(pipeline.jobs().is_empty());
+    assert_eq!(pipeline.get_variable_groups(), vec!["shared-config"]);
+
+    let refs = extract_template_references(path).expect("Failed to extract template references");
+    assert_eq!(refs.len(), 1);
+    assert_eq!(refs[0].kind, TemplateKind::Steps);
+    assert_eq!(refs[0].available_inline_vars, vec!["configuration"]);
+
+    let expansion = expand_templates(path).expect("Failed to expand templates");
+    assert_eq!(expansion.instances.len(), 1);
+    assert_eq!(expansion.instances[0].inserted_into, "pipeline root");
+    assert_eq!(expansion.instances[0].variable_references[0].name, "ArtifactFeed");
+}
+
+/// Test that matrix legs define job-scoped variables
+#[test]
+fn test_matrix_variables() {
+    let path = "tests/fixtures/pipeline_with_matrix.yml";
+    let pipeline = parse_pipeline_file(path).expect("Failed to parse pipeline file");
+
+    let jobs = pipeline.jobs();
+    let JobEntry::Job(python) = jobs[0] else {
+        panic!("Python should be a regular job");
+    };
+    let matrix = python.strategy.as_ref().unwrap().matrix().unwrap();
+    assert_eq!(matrix.legs.len(), 3);
+    assert_eq!(matrix.variables(), vec!["imageName", "pythonVersion"]);
+    assert_eq!(matrix.legs_missing("pythonVersion"), vec!["windows"]);
+
+    let expansion = expand_templates(path).expect("Failed to expand templates");
+    let results =
+        validate_scoped_variables_against_available(&expansion.variable_references, &expansion.scopes, &[], &[]);
+    let find = |name: &str| results.iter().find(|r| r.variable_name == name).unwrap();
+
+    let python_version = find("pythonVersion");
+    assert!(python_version.exists);
+    assert_eq!(
+        python_version.source,
+        VariableSource::Matrix {
+            job: "stage 'Test' / job 'Python'".to_string()
+        }
+    );
+    assert_eq!(python_version.warnings.len(), 1);
+    assert!(python_version.warnings[0].contains("'windows'"));
+
+    let image_name = find("imageName");
+    assert!(image_name.exists);
+    assert!(image_name.warnings.is_empty());
+
+    // Legs generated by ${{ each }} are read through the loop
+    let node_version = find("nodeVersion");
+    assert!(node_version.exists);
+    assert!(node_version.warnings.is_empty());
+
+    // A runtime matrix cannot be checked, which is reported instead of failing
+    let region = find("region");
+    assert!(region.exists);
+    assert_eq!(
+        region.source,
+        VariableSource::Matrix {
+            job: "stage 'Test' / job 'Deploy'".to_string()
+        }
+    );
+    assert!(region.warnings[0].contains("cannot be validated statically"));
+
+    // Parallel strategies define no variables
+    assert!(!find("shardName").exists);
+}
+
+/// Test that inline variables and groups nothing reads are reported
+#[test]
+fn test_unused_definitions() {
+    let path = "tests/fixtures/pipeline_with_unused_variables.yml";
+    let expansion = expand_templates(path).expect("Failed to expand templates");
+
+    let definitions: Vec<&str> = expansion.variable_definitions.iter().map(|d| d.name.as_str()).collect();
+    assert_eq!(
+        definitions,
+        vec![
+            "buildConfiguration",
+            "staleSetting",
+            "system.debug",
+            "feed.name",
+            "outputDir",
+            "unusedStageVar",
+            "manualOnly"
+        ]
+    );
+
+    let usage = VariableUsage::from_expansion(&expansion);
+    let unused = find_unused_variables(&expansion, &usage);
+    let names: Vec<&str> = unused.iter().map(|u| u.name.as_str()).collect();
+    assert_eq!(names, vec!["staleSetting", "unusedStageVar"]);
+    assert_eq!(unused[0].locations[0].line, 8);
+    assert_eq!(unused[1].locations[0].yaml_path.to_string(), "stages[0].variables.unusedStageVar");
+
+    let group = |name: &str| GroupValidationResult {
+        group_name: name.to_string(),
+        exists: true,
+        error: None,
+        group_id: Some(1),
+        locations: Vec::new(),
+        suggestion: None,
+    };
+    let groups = vec![group("app-secrets"), group("stale-secrets")];
+    let available = vec![
+        ("DeployRegion".to_string(), "app-secrets".to_string()),
+        ("OldToken".to_string(), "stale-secrets".to_string()),
+    ];
+    let unused_groups = find_unused_groups(&groups, &available, &usage);
+    assert_eq!(unused_groups.len(), 1);
+    assert_eq!(unused_groups[0].name, "stale-secrets");
+}
+
+/// Test that shadowed variables list their definition sites in precedence order
+#[test]
+fn test_variable_precedence() {
+    let path = "tests/fixtures/pipeline_with_shadowed_variables.yml";
+    let expansion = expand_templates(path).expect("Failed to expand templates");
+
+    let variable = |name: &str, group: &str, value: &str| GroupVariable {
+        name: name.to_string(),
+        group: group.to_string(),
+        value: Some(value.to_string()),
+        is_secret: false,
+    };
+    let group_variables = vec![
+        variable("Tier", "shared", "basic"),
+        variable("Tier", "overrides", "premium"),
+        variable("Port", "web-settings", "8080"),
+    ];
+
+    let reports = diagnose_precedence(&expansion.variable_references, &expansion.scopes, &group_variables, &[]);
+    let names: Vec<&str> = reports.iter().map(|r| r.variable_name.as_str()).collect();
+    assert_eq!(names, vec!["Region", "Tier"]);
+
+    let region = &reports[0];
+    assert_eq!(region.scope, "stage 'Deploy' / job 'Web'");
+    assert_eq!(region.winner().scope.as_deref(), Some("stage 'Deploy'"));
+    assert_eq!(region.winner().value.as_deref(), Some("northeurope"));
+    assert!(region.warnings.is_empty());
+
+    let tier = &reports[1];
+    assert_eq!(tier.winner().source, VariableSource::Group("overrides".to_string()));
+    assert_eq!(tier.warnings.len(), 1);
+    assert_eq!(tier.locations[0].line, 19);
+}
+
+/// Test that secrets used in conditions and read by scripts without an env mapping are reported
+#[test]
+fn test_secret_misuse() {
+    let path = "tests/fixtures/pipeline_with_secrets.yml";
+    let expansion = expand_templates(path).expect("Failed to expand templates");
+
+    // Conditions are runtime expressions, so their variables are references too
+    let names: Vec<(&str, &VariableSyntax)> = expansion
+        .variable_references
+        .iter()
+        .map(|r| (r.name.as_str(), &r.syntax))
+        .collect();
+    assert_eq!(
+        names,
+        vec![
+            ("isMain", &VariableSyntax::RuntimeExpression),
+            ("DeployToken", &VariableSyntax::RuntimeExpression),
+            ("DeployToken", &VariableSyntax::Macro),
+        ]
+    );
+
+    let group_variables = vec![GroupVariable {
+        name: "DeployToken".to_string(),
+        group: "app-secrets".to_string(),
+        value: None,
+        is_secret: true,
+    }];
+    let findings = find_secret_misuse(
+        &expansion.variable_references,
+        &expansion.environment_references,
+        &expansion.scopes,
+        &group_variables,
+        &[],
+    );
+
+    assert_eq!(findings.len(), 2);
+    assert_eq!(findings[0].kind, SecretMisuseKind::Condition);
+    assert_eq!(findings[0].span.line, 11);
+    assert_eq!(
+        findings[1].kind,
+        SecretMisuseKind::UnmappedEnvironment {
+            environment_name: "DEPLOYTOKEN".to_string()
+        }
+    );
+    assert_eq!(findings[1].span.yaml_path.to_string(), "stages[0].jobs[0].steps[0].bash");
+}
+
+/// Test queue-time values against the pipeline settings, YAML definitions and runtime parameters
+#[test]
+fn test_queue_time_overrides() {
+    let expansion = expand_templates("tests/fixtures/pipeline_with_queue_time_variables.yml")
+        .expect("Failed to expand templates");
+    let overrides = parse_overrides(
+        &["imageTag=1.2", "buildConfiguration=Debug", "Region=northeurope", "Undeclared=x", "environment=prod"]
+            .map(|s| s.to_string()),
+    )
+    .unwrap();
+
+    let pipeline_variable = |name: &str, allow_override: bool| PipelineVariable {
+        name: name.to_string(),
+        value: Some(String::new()),
+        is_secret: false,
+        allow_override,
+    };
+    let pipeline_variables = vec![
+        pipeline_variable("ImageTag", true),
+        pipeline_variable("buildConfiguration", true),
+        pipeline_variable("Region", false),
+    ];
+    let group_variables = vec![GroupVariable {
+        name: "buildConfiguration".to_string(),
+        group: "build-settings".to_string(),
+        value: Some("Release".to_string()),
+        is_secret: false,
+    }];
+
+    let results = check_overrides(&overrides, &expansion, &group_variables, &pipeline_variables);
+    let statuses: Vec<&OverrideStatus> = results.iter().map(|r| &r.status).collect();
+    assert_eq!(
+        statuses,
+        vec![
+            &OverrideStatus::Accepted,
+            &OverrideStatus::Shadowed {
+                definitions: vec![
+                    "group 'build-settings' in pipeline root".to_string(),
+                    "inline variable in stage 'Build'".to_string(),
+                ]
+            },
+            &OverrideStatus::NotSettable,
+            &OverrideStatus::NotDefined,
+            &OverrideStatus::RuntimeParameter,
+        ]
+    );
+}
+
+/// Test validating a pipeline against a snapshot instead of Azure DevOps
+#[test]
+fn test_validate_against_snapshot() {
+    let snapshot = Snapshot::load("tests/fixtures/snapshot.json").expect("Failed to load snapshot");
+    assert_eq!(snapshot.project, "myproject");
+    let backend = snapshot.into_backend();
+
+    let groups = vec!["ProductionSecrets".to_string(), "DatabaseConfig".to_string()];
+    let group_results = validate_variable_groups(groups, &backend).unwrap();
+    assert!(group_results.iter().all(|r| r.exists));
+
+    let group_variables = fetch_group_variable_details(&group_results, &backend);
+    let available: Vec<(String, String)> =
+        group_variables.iter().map(|v| (v.name.clone(), v.group.clone())).collect();
+    let references = extract_variable_references("tests/fixtures/pipeline_with_groups.yml").unwrap();
+    let results = validate_variables_against_available(references, &available);
+
+    let missing: Vec<&str> = results
+        .iter()
+        .filter(|r| !r.exists)
+        .map(|r| r.variable_name.as_str())
+        .collect();
+    assert_eq!(missing, vec!["ApiKey"]);
+    assert!(backend.get_pipeline_variables("deploy").unwrap()["Region"].allow_override);
+}